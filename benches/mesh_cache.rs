@@ -0,0 +1,69 @@
+//! Size and decode throughput of the meshlet cache per compression mode. Uses the example models,
+//! which have to be placed next to `Cargo.toml`:
+//! cargo bench --no-default-features --bench mesh_cache
+
+use std::{io::Cursor, path::Path};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use metal_3_example::{
+    mesh::{viewer_build_config, viewer_import_options, Mesh},
+    mesh_cache::{CacheCompression, CacheKey},
+};
+
+const MODELS: [&str; 2] = ["shepherd.obj", "angel.obj"];
+
+fn cache_compression(c: &mut Criterion) {
+    let options = viewer_import_options();
+    let config = viewer_build_config();
+
+    for model in MODELS {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(model);
+        if !path.exists() {
+            eprintln!("Skipping {model}, {path:?} does not exist");
+            continue;
+        }
+
+        let key = CacheKey::from_file(&path, &options, &config).unwrap();
+        let (mesh, _) = Mesh::import(&path, &options, &config).unwrap();
+
+        let caches: Vec<(CacheCompression, Vec<u8>)> = CacheCompression::ALL
+            .into_iter()
+            .map(|compression| {
+                let mut bytes = Vec::new();
+                mesh.write_cache(&mut bytes, &key, compression).unwrap();
+                (compression, bytes)
+            })
+            .collect();
+
+        let raw_size = caches[0].1.len();
+        for (compression, bytes) in &caches {
+            println!(
+                "{model} {}: {} bytes, {:.1}% of uncompressed",
+                compression.name(),
+                bytes.len(),
+                bytes.len() as f64 / raw_size as f64 * 100.0
+            );
+        }
+
+        // Throughput is measured in uncompressed bytes so the modes are comparable.
+        let mut group = c.benchmark_group(format!("decode {model}"));
+        group.throughput(Throughput::Bytes(raw_size as u64));
+        for (compression, bytes) in &caches {
+            group.bench_with_input(
+                BenchmarkId::from_parameter(compression.name()),
+                bytes,
+                |b, bytes| {
+                    b.iter(|| {
+                        Mesh::read_cache(&mut Cursor::new(bytes), &key)
+                            .unwrap()
+                            .unwrap()
+                    })
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, cache_compression);
+criterion_main!(benches);
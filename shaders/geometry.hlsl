@@ -1,3 +1,22 @@
+#ifndef MAX_VERTICES
+#define MAX_VERTICES 64
+#endif
+
+#ifndef MAX_TRIANGLES
+#define MAX_TRIANGLES 124
+#endif
+
+// 0: bytes, 1: one triangle per uint with 10 bit indices, 2: strips, see IndexFormat
+#ifndef INDEX_FORMAT
+#define INDEX_FORMAT 0
+#endif
+
+// Threads per mesh thread group and step of the vertex and triangle loops, see THREAD_GROUP_SIZE
+// in shader_emulation.rs
+#ifndef THREAD_GROUP_SIZE
+#define THREAD_GROUP_SIZE 32
+#endif
+
 uint murmur_hash_11(uint src) {
     const uint M = 0x5bd1e995;
     uint h = 1190494759;
@@ -22,12 +41,27 @@ struct Vertex {
     float posX, posY, posZ;
     float texX, texY;
     float nx, ny, nz;
+    float tx, ty, tz, tw;
+    uint joints_xy, joints_zw;     // u16 joint indices
+    uint weights_xy, weights_zw;   // unorm16 joint weights, all zero if not skinned
+};
+
+struct QuantizedVertex {
+    uint position_xy;              // unorm16 x, y
+    uint position_z_tangent_sign;  // unorm16 z, sint16 bitangent sign
+    uint tex_coord;                // half x, y
+    uint normal;                   // snorm16 octahedral
+    uint tangent;                  // snorm16 octahedral
 };
 
 struct Meshlet {
     uint data_offset;
     uint vertex_count;
     uint triangle_count;
+    float centerX, centerY, centerZ;
+    float radius;
+    float coneApexX, coneApexY, coneApexZ;
+    uint cone_axis_cutoff; // snorm8 x, y, z axis and cutoff packed from low to high byte
 };
 
 struct MeshOutput {
@@ -43,9 +77,17 @@ struct PixelInput {
     float3 meshlet_color : MESHLET_COLOR;
 };
 
+#ifdef QUANTIZED_VERTICES
+StructuredBuffer<QuantizedVertex> vertices : register(t0, space0);
+#else
 StructuredBuffer<Vertex> vertices : register(t0, space0);
+#endif
 StructuredBuffer<Meshlet> meshlets : register(t1, space0);
 StructuredBuffer<uint> meshlet_data : register(t2, space0);
+#ifdef SKINNING
+// Joint palettes of all skinned meshes, see Skeleton::joint_palette
+StructuredBuffer<float4x4> joint_palette : register(t5, space0);
+#endif
 
 Texture2D color_texture : register(t3, space0);
 SamplerState color_sampler : register(s4, space0);
@@ -53,20 +95,116 @@ SamplerState color_sampler : register(s4, space0);
 cbuffer MeshUniforms : register(b0, space0) {
     float4x4 mvp_matrix;
     uint32_t render_type;
+    uint32_t meshlet_offset;
+    uint32_t joint_offset;
+    float4 position_min;
+    float4 position_extent;
 };
 
+float2 unpack_snorm16x2(uint packed) {
+    const int2 value = int2(int(packed << 16) >> 16, int(packed) >> 16);
+    return max(float2(value) / 32767.0, -1.0);
+}
+
+float3 decode_octahedral(uint packed) {
+    const float2 folded = unpack_snorm16x2(packed);
+    float3 direction = float3(folded, 1.0 - abs(folded.x) - abs(folded.y));
+
+    const float t = max(-direction.z, 0.0);
+    direction.x += direction.x >= 0.0 ? -t : t;
+    direction.y += direction.y >= 0.0 ? -t : t;
+
+    return normalize(direction);
+}
+
+Vertex decode_vertex(QuantizedVertex quantized) {
+    const float3 position = float3(quantized.position_xy & 0xFFFF, quantized.position_xy >> 16, quantized.position_z_tangent_sign & 0xFFFF) / 65535.0
+        * position_extent.xyz + position_min.xyz;
+    const float tangent_sign = float(int(quantized.position_z_tangent_sign) >> 16);
+    const float3 normal = decode_octahedral(quantized.normal);
+    const float3 tangent = tangent_sign != 0.0 ? decode_octahedral(quantized.tangent) : float3(0.0, 0.0, 0.0);
+
+    Vertex vertex;
+    vertex.posX = position.x;
+    vertex.posY = position.y;
+    vertex.posZ = position.z;
+    vertex.texX = f16tof32(quantized.tex_coord);
+    vertex.texY = f16tof32(quantized.tex_coord >> 16);
+    vertex.nx = normal.x;
+    vertex.ny = normal.y;
+    vertex.nz = normal.z;
+    vertex.tx = tangent.x;
+    vertex.ty = tangent.y;
+    vertex.tz = tangent.z;
+    vertex.tw = tangent_sign;
+    vertex.joints_xy = 0;
+    vertex.joints_zw = 0;
+    vertex.weights_xy = 0;
+    vertex.weights_zw = 0;
+    return vertex;
+}
+
+Vertex load_vertex(uint vertex_index) {
+#ifdef QUANTIZED_VERTICES
+    return decode_vertex(vertices[vertex_index]);
+#else
+    return vertices[vertex_index];
+#endif
+}
+
+#ifdef SKINNING
+// Same as skin_matrix in skeleton.rs
+Vertex skin_vertex(Vertex vertex) {
+    const float4 weights = float4(vertex.weights_xy & 0xFFFF, vertex.weights_xy >> 16, vertex.weights_zw & 0xFFFF, vertex.weights_zw >> 16);
+    const float weight_sum = weights.x + weights.y + weights.z + weights.w;
+    if (weight_sum == 0.0) {
+        return vertex;
+    }
+
+    const uint4 joints = uint4(vertex.joints_xy & 0xFFFF, vertex.joints_xy >> 16, vertex.joints_zw & 0xFFFF, vertex.joints_zw >> 16) + joint_offset;
+    const float4 normalized_weights = weights / weight_sum;
+    const float4x4 skin_matrix = joint_palette[joints.x] * normalized_weights.x
+        + joint_palette[joints.y] * normalized_weights.y
+        + joint_palette[joints.z] * normalized_weights.z
+        + joint_palette[joints.w] * normalized_weights.w;
+
+    const float3 position = mul(skin_matrix, float4(vertex.posX, vertex.posY, vertex.posZ, 1.0)).xyz;
+    const float3 normal = normalize(mul((float3x3)skin_matrix, float3(vertex.nx, vertex.ny, vertex.nz)));
+    const float3 tangent = mul((float3x3)skin_matrix, float3(vertex.tx, vertex.ty, vertex.tz));
+
+    vertex.posX = position.x;
+    vertex.posY = position.y;
+    vertex.posZ = position.z;
+    vertex.nx = normal.x;
+    vertex.ny = normal.y;
+    vertex.nz = normal.z;
+    vertex.tx = tangent.x;
+    vertex.ty = tangent.y;
+    vertex.tz = tangent.z;
+    return vertex;
+}
+#endif
+
 uint get_index(uint index_offset, uint index) {
     const uint byte_offset = ((index & 3)) << 3;
     return (meshlet_data[index_offset + (index >> 2u)] & (0xFFu << byte_offset)) >> byte_offset;
 }
 
+uint3 unpack_triangle_10(uint packed) {
+    return uint3(packed & 0x3FF, (packed >> 10) & 0x3FF, (packed >> 20) & 0x3FF);
+}
+
+uint get_strip_code(uint code_offset, uint triangle) {
+    return (meshlet_data[code_offset + (triangle >> 4)] >> ((triangle & 15) << 1)) & 3;
+}
+
 [outputtopology("triangle")]
-[numthreads(32, 1, 1)]
-void geometry_mesh(out vertices MeshOutput output_vertices[64],
-                   out indices uint3 output_triangles[124],
+[numthreads(THREAD_GROUP_SIZE, 1, 1)]
+void geometry_mesh(out vertices MeshOutput output_vertices[MAX_VERTICES],
+                   out indices uint3 output_triangles[MAX_TRIANGLES],
                    uint3 gtid : SV_GroupThreadID,
                    uint3 gid : SV_GroupID) {
-    const uint meshlet_index = gid.x;
+    const uint meshlet_index = meshlet_offset + gid.x;
 
     const Meshlet meshlet = meshlets[meshlet_index];
 
@@ -74,9 +212,13 @@ void geometry_mesh(out vertices MeshOutput output_vertices[64],
 
     const float3 meshlet_color = murmur_hash_11_color(meshlet_index);
 
-    for(uint i = gtid.x; i < meshlet.vertex_count; i += 32) {
+    for(uint i = gtid.x; i < meshlet.vertex_count; i += THREAD_GROUP_SIZE) {
         const uint vertex_index = meshlet_data[meshlet.data_offset + i];
-        const Vertex current_vertex = vertices[vertex_index];
+#ifdef SKINNING
+        const Vertex current_vertex = skin_vertex(load_vertex(vertex_index));
+#else
+        const Vertex current_vertex = load_vertex(vertex_index);
+#endif
 
         MeshOutput output;
         output.position = mul(mvp_matrix, float4(current_vertex.posX, current_vertex.posY, current_vertex.posZ, 1.0));
@@ -87,19 +229,54 @@ void geometry_mesh(out vertices MeshOutput output_vertices[64],
         output_vertices[i] = output;
     }
 
-    const uint num_index_groups = (meshlet.triangle_count * 3 + 3) >> 2;
+    const uint data_offset = meshlet.data_offset + meshlet.vertex_count;
+
+#if INDEX_FORMAT == 2
+    // Every triangle depends on the previous one, so a single thread walks the strip.
+    if (gtid.x == 0) {
+        const uint byte_offset = data_offset + ((meshlet.triangle_count + 15) >> 4);
+        uint byte_index = 0;
+        uint3 previous = uint3(0, 0, 0);
 
-    for (uint i = gtid.x; i < meshlet.triangle_count; i++) {
-        const uint data_offset = meshlet.data_offset + meshlet.vertex_count;
+        for (uint i = 0; i < meshlet.triangle_count; i++) {
+            const uint code = get_strip_code(data_offset, i);
+
+            uint3 triangle;
+            if (code == 0) {
+                triangle = uint3(get_index(byte_offset, byte_index), get_index(byte_offset, byte_index + 1), get_index(byte_offset, byte_index + 2));
+                byte_index += 3;
+            } else {
+                const uint new_index = get_index(byte_offset, byte_index);
+                byte_index += 1;
+
+                if (code == 1) {
+                    triangle = uint3(previous.y, previous.x, new_index);
+                } else if (code == 2) {
+                    triangle = uint3(previous.z, previous.y, new_index);
+                } else {
+                    triangle = uint3(previous.x, previous.z, new_index);
+                }
+            }
+
+            output_triangles[i] = triangle;
+            previous = triangle;
+        }
+    }
+#else
+    for (uint i = gtid.x; i < meshlet.triangle_count; i += THREAD_GROUP_SIZE) {
+#if INDEX_FORMAT == 1
+        output_triangles[i] = unpack_triangle_10(meshlet_data[data_offset + i]);
+#else
         const uint index_offset = i * 3;
 
         output_triangles[i] = uint3(get_index(data_offset, index_offset), get_index(data_offset, index_offset + 1), get_index(data_offset, index_offset + 2));
+#endif
     }
+#endif
 }
 
 float4 geometry_pixel(PixelInput input) : SV_Target0 {
-    const float4 color_sample = color_texture.Sample(color_sampler,
-        float2(input.tex_coord.x, 1.0 - input.tex_coord.y));
+    const float4 color_sample = color_texture.Sample(color_sampler, input.tex_coord);
 
     return render_type == 0 ? color_sample : float4(float3(input.meshlet_color), 1.0);
 }
\ No newline at end of file
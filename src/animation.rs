@@ -0,0 +1,410 @@
+use std::ops::{Add, Mul};
+
+use anyhow::{ensure, Result};
+use glam::{Quat, Vec3};
+
+use crate::skeleton::{Skeleton, Transform};
+
+/// How values between two keyframes are computed, matching glTF's interpolation modes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    #[default]
+    Linear,
+    /// Hermite spline. Every keyframe stores an in tangent, the value and an out tangent, in that
+    /// order.
+    CubicSpline,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+impl Keyframes {
+    fn len(&self) -> usize {
+        match self {
+            Self::Translation(values) | Self::Scale(values) => values.len(),
+            Self::Rotation(values) => values.len(),
+        }
+    }
+}
+
+/// Animates one property of one joint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    joint: usize,
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    keyframes: Keyframes,
+}
+
+impl Channel {
+    pub fn new(
+        joint: usize,
+        interpolation: Interpolation,
+        times: Vec<f32>,
+        keyframes: Keyframes,
+    ) -> Result<Self> {
+        validate_keyframes(interpolation, &times, keyframes.len())?;
+
+        Ok(Self {
+            joint,
+            interpolation,
+            times,
+            keyframes,
+        })
+    }
+
+    #[inline]
+    pub fn joint(&self) -> usize {
+        self.joint
+    }
+
+    #[inline]
+    pub fn duration(&self) -> f32 {
+        self.times[self.times.len() - 1]
+    }
+
+    /// Writes the value at `time` into `transform`, clamping to the first and last keyframe.
+    pub fn sample(&self, time: f32, transform: &mut Transform) {
+        let (interpolation, times) = (self.interpolation, &self.times);
+
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation =
+                    sample_keyframes(interpolation, times, time, |i| values[i], Vec3::lerp, |v| v);
+            }
+            Keyframes::Rotation(values) => {
+                transform.rotation = sample_keyframes(
+                    interpolation,
+                    times,
+                    time,
+                    |i| values[i],
+                    Quat::slerp,
+                    Quat::normalize,
+                );
+            }
+            Keyframes::Scale(values) => {
+                transform.scale =
+                    sample_keyframes(interpolation, times, time, |i| values[i], Vec3::lerp, |v| v);
+            }
+        }
+    }
+}
+
+/// Animates the weights of all morph targets of a mesh at once, see
+/// [`Mesh::morphed_vertices`](crate::mesh::Mesh::morphed_vertices).
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeightsChannel {
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    target_count: usize,
+    /// `target_count` weights per keyframe value, in the same order as the values of a
+    /// [`Channel`].
+    weights: Vec<f32>,
+}
+
+impl WeightsChannel {
+    pub fn new(
+        interpolation: Interpolation,
+        times: Vec<f32>,
+        target_count: usize,
+        weights: Vec<f32>,
+    ) -> Result<Self> {
+        ensure!(target_count > 0, "Weights channel has no morph targets");
+        ensure!(
+            weights.len() % target_count == 0,
+            "{} weights are not a multiple of {target_count} morph targets",
+            weights.len()
+        );
+        validate_keyframes(interpolation, &times, weights.len() / target_count)?;
+
+        Ok(Self {
+            interpolation,
+            times,
+            target_count,
+            weights,
+        })
+    }
+
+    #[inline]
+    pub fn target_count(&self) -> usize {
+        self.target_count
+    }
+
+    #[inline]
+    pub fn duration(&self) -> f32 {
+        self.times[self.times.len() - 1]
+    }
+
+    /// Weight of every morph target at `time`, clamping to the first and last keyframe.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        (0..self.target_count)
+            .map(|target| {
+                sample_keyframes(
+                    self.interpolation,
+                    &self.times,
+                    time,
+                    |index| self.weights[index * self.target_count + target],
+                    |a, b, t| a + (b - a) * t,
+                    |weight| weight,
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MorphAnimation {
+    pub name: Option<String>,
+    pub weights: WeightsChannel,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .map(Channel::duration)
+            .fold(0.0, f32::max)
+    }
+
+    /// Local joint transforms at `time`. Joints without channels keep their rest transform.
+    pub fn sample(&self, skeleton: &Skeleton, time: f32) -> Vec<Transform> {
+        let mut pose = skeleton.rest_pose();
+        for channel in &self.channels {
+            channel.sample(time, &mut pose[channel.joint]);
+        }
+
+        pose
+    }
+}
+
+fn validate_keyframes(
+    interpolation: Interpolation,
+    times: &[f32],
+    value_count: usize,
+) -> Result<()> {
+    ensure!(!times.is_empty(), "Channel has no keyframes");
+    ensure!(
+        times.windows(2).all(|pair| pair[0] < pair[1]),
+        "Keyframe times must be increasing"
+    );
+
+    let values_per_keyframe = match interpolation {
+        Interpolation::CubicSpline => 3,
+        _ => 1,
+    };
+    ensure!(
+        value_count == times.len() * values_per_keyframe,
+        "Expected {} values for {} keyframes, got {value_count}",
+        times.len() * values_per_keyframe,
+        times.len()
+    );
+
+    Ok(())
+}
+
+/// Interpolates the keyframes at `time`. `value` returns the stored values by index, for cubic
+/// splines the in tangent, value and out tangent of each keyframe.
+fn sample_keyframes<T>(
+    interpolation: Interpolation,
+    times: &[f32],
+    time: f32,
+    value: impl Fn(usize) -> T,
+    lerp: impl Fn(T, T, f32) -> T,
+    normalize: impl Fn(T) -> T,
+) -> T
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+    let keyframe_value = |keyframe: usize| {
+        match interpolation {
+            Interpolation::CubicSpline => value(keyframe * 3 + 1),
+            _ => value(keyframe),
+        }
+    };
+
+    // Index of the first keyframe after `time`.
+    let next = times.partition_point(|&keyframe_time| keyframe_time <= time);
+    if next == 0 {
+        return keyframe_value(0);
+    }
+    if next == times.len() {
+        return keyframe_value(next - 1);
+    }
+
+    let previous = next - 1;
+    let dt = times[next] - times[previous];
+    let t = (time - times[previous]) / dt;
+
+    match interpolation {
+        Interpolation::Step => keyframe_value(previous),
+        Interpolation::Linear => lerp(keyframe_value(previous), keyframe_value(next), t),
+        Interpolation::CubicSpline => {
+            let out_tangent = value(previous * 3 + 2);
+            let in_tangent = value(next * 3);
+            let t2 = t * t;
+            let t3 = t2 * t;
+
+            normalize(
+                keyframe_value(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * ((t3 - 2.0 * t2 + t) * dt)
+                    + keyframe_value(next) * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * ((t3 - t2) * dt),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use glam::{Mat4, Quat, Vec3};
+
+    use crate::{
+        animation::{AnimationClip, Channel, Interpolation, Keyframes, WeightsChannel},
+        skeleton::{Joint, Skeleton, Transform},
+    };
+
+    fn skeleton() -> Skeleton {
+        Skeleton::new(
+            vec![Joint {
+                name: None,
+                parent: None,
+                rest: Transform::IDENTITY,
+                inverse_bind_matrix: Mat4::IDENTITY,
+            }],
+            Mat4::IDENTITY,
+        )
+        .unwrap()
+    }
+
+    fn clip(channel: Channel) -> AnimationClip {
+        AnimationClip {
+            name: None,
+            channels: vec![channel],
+        }
+    }
+
+    #[test]
+    fn linear_and_step() {
+        let keyframes = Keyframes::Translation(vec![Vec3::ZERO, Vec3::X * 2.0]);
+        let linear = clip(
+            Channel::new(0, Interpolation::Linear, vec![1.0, 3.0], keyframes.clone()).unwrap(),
+        );
+        let step = clip(Channel::new(0, Interpolation::Step, vec![1.0, 3.0], keyframes).unwrap());
+
+        assert_eq!(linear.duration(), 3.0);
+        assert!(linear.sample(&skeleton(), 2.0)[0]
+            .translation
+            .abs_diff_eq(Vec3::X, 1e-6));
+        assert_eq!(step.sample(&skeleton(), 2.9)[0].translation, Vec3::ZERO);
+        assert_eq!(step.sample(&skeleton(), 3.0)[0].translation, Vec3::X * 2.0);
+
+        // Clamped outside of the keyframes.
+        assert_eq!(linear.sample(&skeleton(), 0.0)[0].translation, Vec3::ZERO);
+        assert_eq!(
+            linear.sample(&skeleton(), 10.0)[0].translation,
+            Vec3::X * 2.0
+        );
+    }
+
+    #[test]
+    fn rotations_are_slerped() {
+        let clip = clip(
+            Channel::new(
+                0,
+                Interpolation::Linear,
+                vec![0.0, 1.0],
+                Keyframes::Rotation(vec![Quat::IDENTITY, Quat::from_rotation_y(FRAC_PI_2)]),
+            )
+            .unwrap(),
+        );
+
+        let rotation = clip.sample(&skeleton(), 0.5)[0].rotation;
+        assert!(rotation.abs_diff_eq(Quat::from_rotation_y(FRAC_PI_2 / 2.0), 1e-6));
+        assert!(rotation.is_normalized());
+    }
+
+    #[test]
+    fn cubic_spline() {
+        // With zero tangents the spline eases in and out like smoothstep.
+        let clip = clip(
+            Channel::new(
+                0,
+                Interpolation::CubicSpline,
+                vec![0.0, 2.0],
+                Keyframes::Scale(vec![
+                    Vec3::ZERO,
+                    Vec3::ONE,
+                    Vec3::ZERO,
+                    Vec3::ZERO,
+                    Vec3::splat(3.0),
+                    Vec3::ZERO,
+                ]),
+            )
+            .unwrap(),
+        );
+
+        for time in [0.0, 0.5, 1.0, 1.5, 2.0] {
+            let t = time / 2.0;
+            let smoothstep = t * t * (3.0 - 2.0 * t);
+            let expected = Vec3::ONE.lerp(Vec3::splat(3.0), smoothstep);
+            assert!(clip.sample(&skeleton(), time)[0]
+                .scale
+                .abs_diff_eq(expected, 1e-5));
+        }
+    }
+
+    #[test]
+    fn morph_weights() {
+        // Two targets, the first fades in while the second fades out.
+        let channel = WeightsChannel::new(
+            Interpolation::Linear,
+            vec![0.0, 1.0, 2.0],
+            2,
+            vec![0.0, 1.0, 0.5, 0.5, 1.0, 0.0],
+        )
+        .unwrap();
+
+        assert_eq!(channel.duration(), 2.0);
+        assert_eq!(channel.sample(0.5), [0.25, 0.75]);
+        assert_eq!(channel.sample(1.5), [0.75, 0.25]);
+        assert_eq!(channel.sample(5.0), [1.0, 0.0]);
+
+        assert!(WeightsChannel::new(Interpolation::Linear, vec![0.0], 2, vec![0.0]).is_err());
+        assert!(WeightsChannel::new(Interpolation::Step, vec![0.0, 1.0], 2, vec![0.0; 2]).is_err());
+    }
+
+    #[test]
+    fn invalid_channels() {
+        let translations = Keyframes::Translation(vec![Vec3::ZERO, Vec3::X]);
+
+        assert!(Channel::new(
+            0,
+            Interpolation::Linear,
+            vec![1.0, 0.0],
+            translations.clone()
+        )
+        .is_err());
+        assert!(Channel::new(0, Interpolation::Linear, vec![0.0], translations.clone()).is_err());
+        assert!(Channel::new(0, Interpolation::CubicSpline, vec![0.0, 1.0], translations).is_err());
+        assert!(Channel::new(
+            0,
+            Interpolation::Step,
+            Vec::new(),
+            Keyframes::Scale(Vec::new())
+        )
+        .is_err());
+    }
+}
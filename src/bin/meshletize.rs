@@ -0,0 +1,315 @@
+//! Offline preprocessing for the content pipeline. Runs the full [`Mesh`] pipeline on OBJ and glTF
+//! files, writes the meshlet caches the viewer loads and prints statistics. Needs no GPU.
+
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use metal_3_example::{
+    gltf_import,
+    index_packing::IndexFormat,
+    mesh::{
+        viewer_build_config, viewer_import_options, ImportOptions, Mesh, MeshletBuildConfig, UpAxis,
+    },
+    mesh_cache::{cache_path, CacheCompression, CacheKey, EXTENSION},
+    mesh_repair::ImportLog,
+    mesh_stats::MeshStats,
+    meshlet_order::MeshletOrder,
+};
+use serde::Serialize;
+
+const USAGE: &str = "\
+Usage: meshletize [options] <input>...
+
+Builds meshlet caches for OBJ, glTF and GLB files. By default every cache is written next to its
+input, which is where the viewer looks for it. glTF primitives each get their own cache.
+
+Options:
+  -o, --output-dir <dir>     Write the caches to <dir> instead
+      --max-vertices <n>     Meshlet vertex limit
+      --max-triangles <n>    Meshlet triangle limit
+      --cone-weight <w>      Weight of the normal cone when building meshlets
+      --index-format <name>  bytes, triangle10 or strip
+      --meshlet-order <name> build, morton or hilbert
+      --no-lods              Only build the full resolution LOD
+      --tangents             Generate tangents
+      --weld-tolerance <d>   Distance below which OBJ positions are welded
+      --no-repair            Import OBJs without welding and removing broken triangles
+      --up-axis <axis>       Up axis of OBJs, y or z
+      --flip-handedness      Convert OBJs between right and left handed coordinates
+      --unit-scale <s>       Scale of OBJ positions, e.g. 0.01 for centimeters
+      --no-flip-v            Keep the texture origin of OBJs at the bottom left
+      --compression <name>   Cache compression, none, meshopt or meshopt-deflate
+      --json                 Print the statistics as JSON
+  -h, --help                 Print this help";
+
+#[derive(Debug)]
+struct Args {
+    inputs: Vec<PathBuf>,
+    output_dir: Option<PathBuf>,
+    import_options: ImportOptions,
+    config: MeshletBuildConfig,
+    compression: CacheCompression,
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct Report {
+    input: PathBuf,
+    output: PathBuf,
+    /// Only OBJs are repaired on import.
+    import_log: Option<ImportLog>,
+    stats: MeshStats,
+}
+
+/// Returns `None` if only the usage was requested.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Args>> {
+    let mut args = args.into_iter();
+    let mut parsed = Args {
+        inputs: Vec::new(),
+        output_dir: None,
+        import_options: viewer_import_options(),
+        config: viewer_build_config(),
+        compression: CacheCompression::default(),
+        json: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .with_context(|| format!("Missing value for {arg}"))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output-dir" => parsed.output_dir = Some(value()?.into()),
+            "--max-vertices" => parsed.config.max_vertices = value()?.parse()?,
+            "--max-triangles" => parsed.config.max_triangles = value()?.parse()?,
+            "--cone-weight" => parsed.config.cone_weight = value()?.parse()?,
+            "--index-format" => {
+                let name = value()?;
+                parsed.config.index_format = IndexFormat::ALL
+                    .into_iter()
+                    .find(|format| format.name() == name)
+                    .with_context(|| format!("Unknown index format {name}"))?;
+            }
+            "--meshlet-order" => {
+                let name = value()?;
+                parsed.config.meshlet_order = MeshletOrder::ALL
+                    .into_iter()
+                    .find(|order| order.name() == name)
+                    .with_context(|| format!("Unknown meshlet order {name}"))?;
+            }
+            "--no-lods" => parsed.config.lods.clear(),
+            "--tangents" => parsed.import_options.generate_tangents = true,
+            "--weld-tolerance" => parsed.import_options.weld_tolerance = value()?.parse()?,
+            "--no-repair" => parsed.import_options.repair = false,
+            "--up-axis" => {
+                let name = value()?;
+                parsed.import_options.up_axis = UpAxis::ALL
+                    .into_iter()
+                    .find(|axis| axis.name() == name)
+                    .with_context(|| format!("Unknown up axis {name}"))?;
+            }
+            "--flip-handedness" => parsed.import_options.flip_handedness = true,
+            "--unit-scale" => parsed.import_options.unit_scale = value()?.parse()?,
+            "--no-flip-v" => parsed.import_options.flip_tex_coord_v = false,
+            "--compression" => {
+                let name = value()?;
+                parsed.compression = CacheCompression::ALL
+                    .into_iter()
+                    .find(|compression| compression.name() == name)
+                    .with_context(|| format!("Unknown cache compression {name}"))?;
+            }
+            "--json" => parsed.json = true,
+            _ if arg.starts_with('-') => bail!("Unknown option {arg}\n\n{USAGE}"),
+            _ => parsed.inputs.push(arg.into()),
+        }
+    }
+
+    if parsed.inputs.is_empty() {
+        bail!("No inputs\n\n{USAGE}");
+    }
+    parsed.import_options.validate()?;
+    parsed.config.validate()?;
+
+    Ok(Some(parsed))
+}
+
+fn output_path(input: &Path, output_dir: Option<&Path>, primitive: Option<usize>) -> PathBuf {
+    let path = match primitive {
+        Some(primitive) => input.with_extension(format!("{primitive}.{EXTENSION}")),
+        None => cache_path(input),
+    };
+
+    match output_dir {
+        Some(output_dir) => output_dir.join(path.file_name().unwrap()),
+        None => path,
+    }
+}
+
+fn meshletize(input: &Path, args: &Args) -> Result<Vec<Report>> {
+    let key = CacheKey::from_file(input, &args.import_options, &args.config)?;
+
+    let meshes = match input.extension().and_then(OsStr::to_str) {
+        Some("gltf" | "glb") => {
+            gltf_import::import(input, &args.import_options, &args.config)?
+                .into_iter()
+                .enumerate()
+                .map(|(index, primitive)| (Some(index), primitive.mesh, None))
+                .collect()
+        }
+        _ => {
+            let (mesh, log) = Mesh::import(input, &args.import_options, &args.config)?;
+            vec![(None, mesh, Some(log))]
+        }
+    };
+
+    meshes
+        .into_iter()
+        .map(|(primitive, mesh, import_log)| {
+            mesh.validate()?;
+
+            let output = output_path(input, args.output_dir.as_deref(), primitive);
+            let mut writer = BufWriter::new(File::create(&output)?);
+            mesh.write_cache(&mut writer, &key, args.compression)?;
+            writer.flush()?;
+
+            Ok(Report {
+                input: input.to_path_buf(),
+                output,
+                import_log,
+                stats: MeshStats::new(&mesh, &args.config)?,
+            })
+        })
+        .collect()
+}
+
+fn main() -> Result<()> {
+    let Some(args) = parse_args(env::args().skip(1))? else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+
+    if let Some(output_dir) = &args.output_dir {
+        fs::create_dir_all(output_dir)?;
+    }
+
+    let mut reports = Vec::new();
+    for input in &args.inputs {
+        let input_reports =
+            meshletize(input, &args).with_context(|| format!("Failed to meshletize {input:?}"))?;
+
+        if !args.json {
+            for report in &input_reports {
+                println!("{:?} -> {:?}", report.input, report.output);
+                if let Some(log) = report.import_log.filter(|log| !log.is_clean()) {
+                    println!("{log}");
+                }
+                println!("{}\n", report.stats);
+            }
+        }
+        reports.extend(input_reports);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use metal_3_example::{
+        index_packing::IndexFormat,
+        mesh::{viewer_build_config, viewer_import_options, UpAxis},
+        mesh_cache::{CacheCompression, CacheKey},
+        meshlet_order::MeshletOrder,
+    };
+
+    use crate::{output_path, parse_args};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse() {
+        let parsed = parse_args(args(&[
+            "a.obj",
+            "--index-format",
+            "strip",
+            "--meshlet-order",
+            "hilbert",
+            "--no-lods",
+            "--weld-tolerance",
+            "0.001",
+            "--no-repair",
+            "--up-axis",
+            "z",
+            "--compression",
+            "meshopt-deflate",
+            "-o",
+            "out",
+            "b.glb",
+        ]))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            parsed.inputs,
+            [PathBuf::from("a.obj"), PathBuf::from("b.glb")]
+        );
+        assert_eq!(parsed.output_dir, Some(PathBuf::from("out")));
+        assert_eq!(parsed.config.index_format, IndexFormat::Strip);
+        assert_eq!(parsed.config.meshlet_order, MeshletOrder::Hilbert);
+        assert!(parsed.config.lods.is_empty());
+        assert_eq!(parsed.import_options.weld_tolerance, 0.001);
+        assert!(!parsed.import_options.repair);
+        assert_eq!(parsed.import_options.up_axis, UpAxis::Z);
+        assert_eq!(parsed.compression, CacheCompression::MeshoptDeflate);
+
+        assert!(parse_args(args(&["--help"])).unwrap().is_none());
+        assert!(parse_args(args(&[])).is_err());
+        assert!(parse_args(args(&["a.obj", "--max-vertices"])).is_err());
+        assert!(parse_args(args(&["a.obj", "--max-vertices", "1000"])).is_err());
+        assert!(parse_args(args(&["a.obj", "--index-format", "u16"])).is_err());
+        assert!(parse_args(args(&["a.obj", "--meshlet-order", "random"])).is_err());
+        assert!(parse_args(args(&["a.obj", "--up-axis", "x"])).is_err());
+        assert!(parse_args(args(&["a.obj", "--compression", "zstd"])).is_err());
+        assert!(parse_args(args(&["a.obj", "--weld-tolerance", "-1"])).is_err());
+        assert!(parse_args(args(&["a.obj", "--unit-scale", "0"])).is_err());
+    }
+
+    #[test]
+    fn default_caches_match_viewer() {
+        let parsed = parse_args(args(&["angel.obj"])).unwrap().unwrap();
+
+        assert_eq!(
+            CacheKey::new(b"angel", &parsed.import_options, &parsed.config),
+            CacheKey::new(b"angel", &viewer_import_options(), &viewer_build_config())
+        );
+    }
+
+    #[test]
+    fn output_paths() {
+        let input = Path::new("models/angel.obj");
+
+        assert_eq!(
+            output_path(input, None, None),
+            Path::new("models/angel.meshlets")
+        );
+        assert_eq!(
+            output_path(input, Some(Path::new("out")), Some(2)),
+            Path::new("out/angel.2.meshlets")
+        );
+    }
+}
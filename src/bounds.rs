@@ -0,0 +1,208 @@
+use glam::{BVec3, Mat4, Vec3};
+
+use crate::mesh::Mesh;
+
+/// Axis aligned bounding box.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Box around all `points`, zero sized at the origin if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let (min, max) = points.into_iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), point| (min.min(point), max.max(point)),
+        );
+
+        if min.cmpgt(max).any() {
+            return Self::default();
+        }
+
+        Self { min, max }
+    }
+
+    #[inline]
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    #[inline]
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|corner| {
+            Vec3::select(
+                BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                self.max,
+                self.min,
+            )
+        })
+    }
+
+    /// Box around the transformed box, which is larger than the box of the transformed contents
+    /// if `transform` rotates.
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        Self::from_points(
+            self.corners()
+                .map(|corner| transform.transform_point3(corner)),
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Smallest sphere around both spheres.
+    pub fn union(&self, other: &Self) -> Self {
+        let distance = self.center.distance(other.center);
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+
+        let radius = (distance + self.radius + other.radius) * 0.5;
+        Self {
+            center: self.center
+                + (other.center - self.center) * ((radius - self.radius) / distance),
+            radius,
+        }
+    }
+
+    /// Sphere around the transformed sphere, scaled by the largest scale of `transform`.
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        let scale = [transform.x_axis, transform.y_axis, transform.z_axis]
+            .map(|axis| axis.truncate().length())
+            .into_iter()
+            .fold(0.0, f32::max);
+
+        Self {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Bounding volumes of a [`Mesh`] in mesh units.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    /// Centered on the box, which is not the smallest sphere but close for most meshes.
+    pub sphere: BoundingSphere,
+}
+
+impl Mesh {
+    /// Bounds of all vertices. Like the meshlet bounds they hold for any morph target weights
+    /// between 0 and 1.
+    pub fn bounds(&self) -> Bounds {
+        // Furthest each vertex can move in either direction per axis.
+        let reach = |vertex: usize| {
+            self.morph_targets
+                .iter()
+                .fold((Vec3::ZERO, Vec3::ZERO), |(min, max), target| {
+                    let delta = target.deltas[vertex].position;
+                    (min + delta.min(Vec3::ZERO), max + delta.max(Vec3::ZERO))
+                })
+        };
+
+        let aabb = Aabb::from_points(self.vertices.iter().enumerate().flat_map(|(i, vertex)| {
+            let (min, max) = reach(i);
+            [vertex.position + min, vertex.position + max]
+        }));
+        let center = aabb.center();
+        let radius = self
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(i, vertex)| {
+                let moved: f32 = self
+                    .morph_targets
+                    .iter()
+                    .map(|target| target.deltas[i].position.length())
+                    .sum();
+                vertex.position.distance(center) + moved
+            })
+            .fold(0.0, f32::max);
+
+        Bounds {
+            aabb,
+            sphere: BoundingSphere { center, radius },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec3};
+
+    use crate::{
+        bounds::{Aabb, BoundingSphere},
+        mesh::MeshletBuildConfig,
+        primitives::Primitive,
+    };
+
+    #[test]
+    fn cube_bounds() {
+        let mesh = Primitive::cube(2.0)
+            .to_mesh(&MeshletBuildConfig::default())
+            .unwrap();
+        let bounds = mesh.bounds();
+
+        assert_eq!(bounds.aabb.min, Vec3::splat(-1.0));
+        assert_eq!(bounds.aabb.max, Vec3::splat(1.0));
+        assert_eq!(bounds.sphere.center, Vec3::ZERO);
+        assert!((bounds.sphere.radius - 3.0f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn transforms_and_unions() {
+        let aabb = Aabb {
+            min: Vec3::ZERO,
+            max: Vec3::new(2.0, 1.0, 1.0),
+        };
+        let rotated = aabb.transform(&Mat4::from_quat(Quat::from_rotation_z(
+            std::f32::consts::FRAC_PI_2,
+        )));
+        assert!(rotated.min.abs_diff_eq(Vec3::new(-1.0, 0.0, 0.0), 1e-6));
+        assert!(rotated.max.abs_diff_eq(Vec3::new(0.0, 2.0, 1.0), 1e-6));
+        assert_eq!(Aabb::from_points([]), Aabb::default());
+
+        let a = BoundingSphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        };
+        let b = BoundingSphere {
+            center: Vec3::X * 4.0,
+            radius: 2.0,
+        };
+        let union = a.union(&b);
+        assert!(union.center.abs_diff_eq(Vec3::X * 2.5, 1e-6));
+        assert_eq!(union.radius, 3.5);
+        assert_eq!(a.union(&union), union);
+
+        let scaled = a.transform(&Mat4::from_scale_rotation_translation(
+            Vec3::new(1.0, 3.0, 2.0),
+            Quat::IDENTITY,
+            Vec3::Y,
+        ));
+        assert_eq!(scaled.center, Vec3::Y);
+        assert_eq!(scaled.radius, 3.0);
+    }
+}
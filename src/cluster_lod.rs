@@ -0,0 +1,587 @@
+use std::{collections::HashMap, mem};
+
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use meshopt::{SimplifyOptions, VertexDataAdapter};
+
+use crate::{
+    index_packing::IndexFormat,
+    lod::projected_error,
+    mesh::{append_meshlets, Mesh, Meshlet, MeshletBuildConfig, Vertex},
+    morph,
+};
+
+/// Number of neighbouring clusters that are merged and simplified together.
+pub const GROUP_SIZE: usize = 4;
+
+/// Groups whose triangle count shrinks by less than this are not simplified any further.
+const MIN_REDUCTION: f32 = 0.15;
+
+/// Bounding sphere and accumulated geometric error in mesh units of a simplification step.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct LodBounds {
+    pub center: Vec3,
+    pub radius: f32,
+    pub error: f32,
+}
+
+unsafe impl Zeroable for LodBounds {}
+unsafe impl Pod for LodBounds {}
+
+impl LodBounds {
+    /// Smallest axis aligned sphere enclosing all `bounds`, with the largest of their errors.
+    fn merge(bounds: &[LodBounds]) -> Self {
+        let (min, max) = bounds.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), bounds| {
+                (
+                    min.min(bounds.center - bounds.radius),
+                    max.max(bounds.center + bounds.radius),
+                )
+            },
+        );
+        let center = (min + max) * 0.5;
+
+        Self {
+            center,
+            radius: bounds
+                .iter()
+                .map(|bounds| center.distance(bounds.center) + bounds.radius)
+                .fold(0.0, f32::max),
+            error: bounds.iter().map(|bounds| bounds.error).fold(0.0, f32::max),
+        }
+    }
+
+    /// Error in pixels as seen from `camera_position`, measured at the point of the sphere closest
+    /// to the camera so that enclosing bounds never project to a smaller error.
+    pub fn projected_error(
+        &self,
+        camera_position: Vec3,
+        projection: &Mat4,
+        viewport_height: f32,
+    ) -> f32 {
+        let distance = self.center.distance(camera_position) - self.radius;
+        projected_error(self.error, projection, viewport_height, distance)
+    }
+}
+
+/// A node of the [`ClusterDag`], describing the meshlet with the same index.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct Cluster {
+    pub material: u32,
+    /// Number of simplification steps between this cluster and the full resolution mesh.
+    pub level: u32,
+    /// Bounds of the group this cluster was simplified from, zero error at level 0.
+    pub lod_bounds: LodBounds,
+    /// Bounds of the group this cluster is simplified into, infinite error at the roots.
+    pub parent_bounds: LodBounds,
+}
+
+unsafe impl Zeroable for Cluster {}
+unsafe impl Pod for Cluster {}
+
+/// Meshlets of every simplification level, connected by the groups they were simplified in.
+/// Group borders are locked during simplification, so any cut through the DAG where clusters
+/// switch level at group boundaries is free of cracks. Meshlets index the vertices of the mesh
+/// the DAG was built from.
+#[derive(Clone, Debug, Default)]
+pub struct ClusterDag {
+    pub meshlets: Vec<Meshlet>,
+    pub meshlet_data: Vec<u32>,
+    pub clusters: Vec<Cluster>,
+    pub index_format: IndexFormat,
+}
+
+impl ClusterDag {
+    /// Builds the DAG from the full resolution meshlets of `mesh`. Every submesh is simplified
+    /// on its own, so clusters never mix materials.
+    pub fn new(mesh: &Mesh, config: &MeshletBuildConfig) -> Result<Self> {
+        let vertex_data_adapter = VertexDataAdapter::new(
+            bytemuck::cast_slice(&mesh.vertices),
+            mem::size_of::<Vertex>(),
+            0,
+        )?;
+        let position_ids = position_ids(&mesh.vertices);
+
+        let mut dag = Self {
+            index_format: mesh.index_format,
+            ..Default::default()
+        };
+
+        for submesh in mesh.lod_submeshes(0) {
+            let offset = submesh.meshlet_offset as usize;
+            let mut pending = Vec::with_capacity(submesh.meshlet_count as usize);
+
+            for meshlet in &mesh.meshlets[offset..offset + submesh.meshlet_count as usize] {
+                let lod_bounds = LodBounds {
+                    center: meshlet.center,
+                    radius: meshlet.radius,
+                    error: 0.0,
+                };
+
+                pending.push(dag.clusters.len());
+                dag.push_cluster(
+                    meshlet,
+                    &mesh.meshlet_data,
+                    Cluster {
+                        material: submesh.material,
+                        level: 0,
+                        lod_bounds,
+                        parent_bounds: LodBounds {
+                            error: f32::INFINITY,
+                            ..lod_bounds
+                        },
+                    },
+                )?;
+            }
+
+            let mut level = 0;
+            while pending.len() > 1 {
+                level += 1;
+                pending = dag.simplify_level(
+                    &pending,
+                    &position_ids,
+                    &vertex_data_adapter,
+                    config,
+                    submesh.material,
+                    level,
+                )?;
+            }
+        }
+        morph::expand_meshlet_bounds(
+            &mut dag.meshlets,
+            &dag.meshlet_data,
+            &mesh.vertices,
+            &mesh.morph_targets,
+        );
+
+        Ok(dag)
+    }
+
+    fn push_cluster(
+        &mut self,
+        meshlet: &Meshlet,
+        meshlet_data: &[u32],
+        cluster: Cluster,
+    ) -> Result<()> {
+        let data_offset = self.meshlet_data.len();
+        let data_len = meshlet.data_len(self.index_format, meshlet_data)?;
+        self.meshlet_data
+            .extend_from_slice(&meshlet_data[meshlet.data_offset as usize..][..data_len]);
+
+        self.meshlets.push(Meshlet {
+            data_offset: data_offset as _,
+            ..*meshlet
+        });
+        self.clusters.push(cluster);
+
+        Ok(())
+    }
+
+    /// Groups the `pending` clusters, simplifies every group with locked borders and splits the
+    /// result into new clusters. Returns the clusters that can be simplified further.
+    fn simplify_level(
+        &mut self,
+        pending: &[usize],
+        position_ids: &[u32],
+        vertex_data_adapter: &VertexDataAdapter,
+        config: &MeshletBuildConfig,
+        material: u32,
+        level: u32,
+    ) -> Result<Vec<usize>> {
+        let scale = meshopt::simplify_scale(vertex_data_adapter);
+        let cluster_indices: Vec<Vec<u32>> = pending
+            .iter()
+            .map(|cluster| self.meshlets[*cluster].indices(self.index_format, &self.meshlet_data))
+            .collect::<Result<_>>()?;
+
+        let mut next = Vec::new();
+
+        for group in group_clusters(&cluster_indices, position_ids) {
+            // A cluster without neighbours would only be simplified against its own locked border.
+            if group.len() == 1 {
+                continue;
+            }
+
+            let indices: Vec<u32> = group
+                .iter()
+                .flat_map(|member| cluster_indices[*member].iter().copied())
+                .collect();
+
+            let mut result_error = 0.0;
+            let mut simplified = meshopt::simplify(
+                &indices,
+                vertex_data_adapter,
+                indices.len() / 6 * 3,
+                1.0,
+                SimplifyOptions::LockBorder,
+                Some(&mut result_error),
+            );
+
+            if simplified.len() as f32 > indices.len() as f32 * (1.0 - MIN_REDUCTION) {
+                continue;
+            }
+
+            let mut group_bounds = LodBounds::merge(
+                &group
+                    .iter()
+                    .map(|member| self.clusters[pending[*member]].lod_bounds)
+                    .collect::<Vec<_>>(),
+            );
+            group_bounds.error += result_error * scale;
+
+            for member in &group {
+                self.clusters[pending[*member]].parent_bounds = group_bounds;
+            }
+
+            meshopt::optimize_vertex_cache_in_place(
+                &mut simplified,
+                vertex_data_adapter.vertex_count,
+            );
+
+            let meshlet_offset = self.meshlets.len();
+            append_meshlets(
+                &meshopt::build_meshlets(
+                    &simplified,
+                    vertex_data_adapter,
+                    config.max_vertices,
+                    config.max_triangles,
+                    config.cone_weight,
+                ),
+                vertex_data_adapter,
+                self.index_format,
+                &mut self.meshlets,
+                &mut self.meshlet_data,
+            );
+
+            for cluster in meshlet_offset..self.meshlets.len() {
+                self.clusters.push(Cluster {
+                    material,
+                    level,
+                    lod_bounds: group_bounds,
+                    parent_bounds: LodBounds {
+                        error: f32::INFINITY,
+                        ..group_bounds
+                    },
+                });
+                next.push(cluster);
+            }
+        }
+
+        Ok(next)
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.clusters
+            .iter()
+            .map(|cluster| cluster.level + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the clusters that are detailed enough for `max_pixel_error` while their parents
+    /// are not. `camera_position` is in the space of the mesh.
+    pub fn select(
+        &self,
+        camera_position: Vec3,
+        projection: &Mat4,
+        viewport_height: f32,
+        max_pixel_error: f32,
+    ) -> Vec<u32> {
+        let projected_error = |bounds: &LodBounds| {
+            bounds.projected_error(camera_position, projection, viewport_height)
+        };
+
+        self.clusters
+            .iter()
+            .enumerate()
+            .filter(|(_, cluster)| {
+                projected_error(&cluster.lod_bounds) <= max_pixel_error
+                    && projected_error(&cluster.parent_bounds) > max_pixel_error
+            })
+            .map(|(index, _)| index as u32)
+            .collect()
+    }
+}
+
+/// Maps every vertex to the first vertex with the same position, so clusters that only share
+/// positions across an attribute seam are still considered neighbours.
+fn position_ids(vertices: &[Vertex]) -> Vec<u32> {
+    let mut first_vertex = HashMap::new();
+
+    vertices
+        .iter()
+        .enumerate()
+        .map(|(index, vertex)| {
+            *first_vertex
+                .entry(vertex.position.to_array().map(f32::to_bits))
+                .or_insert(index as u32)
+        })
+        .collect()
+}
+
+/// Greedily grows groups of up to [`GROUP_SIZE`] clusters, always adding the cluster sharing the
+/// most vertices with the group so far.
+fn group_clusters(cluster_indices: &[Vec<u32>], position_ids: &[u32]) -> Vec<Vec<usize>> {
+    let mut clusters_by_position: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (cluster, indices) in cluster_indices.iter().enumerate() {
+        let mut positions: Vec<u32> = indices
+            .iter()
+            .map(|index| position_ids[*index as usize])
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        for position in positions {
+            clusters_by_position
+                .entry(position)
+                .or_default()
+                .push(cluster);
+        }
+    }
+
+    let mut adjacency: Vec<HashMap<usize, u32>> = vec![HashMap::new(); cluster_indices.len()];
+    for clusters in clusters_by_position.values() {
+        for a in clusters {
+            for b in clusters {
+                if a != b {
+                    *adjacency[*a].entry(*b).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let mut grouped = vec![false; cluster_indices.len()];
+    let mut groups = Vec::new();
+
+    for seed in 0..cluster_indices.len() {
+        if grouped[seed] {
+            continue;
+        }
+        grouped[seed] = true;
+
+        let mut group = vec![seed];
+        let mut shared = adjacency[seed].clone();
+
+        while group.len() < GROUP_SIZE {
+            let Some((&next, _)) = shared
+                .iter()
+                .filter(|(cluster, _)| !grouped[**cluster])
+                .max_by_key(|(cluster, count)| (**count, std::cmp::Reverse(**cluster)))
+            else {
+                break;
+            };
+
+            grouped[next] = true;
+            group.push(next);
+            for (cluster, count) in &adjacency[next] {
+                *shared.entry(*cluster).or_default() += count;
+            }
+        }
+
+        groups.push(group);
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        path::Path,
+    };
+
+    use glam::{Mat4, Vec2, Vec3};
+
+    use crate::{
+        cluster_lod::ClusterDag,
+        mesh::{viewer_import_options, Mesh, MeshletBuildConfig, Vertex},
+    };
+
+    type Edge = ([u32; 3], [u32; 3]);
+
+    /// Unit sphere made from a subdivided cube, with the vertices on the cube edges shared.
+    fn sphere(subdivisions: usize) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for axis in 0..3 {
+            for side in [-1.0f32, 1.0] {
+                // Symmetric around zero, so vertices on shared cube edges end up bitwise equal.
+                let coordinate =
+                    |i: usize| (2 * i as i32 - subdivisions as i32) as f32 / subdivisions as f32;
+                let point = |u: usize, v: usize| {
+                    // Mirrored on the negative side to keep the winding consistent.
+                    let u = if side < 0.0 { subdivisions - u } else { u };
+
+                    let mut point = [0.0; 3];
+                    point[axis] = side;
+                    point[(axis + 1) % 3] = coordinate(u);
+                    point[(axis + 2) % 3] = coordinate(v);
+                    Vec3::from(point).normalize()
+                };
+
+                for v in 0..subdivisions {
+                    for u in 0..subdivisions {
+                        for position in [
+                            point(u, v),
+                            point(u + 1, v),
+                            point(u + 1, v + 1),
+                            point(u, v),
+                            point(u + 1, v + 1),
+                            point(u, v + 1),
+                        ] {
+                            indices.push(vertices.len() as u32);
+                            vertices.push(Vertex::new(position, Vec2::ZERO, position));
+                        }
+                    }
+                }
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    /// Edges, as pairs of positions, used an odd number of times by the selected clusters. On a
+    /// closed mesh there are none, otherwise the cut has a crack. On an open mesh they are its
+    /// borders.
+    fn odd_edges(dag: &ClusterDag, mesh: &Mesh, selection: &[u32]) -> HashSet<Edge> {
+        let position = |index: u32| {
+            mesh.vertices[index as usize]
+                .position
+                .to_array()
+                .map(f32::to_bits)
+        };
+
+        let mut edges = HashMap::new();
+        for cluster in selection {
+            for triangle in dag.meshlets[*cluster as usize]
+                .indices(dag.index_format, &dag.meshlet_data)
+                .unwrap()
+                .chunks(3)
+            {
+                for i in 0..3 {
+                    let (a, b) = (position(triangle[i]), position(triangle[(i + 1) % 3]));
+                    *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+                }
+            }
+        }
+
+        edges
+            .into_iter()
+            .filter(|(_, count)| *count % 2 != 0)
+            .map(|(edge, _)| edge)
+            .collect()
+    }
+
+    fn test_dag() -> (Mesh, ClusterDag) {
+        let (vertices, indices) = sphere(24);
+        let config = MeshletBuildConfig::default();
+        let mesh = Mesh::from_vertices(&vertices, Some(&indices), &config).unwrap();
+        let dag = ClusterDag::new(&mesh, &config).unwrap();
+
+        (mesh, dag)
+    }
+
+    #[test]
+    fn dag_errors_are_monotonic() {
+        let (mesh, dag) = test_dag();
+
+        assert!(dag.level_count() > 2);
+        assert_eq!(
+            dag.clusters
+                .iter()
+                .filter(|cluster| cluster.level == 0)
+                .count(),
+            mesh.meshlets.len()
+        );
+
+        for cluster in &dag.clusters {
+            assert!(cluster.parent_bounds.error >= cluster.lod_bounds.error);
+            assert!(
+                cluster.parent_bounds.radius >= cluster.lod_bounds.radius
+                    || cluster.parent_bounds.error == f32::INFINITY
+            );
+        }
+    }
+
+    #[test]
+    fn cut_selection_is_crack_free() {
+        let (mesh, dag) = test_dag();
+        let projection = Mat4::perspective_lh(90.0f32.to_radians(), 1.0, 0.1, 1000.0);
+
+        let mut mixed_levels = false;
+        for camera_position in [Vec3::new(0.0, 0.0, -1.2), Vec3::new(2.0, 1.0, 3.0)] {
+            for max_pixel_error in [0.0, 0.5, 2.0, 8.0, 32.0, f32::MAX] {
+                let selection = dag.select(camera_position, &projection, 1080.0, max_pixel_error);
+
+                assert!(!selection.is_empty());
+                assert!(odd_edges(&dag, &mesh, &selection).is_empty());
+
+                let levels: HashSet<u32> = selection
+                    .iter()
+                    .map(|cluster| dag.clusters[*cluster as usize].level)
+                    .collect();
+                mixed_levels |= levels.len() > 1;
+            }
+        }
+
+        assert!(mixed_levels);
+    }
+
+    /// The example models have open borders, UV seams and several materials, which the sphere
+    /// does not. Every cut has to keep exactly the borders of the full resolution mesh. The models
+    /// aren't part of the repository, place them next to `Cargo.toml` and run:
+    /// cargo test --no-default-features model_cuts_keep_borders -- --ignored
+    #[test]
+    #[ignore = "needs shepherd.obj and angel.obj next to Cargo.toml"]
+    fn model_cuts_keep_borders() {
+        for model in ["shepherd.obj", "angel.obj"] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(model);
+            let config = MeshletBuildConfig::default();
+            let (mesh, _) = Mesh::import(&path, &viewer_import_options(), &config)
+                .unwrap_or_else(|error| panic!("Failed to import {path:?}: {error:#}"));
+            let dag = ClusterDag::new(&mesh, &config).unwrap();
+            let sphere = mesh.bounds().sphere;
+            let projection = Mat4::perspective_lh(90.0f32.to_radians(), 1.0, 0.1, 1000.0);
+
+            let full = dag.select(sphere.center + Vec3::Z * 5.0, &projection, 1080.0, 0.0);
+            assert_eq!(full.len(), mesh.meshlets.len());
+            let borders = odd_edges(&dag, &mesh, &full);
+
+            for offset in [Vec3::new(0.0, 0.0, -1.2), Vec3::new(2.0, 1.0, 3.0)] {
+                let camera_position = sphere.center + offset * sphere.radius;
+                for max_pixel_error in [0.5, 2.0, 8.0, 32.0, f32::MAX] {
+                    let selection =
+                        dag.select(camera_position, &projection, 1080.0, max_pixel_error);
+
+                    assert!(!selection.is_empty());
+                    assert!(
+                        odd_edges(&dag, &mesh, &selection) == borders,
+                        "{model}: cut at {max_pixel_error} pixels from {offset} changes the \
+                         borders"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn selection_extremes() {
+        let (mesh, dag) = test_dag();
+        let projection = Mat4::perspective_lh(90.0f32.to_radians(), 1.0, 0.1, 1000.0);
+
+        let full = dag.select(Vec3::new(0.0, 0.0, -5.0), &projection, 1080.0, 0.0);
+        assert_eq!(full.len(), mesh.meshlets.len());
+
+        let coarsest = dag.select(Vec3::new(0.0, 0.0, -5.0), &projection, 1080.0, f32::MAX);
+        assert!(coarsest.len() < full.len());
+        assert!(coarsest
+            .iter()
+            .all(|cluster| dag.clusters[*cluster as usize].parent_bounds.error == f32::INFINITY));
+    }
+}
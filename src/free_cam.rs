@@ -2,10 +2,11 @@ use std::collections::HashSet;
 
 use dolly::{
     drivers::{Position, Smooth, YawPitch},
-    glam::{Mat4, Quat, Vec3},
+    glam::{Mat4, Vec3},
     handedness::LeftHanded,
     prelude::CameraRig,
 };
+use metal_3_example::bounds::BoundingSphere;
 use sdl3::keyboard::Keycode;
 
 pub const FIELD_OF_VIEW: f32 = 90.;
@@ -59,19 +60,34 @@ impl FreeCam {
         self.camera_rig.update(delta_time);
     }
 
+    pub fn position(&self) -> Vec3 {
+        self.camera_rig.final_transform.position
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> Mat4 {
+        Mat4::perspective_lh(FIELD_OF_VIEW.to_radians(), aspect, 0.1, 1000.0)
+    }
+
+    /// Moves the camera along its view direction until `sphere` just fits into the narrower of
+    /// the two fields of view. The orientation is kept.
+    pub fn frame(&mut self, sphere: &BoundingSphere, aspect: f32) {
+        let half_fov_y = FIELD_OF_VIEW.to_radians() * 0.5;
+        let half_fov_x = (half_fov_y.tan() * aspect).atan();
+        let distance = sphere.radius / half_fov_y.min(half_fov_x).sin();
+
+        let forward = self.camera_rig.final_transform.forward();
+        self.camera_rig.driver_mut::<Position>().position = sphere.center - forward * distance;
+    }
+
     pub fn vp_matrix(&self, aspect: f32) -> Mat4 {
         let final_transform = self.camera_rig.final_transform;
-        let fov = 90.0f32;
-
-        let projection_matrix = Mat4::perspective_lh(fov.to_radians(), aspect, 0.1, 1000.0);
 
-        projection_matrix
+        self.projection_matrix(aspect)
             * Mat4::look_at_lh(
                 final_transform.position,
                 final_transform.position + final_transform.forward(),
                 final_transform.up(),
             )
-            * Mat4::from_rotation_translation(Quat::IDENTITY, Vec3::new(0.0, 0.0, 1.0))
     }
 
     pub fn mouse_movement(&mut self, (x, y): (f32, f32)) {
@@ -0,0 +1,56 @@
+use std::{mem, ptr::NonNull};
+
+use metal_3_example::{geometry_pool::GeometryPool, quantization::VertexFormat};
+use objc2::{rc::Retained, runtime::ProtocolObject};
+use objc2_metal::{MTLBuffer, MTLDevice, MTLResourceOptions};
+
+/// GPU copy of a [`GeometryPool`]. Every mesh in the pool is drawn from these three buffers.
+#[derive(Clone)]
+pub struct GeometryBuffers {
+    pub vertex_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
+    pub meshlet_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
+    pub meshlet_data_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
+}
+
+impl GeometryBuffers {
+    pub unsafe fn from_pool(
+        device: &ProtocolObject<dyn MTLDevice>,
+        pool: &GeometryPool,
+        vertex_format: VertexFormat,
+    ) -> Self {
+        let vertex_buffer = match vertex_format {
+            VertexFormat::Full => new_buffer(device, &pool.vertices),
+            VertexFormat::Quantized => {
+                // Each mesh is quantized within its own bounds, which are passed per draw.
+                let mut vertices = Vec::with_capacity(pool.vertices.len());
+                for (_, allocation) in pool.iter() {
+                    vertices.extend(allocation.quantization.encode_vertices(
+                        &pool.vertices
+                            [allocation.vertices.start as usize..allocation.vertices.end as usize],
+                    ));
+                }
+
+                new_buffer(device, &vertices)
+            }
+        };
+
+        Self {
+            vertex_buffer,
+            meshlet_buffer: new_buffer(device, &pool.meshlets),
+            meshlet_data_buffer: new_buffer(device, &pool.meshlet_data),
+        }
+    }
+}
+
+unsafe fn new_buffer<T>(
+    device: &ProtocolObject<dyn MTLDevice>,
+    data: &[T],
+) -> Retained<ProtocolObject<dyn MTLBuffer>> {
+    device
+        .newBufferWithBytes_length_options(
+            NonNull::new(data.as_ptr() as *mut _).unwrap(),
+            mem::size_of_val(data) as _,
+            MTLResourceOptions::StorageModeShared,
+        )
+        .unwrap()
+}
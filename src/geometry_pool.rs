@@ -0,0 +1,336 @@
+use std::ops::Range;
+
+use anyhow::{ensure, Result};
+
+use crate::{
+    index_packing::IndexFormat,
+    lod::Lod,
+    material::Material,
+    mesh::{Mesh, Meshlet, Submesh, Vertex},
+    morph::MorphTarget,
+    quantization::QuantizationBounds,
+};
+
+/// Identifies a mesh in a [`GeometryPool`]. Handles are never reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PoolHandle(u32);
+
+/// Where a mesh lives in the arrays of a [`GeometryPool`]. The submeshes are rebased onto the
+/// pooled meshlets, so their `meshlet_offset` can be drawn with as is. LODs still index
+/// `submeshes`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolAllocation {
+    pub vertices: Range<u32>,
+    pub meshlets: Range<u32>,
+    pub meshlet_data: Range<u32>,
+    pub submeshes: Vec<Submesh>,
+    pub lods: Vec<Lod>,
+    pub materials: Vec<Material>,
+    /// Bounds the vertices of this mesh are quantized with, see
+    /// [`VertexFormat::Quantized`](crate::quantization::VertexFormat::Quantized).
+    pub quantization: QuantizationBounds,
+    /// Not pooled, see [`Mesh::morphed_vertices`].
+    pub morph_targets: Vec<MorphTarget>,
+}
+
+/// Packs the geometry of many meshes into one vertex, meshlet and meshlet data array each, so all
+/// of them can be drawn from the same three buffers. Meshlet data offsets and the vertex indices
+/// in the meshlet data are rewritten to point into the pooled arrays.
+#[derive(Clone, Debug)]
+pub struct GeometryPool {
+    pub vertices: Vec<Vertex>,
+    pub meshlets: Vec<Meshlet>,
+    pub meshlet_data: Vec<u32>,
+    index_format: IndexFormat,
+    /// Sorted by their ranges, which are contiguous.
+    allocations: Vec<(PoolHandle, PoolAllocation)>,
+    next_handle: u32,
+}
+
+impl GeometryPool {
+    /// All meshes in a pool share the `index_format`, the shader is compiled for a single one.
+    pub fn new(index_format: IndexFormat) -> Self {
+        Self {
+            vertices: Vec::new(),
+            meshlets: Vec::new(),
+            meshlet_data: Vec::new(),
+            index_format,
+            allocations: Vec::new(),
+            next_handle: 0,
+        }
+    }
+
+    #[inline]
+    pub fn index_format(&self) -> IndexFormat {
+        self.index_format
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.allocations.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.allocations.is_empty()
+    }
+
+    pub fn add(&mut self, mesh: &Mesh) -> Result<PoolHandle> {
+        ensure!(
+            mesh.index_format == self.index_format,
+            "Mesh uses index format {:?} but the pool uses {:?}",
+            mesh.index_format,
+            self.index_format
+        );
+
+        let vertex_offset = self.vertices.len() as u32;
+        let meshlet_offset = self.meshlets.len() as u32;
+        let data_offset = self.meshlet_data.len() as u32;
+
+        self.vertices.extend_from_slice(&mesh.vertices);
+        self.meshlet_data.extend_from_slice(&mesh.meshlet_data);
+        for meshlet in &mesh.meshlets {
+            let meshlet = Meshlet {
+                data_offset: meshlet.data_offset + data_offset,
+                ..*meshlet
+            };
+            for vertex in self.meshlet_vertices(&meshlet) {
+                *vertex += vertex_offset;
+            }
+            self.meshlets.push(meshlet);
+        }
+
+        let handle = PoolHandle(self.next_handle);
+        self.next_handle += 1;
+        self.allocations.push((
+            handle,
+            PoolAllocation {
+                vertices: vertex_offset..self.vertices.len() as u32,
+                meshlets: meshlet_offset..self.meshlets.len() as u32,
+                meshlet_data: data_offset..self.meshlet_data.len() as u32,
+                submeshes: mesh
+                    .submeshes
+                    .iter()
+                    .map(|submesh| {
+                        Submesh {
+                            meshlet_offset: submesh.meshlet_offset + meshlet_offset,
+                            ..*submesh
+                        }
+                    })
+                    .collect(),
+                lods: mesh.lods.clone(),
+                materials: mesh.materials.clone(),
+                quantization: QuantizationBounds::from_vertices(&mesh.vertices),
+                morph_targets: mesh.morph_targets.clone(),
+            },
+        ));
+
+        Ok(handle)
+    }
+
+    pub fn get(&self, handle: PoolHandle) -> Option<&PoolAllocation> {
+        self.allocations
+            .iter()
+            .find(|(other, _)| *other == handle)
+            .map(|(_, allocation)| allocation)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (PoolHandle, &PoolAllocation)> {
+        self.allocations
+            .iter()
+            .map(|(handle, allocation)| (*handle, allocation))
+    }
+
+    /// Removes the mesh and moves everything after it down to close the gap. Offsets of the
+    /// remaining meshes change, so they have to be looked up again and the buffers reuploaded.
+    pub fn remove(&mut self, handle: PoolHandle) -> Option<PoolAllocation> {
+        let index = self
+            .allocations
+            .iter()
+            .position(|(other, _)| *other == handle)?;
+        let (_, removed) = self.allocations.remove(index);
+
+        let vertex_count = removed.vertices.len() as u32;
+        let meshlet_count = removed.meshlets.len() as u32;
+        let data_count = removed.meshlet_data.len() as u32;
+
+        self.vertices.drain(range_usize(&removed.vertices));
+        self.meshlets.drain(range_usize(&removed.meshlets));
+        self.meshlet_data.drain(range_usize(&removed.meshlet_data));
+
+        for meshlet_index in removed.meshlets.start as usize..self.meshlets.len() {
+            let mut meshlet = self.meshlets[meshlet_index];
+            meshlet.data_offset -= data_count;
+            for vertex in self.meshlet_vertices(&meshlet) {
+                *vertex -= vertex_count;
+            }
+            self.meshlets[meshlet_index] = meshlet;
+        }
+
+        for (_, allocation) in &mut self.allocations[index..] {
+            allocation.vertices = shift(&allocation.vertices, vertex_count);
+            allocation.meshlets = shift(&allocation.meshlets, meshlet_count);
+            allocation.meshlet_data = shift(&allocation.meshlet_data, data_count);
+            for submesh in &mut allocation.submeshes {
+                submesh.meshlet_offset -= meshlet_count;
+            }
+        }
+
+        Some(removed)
+    }
+
+    /// Copies a mesh back out of the pool with its offsets relative to itself again.
+    pub fn mesh(&self, handle: PoolHandle) -> Option<Mesh> {
+        let allocation = self.get(handle)?;
+
+        let mut mesh = Mesh {
+            vertices: self.vertices[range_usize(&allocation.vertices)].to_vec(),
+            meshlets: self.meshlets[range_usize(&allocation.meshlets)].to_vec(),
+            meshlet_data: self.meshlet_data[range_usize(&allocation.meshlet_data)].to_vec(),
+            submeshes: allocation.submeshes.clone(),
+            materials: allocation.materials.clone(),
+            lods: allocation.lods.clone(),
+            index_format: self.index_format,
+            morph_targets: allocation.morph_targets.clone(),
+        };
+
+        for meshlet in &mut mesh.meshlets {
+            meshlet.data_offset -= allocation.meshlet_data.start;
+            let vertices =
+                meshlet.data_offset as usize..(meshlet.data_offset + meshlet.vertex_count) as usize;
+            for vertex in &mut mesh.meshlet_data[vertices] {
+                *vertex -= allocation.vertices.start;
+            }
+        }
+        for submesh in &mut mesh.submeshes {
+            submesh.meshlet_offset -= allocation.meshlets.start;
+        }
+
+        Some(mesh)
+    }
+
+    fn meshlet_vertices(&mut self, meshlet: &Meshlet) -> &mut [u32] {
+        &mut self.meshlet_data
+            [meshlet.data_offset as usize..(meshlet.data_offset + meshlet.vertex_count) as usize]
+    }
+}
+
+fn range_usize(range: &Range<u32>) -> Range<usize> {
+    range.start as usize..range.end as usize
+}
+
+fn shift(range: &Range<u32>, count: u32) -> Range<u32> {
+    range.start - count..range.end - count
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use crate::{
+        geometry_pool::GeometryPool,
+        index_packing::IndexFormat,
+        mesh::{Mesh, MeshletBuildConfig, Vertex},
+        primitives::Primitive,
+        shader_emulation::geometry_mesh,
+    };
+
+    fn positions(vertices: &[Vertex], triangles: &[[u32; 3]]) -> Vec<[Vec3; 3]> {
+        triangles
+            .iter()
+            .map(|triangle| triangle.map(|index| vertices[index as usize].position))
+            .collect()
+    }
+
+    fn assert_same_mesh(a: &Mesh, b: &Mesh) {
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&a.vertices),
+            bytemuck::cast_slice::<_, u8>(&b.vertices)
+        );
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&a.meshlets),
+            bytemuck::cast_slice::<_, u8>(&b.meshlets)
+        );
+        assert_eq!(a.meshlet_data, b.meshlet_data);
+        assert_eq!(a.submeshes, b.submeshes);
+        assert_eq!(a.lods, b.lods);
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let config = MeshletBuildConfig::default();
+        let meshes = [
+            Primitive::cube(1.0),
+            Primitive::uv_sphere(1.0, 32, 16),
+            Primitive::plane(Vec2::ONE, 16),
+        ]
+        .map(|primitive| primitive.to_mesh(&config).unwrap());
+
+        let mut pool = GeometryPool::new(IndexFormat::default());
+        let handles = meshes.each_ref().map(|mesh| pool.add(mesh).unwrap());
+        assert_eq!(pool.len(), 3);
+
+        for (handle, mesh) in handles.iter().zip(&meshes) {
+            assert_same_mesh(&pool.mesh(*handle).unwrap(), mesh);
+        }
+
+        assert!(pool.remove(handles[1]).is_some());
+        assert!(pool.remove(handles[1]).is_none());
+        assert!(pool.get(handles[1]).is_none());
+        assert_eq!(
+            pool.vertices.len(),
+            meshes[0].vertices.len() + meshes[2].vertices.len()
+        );
+        assert_eq!(
+            pool.meshlet_data.len(),
+            meshes[0].meshlet_data.len() + meshes[2].meshlet_data.len()
+        );
+
+        for index in [0, 2] {
+            assert_same_mesh(&pool.mesh(handles[index]).unwrap(), &meshes[index]);
+        }
+
+        // The shader only sees the pooled arrays, so drawing a submesh from them has to produce
+        // the same triangles as drawing it from the mesh itself.
+        let allocation = pool.get(handles[2]).unwrap();
+        let lod = allocation.lods[0];
+        let mut drawn = Vec::new();
+        for submesh in &allocation.submeshes
+            [lod.submesh_offset as usize..(lod.submesh_offset + lod.submesh_count) as usize]
+        {
+            for group_id in 0..submesh.meshlet_count {
+                let output = geometry_mesh(
+                    &pool.meshlets,
+                    &pool.meshlet_data,
+                    pool.index_format(),
+                    submesh.meshlet_offset,
+                    group_id,
+                )
+                .unwrap();
+                drawn.extend(
+                    output
+                        .triangles
+                        .iter()
+                        .map(|triangle| triangle.map(|index| output.vertices[index as usize])),
+                );
+            }
+        }
+        assert_eq!(
+            positions(&pool.vertices, &drawn),
+            positions(&meshes[2].vertices, &meshes[2].emulate_draw(0).unwrap())
+        );
+    }
+
+    #[test]
+    fn index_format_must_match() {
+        let config = MeshletBuildConfig {
+            index_format: IndexFormat::Strip,
+            ..Default::default()
+        };
+        let mesh = Primitive::cube(1.0).to_mesh(&config).unwrap();
+
+        let mut pool = GeometryPool::new(IndexFormat::Bytes);
+        assert!(pool.add(&mesh).is_err());
+        assert!(pool.is_empty());
+    }
+}
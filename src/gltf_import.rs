@@ -0,0 +1,900 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3};
+use gltf::{animation::util::ReadOutputs, buffer, mesh::Mode, Document, Gltf, Node, Primitive};
+
+use crate::{
+    animation::{AnimationClip, Channel, Interpolation, Keyframes, MorphAnimation, WeightsChannel},
+    material::Material,
+    mesh::{ImportOptions, Mesh, MeshletBuildConfig, Vertex},
+    morph::{MorphDelta, MorphTarget},
+    skeleton::{Joint, Skeleton, Transform},
+};
+
+pub struct GltfPrimitive {
+    pub mesh_name: Option<String>,
+    pub material: Option<usize>,
+    /// Index of the [`GltfSkin`] the vertices are bound to. Skinned primitives are in the bind
+    /// pose, without the transform of their node or the conversion of the [`ImportOptions`].
+    pub skin: Option<usize>,
+    pub mesh: Mesh,
+    /// Weights of [`Mesh::morph_targets`] when not animated.
+    pub morph_weights: Vec<f32>,
+    /// Every animation of the file that changes `morph_weights`.
+    pub morph_animations: Vec<MorphAnimation>,
+}
+
+pub struct GltfSkin {
+    pub name: Option<String>,
+    pub skeleton: Skeleton,
+    /// Every animation of the file that moves at least one joint of the skin, retargeted onto
+    /// its joint indices.
+    pub animations: Vec<AnimationClip>,
+}
+
+/// Imports every triangle primitive of the default scene from a `.gltf` or `.glb` file. Node
+/// transforms are baked into the vertices, so each node instancing a mesh yields its own
+/// primitives.
+pub fn import(
+    path: impl AsRef<Path>,
+    options: &ImportOptions,
+    config: &MeshletBuildConfig,
+) -> Result<Vec<GltfPrimitive>> {
+    let path = path.as_ref();
+
+    let Gltf { document, blob } = Gltf::open(path)?;
+    let buffers = gltf::import_buffers(&document, path.parent(), blob)?;
+
+    import_document(&document, &buffers, options, config)
+}
+
+pub fn import_document(
+    document: &Document,
+    buffers: &[buffer::Data],
+    options: &ImportOptions,
+    config: &MeshletBuildConfig,
+) -> Result<Vec<GltfPrimitive>> {
+    options.validate()?;
+
+    let roots: Vec<Node> = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().collect(),
+        None => {
+            let children: HashSet<usize> = document
+                .nodes()
+                .flat_map(|node| node.children().map(|child| child.index()))
+                .collect();
+
+            document
+                .nodes()
+                .filter(|node| !children.contains(&node.index()))
+                .collect()
+        }
+    };
+
+    let morph_animations = import_morph_animations(document, buffers)?;
+
+    let conversion = conversion_matrix(options);
+    let mut primitives = Vec::new();
+    for node in roots {
+        import_node(
+            &node,
+            &conversion,
+            buffers,
+            &morph_animations,
+            config,
+            &mut primitives,
+        )?;
+    }
+
+    Ok(primitives)
+}
+
+/// The conversion of [`ImportOptions::convert_position`] as a matrix, so it applies to positions,
+/// normals and the winding like a node transform does.
+fn conversion_matrix(options: &ImportOptions) -> Mat4 {
+    Mat4::from_mat3(Mat3::from_cols(
+        options.convert_position(Vec3::X),
+        options.convert_position(Vec3::Y),
+        options.convert_position(Vec3::Z),
+    ))
+}
+
+fn import_node(
+    node: &Node,
+    parent_transform: &Mat4,
+    buffers: &[buffer::Data],
+    morph_animations: &HashMap<usize, Vec<MorphAnimation>>,
+    config: &MeshletBuildConfig,
+    primitives: &mut Vec<GltfPrimitive>,
+) -> Result<()> {
+    let transform = *parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+    // The joints place skinned meshes, the transform of their node is ignored.
+    let vertex_transform = match node.skin() {
+        Some(_) => Mat4::IDENTITY,
+        None => transform,
+    };
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+
+            let (vertices, morph_targets) =
+                primitive_vertices(&primitive, buffers, &vertex_transform).with_context(|| {
+                    format!(
+                        "Failed to import primitive {} of mesh {}",
+                        primitive.index(),
+                        mesh.index()
+                    )
+                })?;
+
+            primitives.push(GltfPrimitive {
+                mesh_name: mesh.name().map(str::to_owned),
+                material: primitive.material().index(),
+                skin: node.skin().map(|skin| skin.index()),
+                mesh: Mesh::from_submeshes(
+                    &vertices,
+                    None,
+                    &vec![0; vertices.len() / 3],
+                    vec![Material::default()],
+                    morph_targets,
+                    config,
+                )?,
+                morph_weights: node.weights().or(mesh.weights()).map_or_else(
+                    || vec![0.0; primitive.morph_targets().len()],
+                    <[f32]>::to_vec,
+                ),
+                morph_animations: morph_animations
+                    .get(&node.index())
+                    .cloned()
+                    .unwrap_or_default(),
+            });
+        }
+    }
+
+    for child in node.children() {
+        import_node(
+            &child,
+            &transform,
+            buffers,
+            morph_animations,
+            config,
+            primitives,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn primitive_vertices(
+    primitive: &Primitive,
+    buffers: &[buffer::Data],
+    transform: &Mat4,
+) -> Result<(Vec<Vertex>, Vec<MorphTarget>)> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+    let positions: Vec<Vec3> = reader
+        .read_positions()
+        .context("Primitive has no POSITION attribute")?
+        .map(Vec3::from)
+        .collect();
+    let normals: Option<Vec<Vec3>> = reader
+        .read_normals()
+        .map(|normals| normals.map(Vec3::from).collect());
+    let tex_coords: Option<Vec<Vec2>> = reader
+        .read_tex_coords(0)
+        .map(|tex_coords| tex_coords.into_f32().map(Vec2::from).collect());
+    let joints: Option<Vec<[u16; 4]>> = reader
+        .read_joints(0)
+        .map(|joints| joints.into_u16().collect());
+    let weights: Option<Vec<[u16; 4]>> = reader
+        .read_weights(0)
+        .map(|weights| weights.into_u16().collect());
+    // Displacements of the targets, which are in the same space as the attributes.
+    let target_deltas: Vec<Vec<MorphDelta>> = reader
+        .read_morph_targets()
+        .map(|(positions_deltas, normal_deltas, _)| {
+            let position_deltas: Vec<Vec3> = positions_deltas.map_or_else(
+                || vec![Vec3::ZERO; positions.len()],
+                |deltas| deltas.map(Vec3::from).collect(),
+            );
+            let normal_deltas: Vec<Vec3> = normal_deltas.map_or_else(
+                || vec![Vec3::ZERO; positions.len()],
+                |deltas| deltas.map(Vec3::from).collect(),
+            );
+
+            position_deltas
+                .into_iter()
+                .zip(normal_deltas)
+                .map(|(position, normal)| MorphDelta { position, normal })
+                .collect()
+        })
+        .collect();
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+
+    ensure!(
+        indices.len() % 3 == 0,
+        "Index count {} is not a multiple of 3",
+        indices.len()
+    );
+    if let Some(index) = indices
+        .iter()
+        .find(|index| **index as usize >= positions.len())
+    {
+        bail!(
+            "Index {index} out of range for {} vertices",
+            positions.len()
+        );
+    }
+
+    for (name, count) in [
+        ("NORMAL", normals.as_ref().map(Vec::len)),
+        ("TEXCOORD_0", tex_coords.as_ref().map(Vec::len)),
+        ("JOINTS_0", joints.as_ref().map(Vec::len)),
+        ("WEIGHTS_0", weights.as_ref().map(Vec::len)),
+    ] {
+        if let Some(count) = count {
+            ensure!(
+                count == positions.len(),
+                "{name} has {count} values for {} vertices",
+                positions.len()
+            );
+        }
+    }
+
+    if let Some(deltas) = target_deltas
+        .iter()
+        .find(|deltas| deltas.len() != positions.len())
+    {
+        bail!(
+            "Morph target has {} deltas for {} vertices",
+            deltas.len(),
+            positions.len()
+        );
+    }
+
+    let linear_transform = Mat3::from_mat4(*transform);
+    let normal_matrix = linear_transform.inverse().transpose();
+    // Mirroring transforms, including `ImportOptions::flip_handedness`, turn the triangles inside
+    // out, so the winding has to be flipped back.
+    let flip_winding = transform.determinant() < 0.0;
+
+    let mut vertices = Vec::with_capacity(indices.len());
+    let mut morph_targets: Vec<MorphTarget> = target_deltas
+        .iter()
+        .map(|_| {
+            MorphTarget {
+                name: None,
+                deltas: Vec::with_capacity(indices.len()),
+            }
+        })
+        .collect();
+
+    for triangle in indices.chunks_exact(3) {
+        let triangle = if flip_winding {
+            [triangle[0], triangle[2], triangle[1]]
+        } else {
+            [triangle[0], triangle[1], triangle[2]]
+        };
+
+        let world_positions =
+            triangle.map(|index| transform.transform_point3(positions[index as usize]));
+        let face_normal = (world_positions[1] - world_positions[0])
+            .cross(world_positions[2] - world_positions[0])
+            .normalize_or_zero();
+
+        for (index, position) in triangle.into_iter().zip(world_positions) {
+            let index = index as usize;
+
+            let normal = normals.as_ref().map_or(face_normal, |normals| {
+                (normal_matrix * normals[index]).normalize_or_zero()
+            });
+            // glTF already puts the texture origin at the top left, like the shader.
+            let tex_coord = tex_coords
+                .as_ref()
+                .map_or(Vec2::ZERO, |tex_coords| tex_coords[index]);
+
+            let mut vertex = Vertex::new(position, tex_coord, normal);
+            if let (Some(joints), Some(weights)) = (&joints, &weights) {
+                vertex.joints = joints[index];
+                vertex.weights = weights[index];
+            }
+
+            vertices.push(vertex);
+
+            for (target, deltas) in morph_targets.iter_mut().zip(&target_deltas) {
+                target.deltas.push(MorphDelta {
+                    position: linear_transform * deltas[index].position,
+                    normal: normal_matrix * deltas[index].normal,
+                });
+            }
+        }
+    }
+
+    Ok((vertices, morph_targets))
+}
+
+/// Morph target weight animations by the node they animate. Every primitive of the node's mesh has
+/// the same number of targets.
+fn import_morph_animations(
+    document: &Document,
+    buffers: &[buffer::Data],
+) -> Result<HashMap<usize, Vec<MorphAnimation>>> {
+    let mut morph_animations: HashMap<usize, Vec<MorphAnimation>> = HashMap::new();
+
+    for animation in document.animations() {
+        for channel in animation.channels() {
+            let node = channel.target().node();
+            let reader =
+                channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+            let Some(ReadOutputs::MorphTargetWeights(weights)) = reader.read_outputs() else {
+                continue;
+            };
+            let Some(primitive) = node.mesh().and_then(|mesh| mesh.primitives().next()) else {
+                continue;
+            };
+
+            let times: Vec<f32> = reader
+                .read_inputs()
+                .context("Animation channel has no keyframe times")?
+                .collect();
+            let weights = WeightsChannel::new(
+                interpolation(channel.sampler().interpolation()),
+                times,
+                primitive.morph_targets().len(),
+                weights.into_f32().collect(),
+            )
+            .with_context(|| {
+                format!(
+                    "Invalid channel {} of animation {}",
+                    channel.index(),
+                    animation.index()
+                )
+            })?;
+
+            morph_animations
+                .entry(node.index())
+                .or_default()
+                .push(MorphAnimation {
+                    name: animation.name().map(str::to_owned),
+                    weights,
+                });
+        }
+    }
+
+    Ok(morph_animations)
+}
+
+/// Imports the skins of a `.gltf` or `.glb` file along with their animations. Indexed by
+/// [`GltfPrimitive::skin`].
+pub fn import_skins(path: impl AsRef<Path>) -> Result<Vec<GltfSkin>> {
+    let path = path.as_ref();
+
+    let Gltf { document, blob } = Gltf::open(path)?;
+    let buffers = gltf::import_buffers(&document, path.parent(), blob)?;
+
+    import_document_skins(&document, &buffers)
+}
+
+pub fn import_document_skins(
+    document: &Document,
+    buffers: &[buffer::Data],
+) -> Result<Vec<GltfSkin>> {
+    let parents: HashMap<usize, Node> = document
+        .nodes()
+        .flat_map(|node| {
+            node.children()
+                .map(move |child| (child.index(), node.clone()))
+        })
+        .collect();
+
+    document
+        .skins()
+        .map(|skin| {
+            let reader = skin.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+            let inverse_bind_matrices: Option<Vec<Mat4>> =
+                reader.read_inverse_bind_matrices().map(|matrices| {
+                    matrices
+                        .map(|matrix| Mat4::from_cols_array_2d(&matrix))
+                        .collect()
+                });
+
+            let joint_nodes: Vec<Node> = skin.joints().collect();
+            let joint_indices: HashMap<usize, usize> = joint_nodes
+                .iter()
+                .enumerate()
+                .map(|(joint, node)| (node.index(), joint))
+                .collect();
+            if let Some(matrices) = &inverse_bind_matrices {
+                ensure!(
+                    matrices.len() >= joint_nodes.len(),
+                    "Skin {} has {} inverse bind matrices for {} joints",
+                    skin.index(),
+                    matrices.len(),
+                    joint_nodes.len()
+                );
+            }
+
+            let mut root_transform = None;
+            let joints = joint_nodes
+                .iter()
+                .enumerate()
+                .map(|(joint, node)| {
+                    let parent = parents
+                        .get(&node.index())
+                        .and_then(|parent| joint_indices.get(&parent.index()).copied());
+                    if parent.is_none() && root_transform.is_none() {
+                        root_transform =
+                            Some(world_transform(parents.get(&node.index()), &parents));
+                    }
+
+                    let (translation, rotation, scale) = node.transform().decomposed();
+                    Joint {
+                        name: node.name().map(str::to_owned),
+                        parent,
+                        rest: Transform {
+                            translation: Vec3::from(translation),
+                            rotation: Quat::from_array(rotation),
+                            scale: Vec3::from(scale),
+                        },
+                        inverse_bind_matrix: inverse_bind_matrices
+                            .as_ref()
+                            .map_or(Mat4::IDENTITY, |matrices| matrices[joint]),
+                    }
+                })
+                .collect();
+
+            let skeleton = Skeleton::new(joints, root_transform.unwrap_or(Mat4::IDENTITY))
+                .with_context(|| format!("Invalid joint hierarchy in skin {}", skin.index()))?;
+            let mut animations = document
+                .animations()
+                .map(|animation| import_animation(&animation, buffers, &joint_indices))
+                .collect::<Result<Vec<_>>>()?;
+            animations.retain(|clip| !clip.channels.is_empty());
+
+            Ok(GltfSkin {
+                name: skin.name().map(str::to_owned),
+                skeleton,
+                animations,
+            })
+        })
+        .collect()
+}
+
+fn world_transform(node: Option<&Node>, parents: &HashMap<usize, Node>) -> Mat4 {
+    let mut transform = Mat4::IDENTITY;
+    let mut node = node;
+    while let Some(current) = node {
+        transform = Mat4::from_cols_array_2d(&current.transform().matrix()) * transform;
+        node = parents.get(&current.index());
+    }
+
+    transform
+}
+
+/// Keeps the channels targeting a node in `joint_indices`. Morph target weights are not supported
+/// and skipped.
+fn import_animation(
+    animation: &gltf::Animation,
+    buffers: &[buffer::Data],
+    joint_indices: &HashMap<usize, usize>,
+) -> Result<AnimationClip> {
+    let mut channels = Vec::new();
+
+    for channel in animation.channels() {
+        let Some(&joint) = joint_indices.get(&channel.target().node().index()) else {
+            continue;
+        };
+
+        let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+        let times: Vec<f32> = reader
+            .read_inputs()
+            .context("Animation channel has no keyframe times")?
+            .collect();
+        let keyframes = match reader
+            .read_outputs()
+            .context("Animation channel has no keyframe values")?
+        {
+            ReadOutputs::Translations(values) => {
+                Keyframes::Translation(values.map(Vec3::from).collect())
+            }
+            ReadOutputs::Rotations(values) => {
+                Keyframes::Rotation(values.into_f32().map(Quat::from_array).collect())
+            }
+            ReadOutputs::Scales(values) => Keyframes::Scale(values.map(Vec3::from).collect()),
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+
+        channels.push(
+            Channel::new(
+                joint,
+                interpolation(channel.sampler().interpolation()),
+                times,
+                keyframes,
+            )
+            .with_context(|| {
+                format!(
+                    "Invalid channel {} of animation {}",
+                    channel.index(),
+                    animation.index()
+                )
+            })?,
+        );
+    }
+
+    Ok(AnimationClip {
+        name: animation.name().map(str::to_owned),
+        channels,
+    })
+}
+
+fn interpolation(interpolation: gltf::animation::Interpolation) -> Interpolation {
+    match interpolation {
+        gltf::animation::Interpolation::Step => Interpolation::Step,
+        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, f32::consts::FRAC_PI_2, fs, path::PathBuf};
+
+    use glam::{Mat4, Quat, Vec3};
+
+    use crate::{
+        gltf_import::{import, import_skins},
+        mesh::{ImportOptions, MeshletBuildConfig, UpAxis, Vertex},
+        skeleton::skin_vertices,
+    };
+
+    const TRIANGLE_BUFFER_BASE64: &str =
+        "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIAAAA=";
+
+    fn triangle_buffer() -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 0] {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        buffer
+    }
+
+    fn document_json(buffer: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [
+                    {{ "translation": [0, 0, 5], "children": [1], "mesh": 0 }},
+                    {{ "scale": [-1, 1, 1], "mesh": 0 }}
+                ],
+                "meshes": [{{
+                    "name": "triangle",
+                    "primitives": [
+                        {{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }},
+                        {{ "attributes": {{ "POSITION": 0 }} }}
+                    ]
+                }}],
+                "buffers": [{{ "byteLength": 44{buffer} }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+                ],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0]
+                    }},
+                    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+                ]
+            }}"#
+        )
+    }
+
+    fn check_primitives(path: &std::path::Path) {
+        let primitives = import(
+            path,
+            &ImportOptions::default(),
+            &MeshletBuildConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(primitives.len(), 4);
+
+        for (i, primitive) in primitives.iter().enumerate() {
+            let mirrored = i >= 2;
+
+            assert_eq!(primitive.mesh_name.as_deref(), Some("triangle"));
+            assert_eq!(primitive.mesh.vertices.len(), 3);
+            assert_eq!(primitive.mesh.meshlets.len(), 1);
+            assert_eq!(primitive.mesh.meshlets[0].triangle_count, 1);
+
+            for vertex in &primitive.mesh.vertices {
+                assert_eq!(vertex.position.z, 5.0);
+                assert_eq!(
+                    vertex.position.x <= 0.0,
+                    mirrored || vertex.position.x == 0.0
+                );
+                assert!(vertex.normal.abs_diff_eq(Vec3::Z, 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn import_embedded_gltf() {
+        let path = env::temp_dir().join("import_embedded_gltf.gltf");
+        fs::write(
+            &path,
+            document_json(&format!(
+                r#", "uri": "data:application/octet-stream;base64,{TRIANGLE_BUFFER_BASE64}""#
+            )),
+        )
+        .unwrap();
+
+        check_primitives(&path);
+    }
+
+    #[test]
+    fn import_glb() {
+        let path = write_glb("import_glb.glb", &document_json(""), &triangle_buffer());
+
+        check_primitives(&path);
+    }
+
+    #[test]
+    fn import_applies_options() {
+        let path = write_glb(
+            "import_applies_options.glb",
+            &document_json(""),
+            &triangle_buffer(),
+        );
+        let options = ImportOptions {
+            up_axis: UpAxis::Z,
+            flip_handedness: true,
+            unit_scale: 2.0,
+            ..Default::default()
+        };
+
+        let primitives = import(&path, &options, &MeshletBuildConfig::default()).unwrap();
+        assert_eq!(primitives.len(), 4);
+        for primitive in &primitives {
+            for vertex in &primitive.mesh.vertices {
+                // Z up becomes Y up, so the node translation of 5 along Z is 10 along Y.
+                assert_eq!(vertex.position.y, 10.0);
+                assert!(vertex.position.x.abs() <= 2.0);
+                // The winding follows the mirrored Z, so the triangle still faces the old +Z.
+                assert!(vertex.normal.abs_diff_eq(Vec3::Y, 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn import_skinned_glb() {
+        let mut bin = Vec::new();
+        let mut push_f32s = |values: &[f32]| {
+            for value in values {
+                bin.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        // Positions
+        push_f32s(&[0.0, 0.5, 0.0, 1.0, 0.5, 0.0, 0.0, 2.0, 0.0]);
+        // Weights
+        push_f32s(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        // Inverse bind matrices, the joints are at (0, 0, 2) and (0, 1, 2)
+        for translation in [Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.0, -1.0, -2.0)] {
+            push_f32s(&Mat4::from_translation(translation).to_cols_array());
+        }
+        // Keyframe times and rotations
+        push_f32s(&[0.0, 1.0]);
+        push_f32s(&Quat::IDENTITY.to_array());
+        push_f32s(&Quat::from_rotation_z(FRAC_PI_2).to_array());
+        // Joints
+        for joint in [0u16, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0] {
+            bin.extend_from_slice(&joint.to_le_bytes());
+        }
+
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [
+                { "translation": [0, 0, 2], "children": [1, 3] },
+                { "name": "upper", "children": [2] },
+                { "name": "lower", "translation": [0, 1, 0] },
+                { "translation": [5, 0, 0], "mesh": 0, "skin": 0 }
+            ],
+            "meshes": [{
+                "primitives": [{ "attributes": { "POSITION": 0, "WEIGHTS_0": 1, "JOINTS_0": 5 } }]
+            }],
+            "skins": [{ "joints": [1, 2], "inverseBindMatrices": 2 }],
+            "animations": [{
+                "name": "bend",
+                "samplers": [{ "input": 3, "output": 4 }],
+                "channels": [{ "sampler": 0, "target": { "node": 2, "path": "rotation" } }]
+            }],
+            "buffers": [{ "byteLength": 276 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 48 },
+                { "buffer": 0, "byteOffset": 84, "byteLength": 128 },
+                { "buffer": 0, "byteOffset": 212, "byteLength": 8 },
+                { "buffer": 0, "byteOffset": 220, "byteLength": 32 },
+                { "buffer": 0, "byteOffset": 252, "byteLength": 24 }
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0.5, 0], "max": [1, 2, 0]
+                },
+                { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC4" },
+                { "bufferView": 2, "componentType": 5126, "count": 2, "type": "MAT4" },
+                {
+                    "bufferView": 3, "componentType": 5126, "count": 2, "type": "SCALAR",
+                    "min": [0], "max": [1]
+                },
+                { "bufferView": 4, "componentType": 5126, "count": 2, "type": "VEC4" },
+                { "bufferView": 5, "componentType": 5123, "count": 3, "type": "VEC4" }
+            ]
+        }"#;
+        let path = write_glb("import_skinned_glb.glb", json, &bin);
+
+        let primitives = import(
+            &path,
+            &ImportOptions::default(),
+            &MeshletBuildConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(primitives.len(), 1);
+        assert_eq!(primitives[0].skin, Some(0));
+        let vertices = &primitives[0].mesh.vertices;
+        assert!(vertices.iter().all(Vertex::is_skinned));
+        // The transform of the skinned node is ignored.
+        assert!(vertices.iter().all(|vertex| vertex.position.x <= 1.0));
+
+        let skins = import_skins(&path).unwrap();
+        assert_eq!(skins.len(), 1);
+        let skeleton = &skins[0].skeleton;
+        assert_eq!(skeleton.joints[0].name.as_deref(), Some("upper"));
+        assert_eq!(skeleton.joints[1].parent, Some(0));
+        assert_eq!(
+            skeleton.root_transform,
+            Mat4::from_translation(Vec3::Z * 2.0)
+        );
+
+        // In the rest pose the vertices stay where they are.
+        let rest = skin_vertices(vertices, &skeleton.joint_palette(&skeleton.rest_pose()));
+        for (skinned, vertex) in rest.iter().zip(vertices) {
+            assert!(skinned.position.abs_diff_eq(vertex.position, 1e-5));
+        }
+
+        let clip = &skins[0].animations[0];
+        assert_eq!(clip.name.as_deref(), Some("bend"));
+        assert_eq!(clip.duration(), 1.0);
+        let bent = skin_vertices(
+            vertices,
+            &skeleton.joint_palette(&clip.sample(skeleton, 1.0)),
+        );
+        let tip = vertices
+            .iter()
+            .position(|vertex| vertex.position.y == 2.0)
+            .unwrap();
+        assert!(bent[tip]
+            .position
+            .abs_diff_eq(Vec3::new(-1.0, 1.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn import_morph_targets() {
+        let mut bin = Vec::new();
+        // Positions, then the deltas of the only target, which lift the last corner
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [0.0f32, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        // Keyframe times and weights
+        for value in [0.0f32, 2.0, 0.0, 1.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "nodes": [{ "translation": [0, 0, 5], "mesh": 0 }],
+            "meshes": [{
+                "primitives": [{ "attributes": { "POSITION": 0 }, "targets": [{ "POSITION": 1 }] }],
+                "weights": [0.5]
+            }],
+            "animations": [{
+                "name": "lift",
+                "samplers": [{ "input": 2, "output": 3 }],
+                "channels": [{ "sampler": 0, "target": { "node": 0, "path": "weights" } }]
+            }],
+            "buffers": [{ "byteLength": 88 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 72, "byteLength": 8 },
+                { "buffer": 0, "byteOffset": 80, "byteLength": 8 }
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]
+                },
+                {
+                    "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [0, 0, 1]
+                },
+                {
+                    "bufferView": 2, "componentType": 5126, "count": 2, "type": "SCALAR",
+                    "min": [0], "max": [2]
+                },
+                { "bufferView": 3, "componentType": 5126, "count": 2, "type": "SCALAR" }
+            ]
+        }"#;
+        let path = write_glb("import_morph_targets.glb", json, &bin);
+
+        let primitives = import(
+            &path,
+            &ImportOptions::default(),
+            &MeshletBuildConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(primitives.len(), 1);
+        let primitive = &primitives[0];
+        assert_eq!(primitive.mesh.morph_targets.len(), 1);
+        assert_eq!(primitive.morph_weights, [0.5]);
+
+        let animation = &primitive.morph_animations[0];
+        assert_eq!(animation.name.as_deref(), Some("lift"));
+        let weights = animation.weights.sample(1.0);
+        assert_eq!(weights, [0.5]);
+
+        let morphed = primitive.mesh.morphed_vertices(&weights);
+        let lifted = primitive
+            .mesh
+            .vertices
+            .iter()
+            .position(|vertex| vertex.position.y == 1.0)
+            .unwrap();
+        assert_eq!(morphed[lifted].position, Vec3::new(0.0, 1.0, 5.5));
+        // The bounds already contain the fully morphed triangle.
+        let meshlet = &primitive.mesh.meshlets[0];
+        assert!(Vec3::new(0.0, 1.0, 6.0).distance(meshlet.center) <= meshlet.radius);
+    }
+
+    fn write_glb(name: &str, json: &str, bin: &[u8]) -> PathBuf {
+        let mut json = json.as_bytes().to_vec();
+        json.resize((json.len() + 3) & !3, b' ');
+
+        let mut glb = Vec::new();
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(bin);
+
+        let path = env::temp_dir().join(name);
+        fs::write(&path, glb).unwrap();
+        path
+    }
+}
@@ -0,0 +1,419 @@
+use std::mem;
+
+use anyhow::{bail, Context, Result};
+
+use crate::mesh::Mesh;
+
+/// How the local triangle indices of a meshlet are stored in `meshlet_data`, after its vertex
+/// indices. Every format has a matching unpack function in `shaders/geometry.hlsl`, selected by
+/// the `INDEX_FORMAT` define.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IndexFormat {
+    /// 8 bit indices packed four per `u32`, so triangles straddle words.
+    #[default]
+    Bytes,
+    /// One triangle per `u32` with 10 bits per index.
+    Triangle10,
+    /// Triangles reordered into strips. A 2 bit code per triangle either restarts the strip with
+    /// three explicit indices or reuses an edge of the previous triangle with one new index. All
+    /// codes come first, packed 16 per `u32`, followed by the indices as bytes.
+    Strip,
+}
+
+impl IndexFormat {
+    pub const ALL: [Self; 3] = [Self::Bytes, Self::Triangle10, Self::Strip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bytes => "bytes",
+            Self::Triangle10 => "triangle10",
+            Self::Strip => "strip",
+        }
+    }
+
+    /// Value of the `INDEX_FORMAT` shader define.
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::Bytes => 0,
+            Self::Triangle10 => 1,
+            Self::Strip => 2,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Result<Self> {
+        match Self::ALL.into_iter().find(|format| format.id() == id) {
+            Some(format) => Ok(format),
+            None => bail!("Unknown index format {id}"),
+        }
+    }
+
+    /// Packs flat local triangle indices. The strip format may reorder and rotate triangles,
+    /// but keeps their winding.
+    pub fn pack(&self, triangles: &[u8]) -> Vec<u32> {
+        match self {
+            Self::Bytes => pack_bytes(triangles),
+            Self::Triangle10 => {
+                triangles
+                    .chunks(3)
+                    .map(|triangle| {
+                        triangle[0] as u32 | (triangle[1] as u32) << 10 | (triangle[2] as u32) << 20
+                    })
+                    .collect()
+            }
+            Self::Strip => pack_strip(triangles),
+        }
+    }
+
+    /// Number of `u32`s `triangle_count` packed triangles at the start of `packed` occupy, or
+    /// `None` if `packed` is too short to tell.
+    pub fn packed_len(&self, packed: &[u32], triangle_count: u32) -> Option<usize> {
+        let triangle_count = triangle_count as usize;
+
+        let len = match self {
+            Self::Bytes => (triangle_count * 3).div_ceil(4),
+            Self::Triangle10 => triangle_count,
+            Self::Strip => {
+                let code_len = triangle_count.div_ceil(16);
+                let byte_count: usize = (0..triangle_count)
+                    .map(|triangle| strip_code(packed.get(..code_len)?, triangle))
+                    .map(|code| code.map(|code| if code == 0 { 3 } else { 1 }))
+                    .sum::<Option<usize>>()?;
+
+                code_len + byte_count.div_ceil(4)
+            }
+        };
+
+        (len <= packed.len()).then_some(len)
+    }
+
+    /// Inverse of [`IndexFormat::pack`], returns flat local triangle indices. Fails if `packed`
+    /// is too short for `triangle_count` triangles.
+    pub fn unpack(&self, packed: &[u32], triangle_count: u32) -> Result<Vec<u32>> {
+        let Some(len) = self.packed_len(packed, triangle_count) else {
+            bail!(
+                "Truncated {} triangles, {triangle_count} triangles do not fit in {} words",
+                self.name(),
+                packed.len()
+            );
+        };
+        let packed = &packed[..len];
+        let triangle_count = triangle_count as usize;
+
+        Ok(match self {
+            Self::Bytes => {
+                bytemuck::cast_slice::<_, u8>(packed)[..triangle_count * 3]
+                    .iter()
+                    .map(|index| *index as u32)
+                    .collect()
+            }
+            Self::Triangle10 => {
+                packed
+                    .iter()
+                    .flat_map(|packed| unpack_triangle_10(*packed))
+                    .collect()
+            }
+            Self::Strip => {
+                unpack_strip(packed, triangle_count)?
+                    .into_iter()
+                    .flatten()
+                    .map(|index| index as u32)
+                    .collect()
+            }
+        })
+    }
+}
+
+impl Mesh {
+    /// Average size of the packed triangles of all meshlets if they were stored in each format.
+    pub fn index_bytes_per_triangle(&self) -> Result<Vec<(IndexFormat, f32)>> {
+        let triangles: Vec<(Vec<u8>, u32)> = self
+            .meshlets
+            .iter()
+            .enumerate()
+            .map(|(index, meshlet)| {
+                let packed = self
+                    .meshlet_data
+                    .get(meshlet.data_offset as usize + meshlet.vertex_count as usize..)
+                    .with_context(|| format!("Meshlet {index} data is out of range"))?;
+                let triangles = self
+                    .index_format
+                    .unpack(packed, meshlet.triangle_count)
+                    .with_context(|| format!("Meshlet {index} has invalid triangles"))?
+                    .into_iter()
+                    .map(|index| index as u8)
+                    .collect();
+                Ok((triangles, meshlet.triangle_count))
+            })
+            .collect::<Result<_>>()?;
+        let triangle_count: u32 = triangles.iter().map(|(_, count)| count).sum();
+
+        Ok(IndexFormat::ALL
+            .into_iter()
+            .map(|format| {
+                let packed_len: usize = triangles
+                    .iter()
+                    .map(|(triangles, _)| format.pack(triangles).len())
+                    .sum();
+                let bytes = packed_len * mem::size_of::<u32>();
+                (format, bytes as f32 / triangle_count.max(1) as f32)
+            })
+            .collect())
+    }
+}
+
+fn pack_bytes(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks(4)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |packed, (i, byte)| packed | (*byte as u32) << (i * 8))
+        })
+        .collect()
+}
+
+/// Same bit logic as `unpack_triangle_10` in the shader.
+pub fn unpack_triangle_10(packed: u32) -> [u32; 3] {
+    [
+        packed & 0x3FF,
+        (packed >> 10) & 0x3FF,
+        (packed >> 20) & 0x3FF,
+    ]
+}
+
+fn strip_code(codes: &[u32], triangle: usize) -> Option<u32> {
+    Some((codes.get(triangle >> 4)? >> ((triangle & 15) << 1)) & 3)
+}
+
+/// The triangle reusing an edge of `previous` for strip `code` 1, 2 or 3. The shared edge is
+/// walked in the opposite direction, which keeps the winding of both triangles consistent.
+#[inline]
+fn strip_triangle(previous: [u8; 3], code: u32, new_index: u8) -> [u8; 3] {
+    let [a, b, c] = previous;
+    match code {
+        1 => [b, a, new_index],
+        2 => [c, b, new_index],
+        _ => [a, c, new_index],
+    }
+}
+
+/// Greedily continues the strip with any remaining triangle adjacent to the last one.
+fn pack_strip(triangles: &[u8]) -> Vec<u32> {
+    let triangles: Vec<[u8; 3]> = triangles
+        .chunks(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect();
+    let mut packed_triangles = vec![false; triangles.len()];
+
+    let mut codes = Vec::with_capacity(triangles.len());
+    let mut bytes = Vec::new();
+    let mut previous: Option<[u8; 3]> = None;
+
+    for _ in 0..triangles.len() {
+        let continuation = previous.and_then(|previous| {
+            (0..triangles.len())
+                .filter(|triangle| !packed_triangles[*triangle])
+                .find_map(|triangle| {
+                    (1..=3).find_map(|code| {
+                        let [first, second, _] = strip_triangle(previous, code, 0);
+                        let [a, b, c] = triangles[triangle];
+                        let new_index = [(a, b, c), (b, c, a), (c, a, b)]
+                            .into_iter()
+                            .find(|(x, y, _)| (*x, *y) == (first, second))?
+                            .2;
+                        Some((triangle, code, new_index))
+                    })
+                })
+        });
+
+        let triangle = match continuation {
+            Some((triangle, code, new_index)) => {
+                codes.push(code);
+                bytes.push(new_index);
+                packed_triangles[triangle] = true;
+                strip_triangle(previous.unwrap(), code, new_index)
+            }
+            None => {
+                let triangle = (0..triangles.len())
+                    .find(|triangle| !packed_triangles[*triangle])
+                    .unwrap();
+                codes.push(0);
+                bytes.extend(triangles[triangle]);
+                packed_triangles[triangle] = true;
+                triangles[triangle]
+            }
+        };
+        previous = Some(triangle);
+    }
+
+    let mut packed: Vec<u32> = codes
+        .chunks(16)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |packed, (i, code)| packed | code << (i * 2))
+        })
+        .collect();
+    packed.extend(pack_bytes(&bytes));
+    packed
+}
+
+/// Decodes the strip sequentially, like the first thread of `geometry_mesh` does. Fails if
+/// `packed` ends before the last triangle.
+pub fn unpack_strip(packed: &[u32], triangle_count: usize) -> Result<Vec<[u8; 3]>> {
+    let codes = packed
+        .get(..triangle_count.div_ceil(16))
+        .context("Truncated strip codes")?;
+    let bytes: &[u8] = bytemuck::cast_slice(&packed[codes.len()..]);
+
+    let mut triangles = Vec::with_capacity(triangle_count);
+    let mut byte_index = 0;
+    let mut previous = [0; 3];
+
+    for triangle in 0..triangle_count {
+        let code = strip_code(codes, triangle).context("Truncated strip codes")?;
+        let new_indices = if code == 0 { 3 } else { 1 };
+        let new_bytes = bytes
+            .get(byte_index..byte_index + new_indices)
+            .with_context(|| format!("Truncated strip indices at triangle {triangle}"))?;
+        byte_index += new_indices;
+
+        previous = match *new_bytes {
+            [a, b, c] => [a, b, c],
+            _ => strip_triangle(previous, code, new_bytes[0]),
+        };
+        triangles.push(previous);
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use crate::{
+        index_packing::IndexFormat,
+        mesh::{Mesh, MeshletBuildConfig, Vertex},
+    };
+
+    /// Rotates every triangle to start at its smallest index and sorts them, so triangle lists
+    /// can be compared regardless of order and rotation.
+    fn canonical(triangles: impl IntoIterator<Item = impl Into<u32>>) -> Vec<[u32; 3]> {
+        let triangles: Vec<u32> = triangles.into_iter().map(Into::into).collect();
+        let mut triangles: Vec<[u32; 3]> = triangles
+            .chunks(3)
+            .map(|triangle| {
+                let first = (0..3).min_by_key(|corner| triangle[*corner]).unwrap();
+                [0, 1, 2].map(|corner| triangle[(first + corner) % 3])
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    /// Two triangles per cell of a `size` x `size` grid.
+    fn grid(size: u8) -> Vec<u8> {
+        let mut triangles = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let corner = |dx: u8, dy: u8| (y + dy) * (size + 1) + x + dx;
+                triangles.extend([corner(0, 0), corner(1, 0), corner(1, 1)]);
+                triangles.extend([corner(0, 0), corner(1, 1), corner(0, 1)]);
+            }
+        }
+        triangles
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        let triangles = grid(8);
+        let triangle_count = (triangles.len() / 3) as u32;
+
+        for format in IndexFormat::ALL {
+            let mut packed = format.pack(&triangles);
+            let packed_len = format.packed_len(&packed, triangle_count).unwrap();
+            assert_eq!(packed_len, packed.len(), "{format:?}");
+
+            // Decoding must not depend on data past the end of the meshlet.
+            packed.extend([u32::MAX; 4]);
+            let unpacked = format.unpack(&packed, triangle_count).unwrap();
+            assert_eq!(
+                canonical(unpacked),
+                canonical(triangles.clone()),
+                "{format:?}"
+            );
+
+            assert!(format
+                .packed_len(&packed[..packed_len - 1], triangle_count)
+                .is_none());
+            assert!(format
+                .unpack(&packed[..packed_len - 1], triangle_count)
+                .is_err());
+            assert_eq!(IndexFormat::from_id(format.id()).unwrap(), format);
+        }
+    }
+
+    #[test]
+    fn strips_are_smaller() {
+        let triangles = grid(8);
+        let triangle_count = (triangles.len() / 3) as u32;
+        let packed_len = |format: IndexFormat| {
+            format
+                .packed_len(&format.pack(&triangles), triangle_count)
+                .unwrap()
+        };
+
+        assert_eq!(packed_len(IndexFormat::Bytes), 96);
+        assert_eq!(packed_len(IndexFormat::Triangle10), 128);
+        assert!(packed_len(IndexFormat::Strip) < 96 / 2);
+    }
+
+    #[test]
+    fn bytes_per_triangle_report() {
+        let size = 8;
+        let vertices: Vec<Vertex> = (0..=size)
+            .flat_map(|y| {
+                (0..=size).map(move |x| {
+                    Vertex::new(Vec3::new(x as f32, y as f32, 0.0), Vec2::ZERO, Vec3::Z)
+                })
+            })
+            .collect();
+        let indices: Vec<u32> = grid(size as u8).into_iter().map(u32::from).collect();
+        let mesh =
+            Mesh::from_vertices(&vertices, Some(&indices), &MeshletBuildConfig::default()).unwrap();
+
+        let report = mesh.index_bytes_per_triangle().unwrap();
+        // Bytes pads every meshlet to whole words.
+        assert_eq!(report[0].0, IndexFormat::Bytes);
+        assert!((3.0..3.1).contains(&report[0].1));
+        assert_eq!(report[1], (IndexFormat::Triangle10, 4.0));
+        assert!(report[2].1 < 2.0);
+
+        // Truncated data is an error instead of a panic.
+        let mut truncated = mesh.clone();
+        truncated
+            .meshlet_data
+            .truncate(truncated.meshlet_data.len() - 1);
+        assert!(truncated.index_bytes_per_triangle().is_err());
+        let last = truncated.meshlets.last().unwrap();
+        assert!(last
+            .indices(truncated.index_format, &truncated.meshlet_data)
+            .is_err());
+    }
+
+    #[test]
+    fn disconnected_strip_triangles() {
+        let triangles = [0, 1, 2, 3, 4, 5, 2, 1, 6];
+        let packed = IndexFormat::Strip.pack(&triangles);
+
+        assert_eq!(
+            canonical(IndexFormat::Strip.unpack(&packed, 3).unwrap()),
+            canonical(triangles)
+        );
+        // Two restarts with three indices each and one continuation.
+        assert_eq!(packed.len(), 1 + 7usize.div_ceil(4));
+    }
+}
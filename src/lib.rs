@@ -0,0 +1,25 @@
+//! Geometry pipeline shared by the viewer and the `meshletize` tool. Nothing in here depends on
+//! Metal, so it also builds on machines without a GPU.
+
+pub mod animation;
+pub mod bounds;
+pub mod cluster_lod;
+pub mod geometry_pool;
+pub mod gltf_import;
+pub mod index_packing;
+pub mod lod;
+pub mod material;
+pub mod mesh;
+pub mod mesh_cache;
+pub mod mesh_repair;
+pub mod mesh_stats;
+pub mod mesh_validation;
+pub mod meshlet_order;
+pub mod morph;
+pub mod normals;
+pub mod picking;
+pub mod primitives;
+pub mod quantization;
+pub mod shader_emulation;
+pub mod skeleton;
+pub mod tangents;
@@ -0,0 +1,315 @@
+use anyhow::{ensure, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use meshopt::{SimplifyOptions, VertexDataAdapter};
+
+use crate::mesh::{append_meshlets, Mesh, Meshlet, MeshletBuildConfig, Submesh};
+
+/// Parameters for one level of the LOD chain. Each level is simplified from the previous one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LodLevel {
+    /// Fraction of the full resolution triangle count to aim for.
+    pub target_ratio: f32,
+    /// Maximum simplification error relative to the mesh extents.
+    pub target_error: f32,
+}
+
+/// LOD chain the viewer builds its meshes with, see [`viewer_build_config`].
+///
+/// [`viewer_build_config`]: crate::mesh::viewer_build_config
+pub const DEFAULT_LOD_LEVELS: [LodLevel; 3] = [
+    LodLevel {
+        target_ratio: 0.5,
+        target_error: 0.01,
+    },
+    LodLevel {
+        target_ratio: 0.25,
+        target_error: 0.02,
+    },
+    LodLevel {
+        target_ratio: 0.1,
+        target_error: 0.05,
+    },
+];
+
+impl LodLevel {
+    #[inline]
+    pub fn new(target_ratio: f32, target_error: f32) -> Self {
+        Self {
+            target_ratio,
+            target_error,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.target_ratio > 0.0 && self.target_ratio <= 1.0,
+            "target_ratio must be in (0.0, 1.0], got {}",
+            self.target_ratio
+        );
+        ensure!(
+            self.target_error >= 0.0,
+            "target_error must not be negative, got {}",
+            self.target_error
+        );
+
+        Ok(())
+    }
+}
+
+/// A level of detail, made of the submeshes `submesh_offset..submesh_offset + submesh_count`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct Lod {
+    /// Geometric error in mesh units, accumulated over all previous levels.
+    pub error: f32,
+    pub submesh_offset: u32,
+    pub submesh_count: u32,
+}
+
+unsafe impl Zeroable for Lod {}
+unsafe impl Pod for Lod {}
+
+impl Lod {
+    #[inline]
+    pub fn new(error: f32, submesh_offset: u32, submesh_count: u32) -> Self {
+        Self {
+            error,
+            submesh_offset,
+            submesh_count,
+        }
+    }
+}
+
+/// Simplifies the full resolution submeshes level by level and appends the meshletized result.
+/// The chain ends early once a level no longer removes any triangles.
+pub(crate) fn build_lod_chain(
+    submesh_indices: Vec<(u32, Vec<u32>)>,
+    vertex_data_adapter: &VertexDataAdapter,
+    config: &MeshletBuildConfig,
+    meshlets: &mut Vec<Meshlet>,
+    meshlet_data: &mut Vec<u32>,
+    submeshes: &mut Vec<Submesh>,
+    lods: &mut Vec<Lod>,
+) {
+    // Simplifying materials separately must not open cracks between them.
+    let options = if submesh_indices.len() > 1 {
+        SimplifyOptions::LockBorder
+    } else {
+        SimplifyOptions::None
+    };
+
+    let scale = meshopt::simplify_scale(vertex_data_adapter);
+    let full_index_counts: Vec<usize> = submesh_indices
+        .iter()
+        .map(|(_, indices)| indices.len())
+        .collect();
+
+    let mut previous = submesh_indices;
+    let mut error = 0.0;
+
+    for level in &config.lods {
+        let mut level_error = 0.0f32;
+
+        let simplified: Vec<(u32, Vec<u32>)> = previous
+            .iter()
+            .zip(&full_index_counts)
+            .map(|((material, indices), full_index_count)| {
+                let target_count = (*full_index_count as f32 * level.target_ratio) as usize / 3 * 3;
+
+                let mut result_error = 0.0;
+                let mut simplified = meshopt::simplify(
+                    indices,
+                    vertex_data_adapter,
+                    target_count,
+                    level.target_error,
+                    options,
+                    Some(&mut result_error),
+                );
+                meshopt::optimize_vertex_cache_in_place(
+                    &mut simplified,
+                    vertex_data_adapter.vertex_count,
+                );
+
+                level_error = level_error.max(result_error);
+                (*material, simplified)
+            })
+            .collect();
+
+        let index_count = |submeshes: &[(u32, Vec<u32>)]| -> usize {
+            submeshes.iter().map(|(_, indices)| indices.len()).sum()
+        };
+        if index_count(&simplified) >= index_count(&previous) {
+            break;
+        }
+
+        error += level_error * scale;
+
+        let submesh_offset = submeshes.len();
+        for (material, indices) in &simplified {
+            if indices.is_empty() {
+                continue;
+            }
+
+            let meshlet_offset = meshlets.len();
+            append_meshlets(
+                &meshopt::build_meshlets(
+                    indices,
+                    vertex_data_adapter,
+                    config.max_vertices,
+                    config.max_triangles,
+                    config.cone_weight,
+                ),
+                vertex_data_adapter,
+                config.index_format,
+                meshlets,
+                meshlet_data,
+            );
+
+            submeshes.push(Submesh::new(
+                *material,
+                meshlet_offset as _,
+                (meshlets.len() - meshlet_offset) as _,
+            ));
+        }
+
+        lods.push(Lod::new(
+            error,
+            submesh_offset as _,
+            (submeshes.len() - submesh_offset) as _,
+        ));
+        previous = simplified;
+    }
+}
+
+/// Size in pixels of a geometric `error` at `distance` in front of the camera, on a viewport that
+/// is `viewport_height` pixels high.
+pub fn projected_error(error: f32, projection: &Mat4, viewport_height: f32, distance: f32) -> f32 {
+    // The y scale of a perspective projection is `1 / tan(fov_y / 2)`.
+    error * projection.y_axis.y * viewport_height * 0.5 / distance.max(f32::EPSILON)
+}
+
+/// Picks the coarsest LOD whose error, projected at `distance` in front of the camera, stays
+/// below `max_pixel_error` pixels on a viewport that is `viewport_height` pixels high.
+pub fn select_lod(
+    lods: &[Lod],
+    projection: &Mat4,
+    viewport_height: f32,
+    distance: f32,
+    max_pixel_error: f32,
+) -> usize {
+    lods.iter()
+        .rposition(|lod| {
+            projected_error(lod.error, projection, viewport_height, distance) <= max_pixel_error
+        })
+        .unwrap_or(0)
+}
+
+impl Mesh {
+    pub fn select_lod(
+        &self,
+        projection: &Mat4,
+        viewport_height: f32,
+        distance: f32,
+        max_pixel_error: f32,
+    ) -> usize {
+        select_lod(
+            &self.lods,
+            projection,
+            viewport_height,
+            distance,
+            max_pixel_error,
+        )
+    }
+
+    pub fn lod_submeshes(&self, lod: usize) -> &[Submesh] {
+        let lod = &self.lods[lod];
+        &self.submeshes
+            [lod.submesh_offset as usize..(lod.submesh_offset + lod.submesh_count) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec2, Vec3};
+
+    use crate::{
+        lod::{select_lod, Lod, LodLevel},
+        mesh::{Mesh, MeshletBuildConfig, Vertex},
+    };
+
+    fn grid(size: usize) -> Vec<Vertex> {
+        let vertex = |x: usize, y: usize| {
+            let position = Vec3::new(x as f32, y as f32, ((x * y) as f32 * 0.01).sin());
+            Vertex::new(position, Vec2::ZERO, Vec3::Z)
+        };
+
+        let mut vertices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                vertices.extend([
+                    vertex(x, y),
+                    vertex(x + 1, y),
+                    vertex(x + 1, y + 1),
+                    vertex(x, y),
+                    vertex(x + 1, y + 1),
+                    vertex(x, y + 1),
+                ]);
+            }
+        }
+        vertices
+    }
+
+    fn triangle_count(mesh: &Mesh, lod: usize) -> u32 {
+        mesh.lod_submeshes(lod)
+            .iter()
+            .flat_map(|submesh| {
+                let offset = submesh.meshlet_offset as usize;
+                &mesh.meshlets[offset..offset + submesh.meshlet_count as usize]
+            })
+            .map(|meshlet| meshlet.triangle_count)
+            .sum()
+    }
+
+    #[test]
+    fn lod_chain_reduces_triangles() {
+        let config = MeshletBuildConfig {
+            lods: vec![LodLevel::new(0.5, 0.1), LodLevel::new(0.1, 0.5)],
+            ..Default::default()
+        };
+        let mesh = Mesh::from_vertices(&grid(32), None, &config).unwrap();
+
+        assert!(mesh.lods.len() > 1);
+        assert_eq!(mesh.lods[0].error, 0.0);
+        assert_eq!(triangle_count(&mesh, 0), 32 * 32 * 2);
+
+        for lod in 1..mesh.lods.len() {
+            assert!(triangle_count(&mesh, lod) < triangle_count(&mesh, lod - 1));
+            assert!(mesh.lods[lod].error >= mesh.lods[lod - 1].error);
+        }
+    }
+
+    #[test]
+    fn invalid_lod_levels() {
+        assert!(LodLevel::new(0.0, 0.1).validate().is_err());
+        assert!(LodLevel::new(1.5, 0.1).validate().is_err());
+        assert!(LodLevel::new(0.5, -1.0).validate().is_err());
+        assert!(LodLevel::new(0.5, 0.0).validate().is_ok());
+    }
+
+    #[test]
+    fn lod_selection() {
+        let lods = [
+            Lod::new(0.0, 0, 1),
+            Lod::new(0.01, 1, 1),
+            Lod::new(0.1, 2, 1),
+        ];
+        let projection = Mat4::perspective_lh(90.0f32.to_radians(), 16.0 / 9.0, 0.1, 1000.0);
+
+        // At a distance of 1 with a 90 degree field of view one unit covers half the viewport.
+        assert_eq!(select_lod(&lods, &projection, 1000.0, 1.0, 1.0), 0);
+        assert_eq!(select_lod(&lods, &projection, 1000.0, 10.0, 1.0), 1);
+        assert_eq!(select_lod(&lods, &projection, 1000.0, 100.0, 1.0), 2);
+        assert_eq!(select_lod(&lods, &projection, 1000.0, 0.0, 1.0), 0);
+    }
+}
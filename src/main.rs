@@ -1,12 +1,26 @@
 mod free_cam;
-mod mesh;
+mod geometry_buffers;
 mod shader_compiler;
 mod texture;
 
-use std::{mem, ptr::NonNull};
+use std::{
+    mem,
+    path::{Path, PathBuf},
+    ptr::NonNull,
+};
 
-use dolly::glam::{Mat4, Vec3};
+use dolly::glam::{Mat4, Vec2, Vec3, Vec4};
 use glam::{EulerRot, Quat};
+use metal_3_example::{
+    geometry_pool::GeometryPool,
+    lod::select_lod,
+    material::Material,
+    mesh::{viewer_build_config, viewer_import_options, Mesh},
+    mesh_stats::MeshStats,
+    picking::{pick, MeshletBvh, Ray},
+    quantization::VertexFormat,
+    shader_emulation::THREAD_GROUP_SIZE,
+};
 use objc2::{
     ffi::NSUInteger,
     rc::{autoreleasepool, Retained},
@@ -25,6 +39,7 @@ use objc2_quartz_core::{CAMetalDrawable, CAMetalLayer};
 use sdl3::{
     event::{Event, WindowEvent},
     keyboard::Keycode,
+    mouse::MouseButton,
     sys::{
         metal::{SDL_Metal_CreateView, SDL_Metal_DestroyView, SDL_Metal_GetLayer},
         mouse::{SDL_HideCursor, SDL_SetWindowRelativeMouseMode, SDL_ShowCursor},
@@ -34,16 +49,25 @@ use sdl3::{
 
 use crate::{
     free_cam::FreeCam,
-    mesh::MeshBuffers,
+    geometry_buffers::GeometryBuffers,
     shader_compiler::{compile, DescriptorTableEntry, ShaderKind},
     texture::ModelTexture,
 };
 
+/// Largest on screen error in pixels that is accepted when picking a LOD.
+const MAX_PIXEL_ERROR: f32 = 1.0;
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 struct UniformData {
     view_projection_matrix: Mat4,
     render_type: u32,
+    meshlet_offset: u32,
+    // Offset of the joints of the mesh into the joint palette, only read with SKINNING.
+    joint_offset: u32,
+    // Vec4 instead of Vec3 to match the 16 byte alignment of the HLSL cbuffer.
+    position_min: Vec4,
+    position_extent: Vec4,
 }
 
 fn prepare_render_pass_descriptor(
@@ -64,6 +88,15 @@ fn prepare_render_pass_descriptor(
     }
 }
 
+/// Falls back to a png named like the mesh if the material has no diffuse texture on disk.
+fn diffuse_texture_path(material: &Material, mesh_path: impl AsRef<Path>) -> PathBuf {
+    material
+        .diffuse_texture
+        .clone()
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| mesh_path.as_ref().with_extension("png"))
+}
+
 fn main() {
     autoreleasepool(|_| {
         unsafe {
@@ -103,17 +136,24 @@ fn main() {
             layer.setPresentsWithTransaction(false);
             layer.setDrawableSize(CGSize::new(window.size().0 as _, window.size().1 as _));
 
+            let meshlet_build_config = viewer_build_config();
+            let vertex_format = VertexFormat::default();
+            let mut shader_defines = meshlet_build_config.shader_defines();
+            shader_defines.extend(vertex_format.shader_defines(false).unwrap());
+
             let (_, mesh) = compile(
                 &device,
                 "shaders/geometry.hlsl",
                 "geometry_mesh",
                 ShaderKind::Mesh,
+                &shader_defines,
             );
             let (_, frag) = compile(
                 &device,
                 "shaders/geometry.hlsl",
                 "geometry_pixel",
                 ShaderKind::Fragment,
+                &shader_defines,
             );
 
             let pipeline_state_desc = MTLMeshRenderPipelineDescriptor::new();
@@ -166,14 +206,83 @@ fn main() {
                 view_projection_matrix: camera
                     .vp_matrix(window.size().0 as f32 / window.size().1 as f32),
                 render_type: 0,
+                meshlet_offset: 0,
+                joint_offset: 0,
+                position_min: Vec4::ZERO,
+                position_extent: Vec4::ONE,
             };
 
-            let mesh_buffers = unsafe { MeshBuffers::new(&device, "shepherd.obj") }.unwrap();
+            let import_options = viewer_import_options();
+
+            let mut geometry_pool = GeometryPool::new(meshlet_build_config.index_format);
+
             //TODO: we dont want to hardcode this in the future
-            let mesh_buffers2 = unsafe { MeshBuffers::new(&device, "angel.obj") }.unwrap();
+            let model_paths = ["shepherd.obj", "angel.obj"];
+            let models = model_paths.map(|path| {
+                let (mesh, report) =
+                    Mesh::load(path, &import_options, &meshlet_build_config).unwrap();
+                println!("{path}: {}", report.cache);
+                if let Some(log) = report.import_log.filter(|log| !log.is_clean()) {
+                    println!("Repaired {path}: {log}");
+                }
+                println!(
+                    "{path}:\n{}",
+                    MeshStats::new(&mesh, &meshlet_build_config).unwrap()
+                );
+                for (index_format, bytes) in mesh.index_bytes_per_triangle().unwrap() {
+                    println!(
+                        "{path}: {:.2} index bytes per triangle as {}",
+                        bytes,
+                        index_format.name()
+                    );
+                }
+
+                let textures: Vec<ModelTexture> = mesh
+                    .materials
+                    .iter()
+                    .map(|material| {
+                        ModelTexture::new(&device, diffuse_texture_path(material, path))
+                    })
+                    .collect();
+
+                (
+                    geometry_pool.add(&mesh).unwrap(),
+                    textures,
+                    mesh.bounds(),
+                    MeshletBvh::new(&mesh, 0).unwrap(),
+                )
+            });
+
+            // Every model is scaled to fit a unit sphere and placed next to the previous one. The
+            // rotations are scene placement that turns the angel towards the camera, not a fix of
+            // its coordinate system, so they stay out of the import options and the caches.
+            let model_rotations = [
+                Quat::IDENTITY,
+                Quat::from_euler(EulerRot::XYZ, 0., 90.0f32.to_radians(), 0.),
+            ];
+            let model_matrices: Vec<Mat4> = models
+                .iter()
+                .zip(model_rotations)
+                .enumerate()
+                .map(|(i, ((_, _, bounds, _), rotation))| {
+                    Mat4::from_rotation_translation(rotation, Vec3::new(2.0 * i as f32, 0., 0.))
+                        * Mat4::from_scale(Vec3::splat(1.0 / bounds.sphere.radius))
+                        * Mat4::from_translation(-bounds.sphere.center)
+                })
+                .collect();
+            let scene_sphere = models
+                .iter()
+                .zip(&model_matrices)
+                .map(|((_, _, bounds, _), model_matrix)| bounds.sphere.transform(model_matrix))
+                .reduce(|a, b| a.union(&b))
+                .unwrap();
+            camera.frame(
+                &scene_sphere,
+                window.size().0 as f32 / window.size().1 as f32,
+            );
 
-            let texture = ModelTexture::new(&device, "shepherd.png");
-            let texture2 = ModelTexture::new(&device, "angel.png");
+            let geometry_buffers =
+                GeometryBuffers::from_pool(&device, &geometry_pool, vertex_format);
 
             while running {
                 for event in event_pump.poll_iter() {
@@ -197,6 +306,11 @@ fn main() {
                                     uniform_data.render_type = 0;
                                 } else if keycode == Keycode::_2 {
                                     uniform_data.render_type = 1;
+                                } else if keycode == Keycode::F {
+                                    camera.frame(
+                                        &scene_sphere,
+                                        window.size().0 as f32 / window.size().1 as f32,
+                                    );
                                 }
 
                                 camera.key_event(true, keycode);
@@ -210,6 +324,33 @@ fn main() {
                         Event::MouseMotion { xrel, yrel, .. } => {
                             camera.mouse_movement((xrel, yrel));
                         }
+                        Event::MouseButtonDown {
+                            mouse_btn: MouseButton::Left,
+                            ..
+                        } => {
+                            // The cursor is grabbed, so picking aims at the center of the window.
+                            let viewport_size =
+                                Vec2::new(window.size().0 as f32, window.size().1 as f32);
+                            let ray = Ray::from_screen(
+                                viewport_size * 0.5,
+                                viewport_size,
+                                &camera.vp_matrix(viewport_size.x / viewport_size.y),
+                            );
+                            let meshes = models
+                                .iter()
+                                .zip(model_matrices.iter().copied())
+                                .map(|((_, _, _, bvh), model_matrix)| (bvh, model_matrix));
+
+                            match pick(&ray, meshes) {
+                                Some((model, hit)) => {
+                                    println!(
+                                        "Picked {} meshlet {} triangle {} at distance {:.3}",
+                                        model_paths[model], hit.meshlet, hit.triangle, hit.hit.t
+                                    )
+                                }
+                                None => println!("Picked nothing"),
+                            }
+                        }
                         _ => {}
                     }
                 }
@@ -218,17 +359,9 @@ fn main() {
 
                 let delta_time = 1. / 60.; //TODO:
 
-                uniform_data.view_projection_matrix =
-                    camera.vp_matrix(window.size().0 as f32 / window.size().1 as f32);
-
-                let mut uniform_data2 = uniform_data;
-                uniform_data2.view_projection_matrix *= Mat4::from_rotation_translation(
-                    Quat::from_euler(EulerRot::XYZ, 0., 90.0f32.to_radians(), 0.),
-                    Vec3::new(0., 0., 0.5),
-                );
-
-                uniform_data.view_projection_matrix *= Mat4::from_scale(Vec3::new(2., 2., 2.))
-                    * Mat4::from_translation(Vec3::new(-0.1, -0.2, -0.1));
+                let aspect = window.size().0 as f32 / window.size().1 as f32;
+                uniform_data.view_projection_matrix = camera.vp_matrix(aspect);
+                let projection_matrix = camera.projection_matrix(aspect);
 
                 camera.update(delta_time);
 
@@ -254,166 +387,122 @@ fn main() {
                     .renderCommandEncoderWithDescriptor(&render_pass_descriptor)
                     .unwrap();
 
-                let uniform_data_buffer = device
-                    .newBufferWithBytes_length_options(
-                        NonNull::new(&mut uniform_data as *mut _ as *mut _).unwrap(),
-                        mem::size_of::<UniformData>() as _,
-                        MTLResourceOptions::StorageModeShared,
-                    )
-                    .unwrap();
-
-                let uniform_data_buffer2 = device
-                    .newBufferWithBytes_length_options(
-                        NonNull::new(&mut uniform_data2 as *mut _ as *mut _).unwrap(),
-                        mem::size_of::<UniformData>() as _,
-                        MTLResourceOptions::StorageModeShared,
-                    )
-                    .unwrap();
-
                 let sampler_desc = MTLSamplerDescriptor::new();
 
                 let sampler = device.newSamplerStateWithDescriptor(&sampler_desc).unwrap();
 
-                let mut mesh_arguments = [
-                    DescriptorTableEntry::buffer(&mesh_buffers.vertex_buffer, 0),
-                    DescriptorTableEntry::buffer(&mesh_buffers.meshlet_buffer, 0),
-                    DescriptorTableEntry::buffer(&mesh_buffers.meshlet_data_buffer, 0),
-                    DescriptorTableEntry::buffer(&uniform_data_buffer, 0),
-                ];
-
-                let mut frag_arguments = [
-                    DescriptorTableEntry::texture(&texture.texture, 0., 0),
-                    DescriptorTableEntry::buffer(&uniform_data_buffer, 0),
-                    DescriptorTableEntry::sampler(&sampler, 0.),
-                ];
-
-                encoder.setMeshBytes_length_atIndex(
-                    NonNull::new(mesh_arguments.as_mut_ptr().cast()).unwrap(),
-                    mem::size_of::<DescriptorTableEntry>() * 4,
-                    2,
-                );
-                encoder.setFragmentBytes_length_atIndex(
-                    NonNull::new(frag_arguments.as_mut_ptr().cast()).unwrap(),
-                    mem::size_of::<DescriptorTableEntry>() * 3,
-                    2,
-                );
-
-                encoder.useResource_usage_stages(
-                    mesh_buffers.vertex_buffer.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Mesh,
-                );
-                encoder.useResource_usage_stages(
-                    mesh_buffers.meshlet_buffer.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Mesh,
-                );
-                encoder.useResource_usage_stages(
-                    mesh_buffers.meshlet_data_buffer.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Mesh,
-                );
-                encoder.useResource_usage_stages(
-                    uniform_data_buffer.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Mesh | MTLRenderStages::Fragment,
-                );
-                encoder.useResource_usage_stages(
-                    texture.texture.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Fragment,
-                );
-
                 encoder.setRenderPipelineState(&pipeline_state);
                 encoder.setDepthStencilState(Some(&depth_stencil_state));
 
-                encoder.drawMeshThreadgroups_threadsPerObjectThreadgroup_threadsPerMeshThreadgroup(
-                    MTLSize {
-                        width: ((mesh_buffers.num_meshlets * 32 + 31) / 32) as NSUInteger,
-                        height: 1,
-                        depth: 1,
-                    },
-                    MTLSize {
-                        width: 1,
-                        height: 1,
-                        depth: 1,
-                    },
-                    MTLSize {
-                        width: 32,
-                        height: 1,
-                        depth: 1,
-                    },
-                );
-
-                //Render second hardcoded mesh xDD
-
-                let mut mesh_arguments = [
-                    DescriptorTableEntry::buffer(&mesh_buffers2.vertex_buffer, 0),
-                    DescriptorTableEntry::buffer(&mesh_buffers2.meshlet_buffer, 0),
-                    DescriptorTableEntry::buffer(&mesh_buffers2.meshlet_data_buffer, 0),
-                    DescriptorTableEntry::buffer(&uniform_data_buffer2, 0),
-                ];
-
-                let mut frag_arguments = [
-                    DescriptorTableEntry::texture(&texture2.texture, 0., 0),
-                    DescriptorTableEntry::buffer(&uniform_data_buffer2, 0),
-                    DescriptorTableEntry::sampler(&sampler, 0.),
-                ];
-
-                encoder.setMeshBytes_length_atIndex(
-                    NonNull::new(mesh_arguments.as_mut_ptr().cast()).unwrap(),
-                    mem::size_of::<DescriptorTableEntry>() * 4,
-                    2,
-                );
-                encoder.setFragmentBytes_length_atIndex(
-                    NonNull::new(frag_arguments.as_mut_ptr().cast()).unwrap(),
-                    mem::size_of::<DescriptorTableEntry>() * 3,
-                    2,
-                );
-
-                encoder.drawMeshThreadgroups_threadsPerObjectThreadgroup_threadsPerMeshThreadgroup(
-                    MTLSize {
-                        width: ((mesh_buffers2.num_meshlets * 32 + 31) / 32) as NSUInteger,
-                        height: 1,
-                        depth: 1,
-                    },
-                    MTLSize {
-                        width: 1,
-                        height: 1,
-                        depth: 1,
-                    },
-                    MTLSize {
-                        width: 32,
-                        height: 1,
-                        depth: 1,
-                    },
-                );
+                for buffer in [
+                    &geometry_buffers.vertex_buffer,
+                    &geometry_buffers.meshlet_buffer,
+                    &geometry_buffers.meshlet_data_buffer,
+                ] {
+                    encoder.useResource_usage_stages(
+                        buffer.as_ref(),
+                        MTLResourceUsage::Read,
+                        MTLRenderStages::Mesh,
+                    );
+                }
 
-                encoder.useResource_usage_stages(
-                    mesh_buffers2.vertex_buffer.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Mesh,
-                );
-                encoder.useResource_usage_stages(
-                    mesh_buffers2.meshlet_buffer.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Mesh,
-                );
-                encoder.useResource_usage_stages(
-                    mesh_buffers2.meshlet_data_buffer.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Mesh,
-                );
-                encoder.useResource_usage_stages(
-                    uniform_data_buffer2.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Mesh | MTLRenderStages::Fragment,
-                );
-                encoder.useResource_usage_stages(
-                    texture2.texture.as_ref(),
-                    MTLResourceUsage::Read,
-                    MTLRenderStages::Fragment,
-                );
+                for ((handle, textures, bounds, _), model_matrix) in
+                    models.iter().zip(model_matrices.iter().copied())
+                {
+                    let allocation = geometry_pool.get(*handle).unwrap();
+
+                    // LOD errors are in mesh units, which the model matrix scales by
+                    // `1 / radius`.
+                    let distance = model_matrix
+                        .transform_point3(bounds.sphere.center)
+                        .distance(camera.position())
+                        * bounds.sphere.radius;
+                    let lod = &allocation.lods[select_lod(
+                        &allocation.lods,
+                        &projection_matrix,
+                        window.size().1 as f32,
+                        distance,
+                        MAX_PIXEL_ERROR,
+                    )];
+
+                    for submesh in &allocation.submeshes[lod.submesh_offset as usize
+                        ..(lod.submesh_offset + lod.submesh_count) as usize]
+                    {
+                        let texture = &textures[submesh.material as usize];
+
+                        let mut submesh_uniform_data = UniformData {
+                            view_projection_matrix: uniform_data.view_projection_matrix
+                                * model_matrix,
+                            meshlet_offset: submesh.meshlet_offset,
+                            position_min: allocation.quantization.min.extend(0.0),
+                            position_extent: allocation.quantization.extent.extend(0.0),
+                            ..uniform_data
+                        };
+
+                        let uniform_data_buffer = device
+                            .newBufferWithBytes_length_options(
+                                NonNull::new(&mut submesh_uniform_data as *mut _ as *mut _)
+                                    .unwrap(),
+                                mem::size_of::<UniformData>() as _,
+                                MTLResourceOptions::StorageModeShared,
+                            )
+                            .unwrap();
+
+                        let mut mesh_arguments = [
+                            DescriptorTableEntry::buffer(&geometry_buffers.vertex_buffer, 0),
+                            DescriptorTableEntry::buffer(&geometry_buffers.meshlet_buffer, 0),
+                            DescriptorTableEntry::buffer(&geometry_buffers.meshlet_data_buffer, 0),
+                            DescriptorTableEntry::buffer(&uniform_data_buffer, 0),
+                        ];
+
+                        let mut frag_arguments = [
+                            DescriptorTableEntry::texture(&texture.texture, 0., 0),
+                            DescriptorTableEntry::buffer(&uniform_data_buffer, 0),
+                            DescriptorTableEntry::sampler(&sampler, 0.),
+                        ];
+
+                        encoder.setMeshBytes_length_atIndex(
+                            NonNull::new(mesh_arguments.as_mut_ptr().cast()).unwrap(),
+                            mem::size_of::<DescriptorTableEntry>() * 4,
+                            2,
+                        );
+                        encoder.setFragmentBytes_length_atIndex(
+                            NonNull::new(frag_arguments.as_mut_ptr().cast()).unwrap(),
+                            mem::size_of::<DescriptorTableEntry>() * 3,
+                            2,
+                        );
+
+                        encoder.useResource_usage_stages(
+                            uniform_data_buffer.as_ref(),
+                            MTLResourceUsage::Read,
+                            MTLRenderStages::Mesh | MTLRenderStages::Fragment,
+                        );
+                        encoder.useResource_usage_stages(
+                            texture.texture.as_ref(),
+                            MTLResourceUsage::Read,
+                            MTLRenderStages::Fragment,
+                        );
+
+                        encoder
+                            .drawMeshThreadgroups_threadsPerObjectThreadgroup_threadsPerMeshThreadgroup(
+                                MTLSize {
+                                    width: submesh.meshlet_count as NSUInteger,
+                                    height: 1,
+                                    depth: 1,
+                                },
+                                MTLSize {
+                                    width: 1,
+                                    height: 1,
+                                    depth: 1,
+                                },
+                                MTLSize {
+                                    width: THREAD_GROUP_SIZE as NSUInteger,
+                                    height: 1,
+                                    depth: 1,
+                                },
+                            );
+                    }
+                }
 
                 encoder.endEncoding();
 
@@ -0,0 +1,298 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use glam::Vec3;
+
+pub const DEFAULT_MATERIAL_NAME: &str = "default";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub emissive: Vec3,
+    pub shininess: f32,
+    pub optical_density: f32,
+    pub dissolve: f32,
+    pub illumination_model: u32,
+    pub ambient_texture: Option<PathBuf>,
+    pub diffuse_texture: Option<PathBuf>,
+    pub specular_texture: Option<PathBuf>,
+    pub emissive_texture: Option<PathBuf>,
+    pub shininess_texture: Option<PathBuf>,
+    pub dissolve_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::new(DEFAULT_MATERIAL_NAME)
+    }
+}
+
+impl Material {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ambient: Vec3::ZERO,
+            diffuse: Vec3::ONE,
+            specular: Vec3::ZERO,
+            emissive: Vec3::ZERO,
+            shininess: 0.0,
+            optical_density: 1.0,
+            dissolve: 1.0,
+            illumination_model: 2,
+            ambient_texture: None,
+            diffuse_texture: None,
+            specular_texture: None,
+            emissive_texture: None,
+            shininess_texture: None,
+            dissolve_texture: None,
+            normal_texture: None,
+        }
+    }
+}
+
+fn parse_floats<const N: usize>(values: &[&str], line: usize) -> Result<[f32; N]> {
+    if values.len() < N {
+        bail!("Line {line}: expected {N} values, got {}", values.len());
+    }
+
+    let mut result = [0.0; N];
+    for (value, string) in result.iter_mut().zip(values) {
+        *value = string
+            .parse()
+            .with_context(|| format!("Line {line}: invalid number {string:?}"))?;
+    }
+
+    Ok(result)
+}
+
+/// Parses a Wavefront material library. Texture paths are resolved relative to `base_dir`.
+pub fn parse_mtl(source: &str, base_dir: &Path) -> Result<Vec<Material>> {
+    let mut materials: Vec<Material> = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((keyword, values)) = tokens.split_first() else {
+            continue;
+        };
+
+        if keyword.starts_with('#') {
+            continue;
+        }
+
+        if *keyword == "newmtl" {
+            materials.push(Material::new(values.join(" ")));
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            bail!("Line {line_number}: {keyword} before the first newmtl");
+        };
+
+        // Texture options like `-s 1 1 1` precede the file name, so it is always the last token.
+        let texture = || -> Result<Option<PathBuf>> {
+            match values.last() {
+                Some(file) => Ok(Some(base_dir.join(file))),
+                None => bail!("Line {line_number}: {keyword} without a file name"),
+            }
+        };
+
+        match *keyword {
+            "Ka" => material.ambient = Vec3::from(parse_floats(values, line_number)?),
+            "Kd" => material.diffuse = Vec3::from(parse_floats(values, line_number)?),
+            "Ks" => material.specular = Vec3::from(parse_floats(values, line_number)?),
+            "Ke" => material.emissive = Vec3::from(parse_floats(values, line_number)?),
+            "Ns" => [material.shininess] = parse_floats(values, line_number)?,
+            "Ni" => [material.optical_density] = parse_floats(values, line_number)?,
+            "d" => [material.dissolve] = parse_floats(values, line_number)?,
+            "Tr" => {
+                let [transparency] = parse_floats(values, line_number)?;
+                material.dissolve = 1.0 - transparency;
+            }
+            "illum" => {
+                let [illumination_model] = parse_floats(values, line_number)?;
+                material.illumination_model = illumination_model as u32;
+            }
+            "map_Ka" => material.ambient_texture = texture()?,
+            "map_Kd" => material.diffuse_texture = texture()?,
+            "map_Ks" => material.specular_texture = texture()?,
+            "map_Ke" => material.emissive_texture = texture()?,
+            "map_Ns" => material.shininess_texture = texture()?,
+            "map_d" => material.dissolve_texture = texture()?,
+            "map_Bump" | "map_bump" | "bump" | "norm" => material.normal_texture = texture()?,
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<Vec<Material>> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+
+    parse_mtl(&source, path.parent().unwrap_or(Path::new("")))
+        .with_context(|| format!("Failed to parse {path:?}"))
+}
+
+/// Materials of an OBJ in the order of their first `usemtl`, and the index of the material used
+/// by every face.
+#[derive(Clone, Debug, Default)]
+pub struct ObjMaterials {
+    pub materials: Vec<Material>,
+    pub face_materials: Vec<u32>,
+}
+
+/// Scans an OBJ for `mtllib` and `usemtl` statements, which `fast_obj` does not expose. Faces
+/// without a material or referencing a material missing from the libraries get a default one.
+pub fn parse_obj_materials(source: &str, base_dir: &Path) -> Result<ObjMaterials> {
+    let mut library = Vec::new();
+    let mut materials: Vec<Material> = Vec::new();
+    let mut face_materials = Vec::new();
+    let mut current = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((keyword, values)) = tokens.split_first() else {
+            continue;
+        };
+
+        match *keyword {
+            "mtllib" => {
+                for file in values {
+                    let path = base_dir.join(file);
+                    match load_mtl(&path) {
+                        Ok(mut loaded) => library.append(&mut loaded),
+                        Err(e) => println!("Ignoring material library {path:?}: {e:#}"),
+                    }
+                }
+            }
+            "usemtl" => current = Some(values.join(" ")),
+            "f" => {
+                if values.len() != 3 {
+                    bail!(
+                        "Line {}: face with {} vertices, only triangles are supported",
+                        i + 1,
+                        values.len()
+                    );
+                }
+
+                let name = current.as_deref().unwrap_or(DEFAULT_MATERIAL_NAME);
+                let index = match materials.iter().position(|material| material.name == name) {
+                    Some(index) => index,
+                    None => {
+                        materials.push(
+                            library
+                                .iter()
+                                .find(|material| material.name == name)
+                                .cloned()
+                                .unwrap_or_else(|| Material::new(name)),
+                        );
+                        materials.len() - 1
+                    }
+                };
+
+                face_materials.push(index as u32);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ObjMaterials {
+        materials,
+        face_materials,
+    })
+}
+
+pub fn load_obj_materials(path: impl AsRef<Path>) -> Result<ObjMaterials> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+
+    parse_obj_materials(&source, path.parent().unwrap_or(Path::new("")))
+        .with_context(|| format!("Failed to parse {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use glam::Vec3;
+
+    use crate::material::{parse_mtl, parse_obj_materials, DEFAULT_MATERIAL_NAME};
+
+    const ANGEL_MTL: &str = include_str!("../angel.mtl");
+
+    #[test]
+    fn parse_angel_mtl() {
+        let materials = parse_mtl(ANGEL_MTL, Path::new("assets")).unwrap();
+
+        assert_eq!(materials.len(), 1);
+
+        let material = &materials[0];
+        assert_eq!(material.name, "Texture");
+        assert_eq!(material.shininess, 10.000005);
+        assert_eq!(material.ambient, Vec3::ONE);
+        assert_eq!(material.specular, Vec3::splat(0.5));
+        assert_eq!(material.emissive, Vec3::ZERO);
+        assert_eq!(material.optical_density, 1.45);
+        assert_eq!(material.dissolve, 1.0);
+        assert_eq!(material.illumination_model, 2);
+        assert_eq!(
+            material.diffuse_texture.as_deref(),
+            Some(Path::new("assets/baked_mesh_tex0.png"))
+        );
+        assert_eq!(material.normal_texture, None);
+    }
+
+    #[test]
+    fn texture_options_are_skipped() {
+        let materials = parse_mtl(
+            "newmtl a\nmap_Kd -s 2 2 1 -o 0.5 0 0 albedo.png\nbump -bm 0.5 normal.png\nTr 0.25\n",
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(
+            materials[0].diffuse_texture.as_deref(),
+            Some(Path::new("albedo.png"))
+        );
+        assert_eq!(
+            materials[0].normal_texture.as_deref(),
+            Some(Path::new("normal.png"))
+        );
+        assert_eq!(materials[0].dissolve, 0.75);
+    }
+
+    #[test]
+    fn invalid_mtl_is_an_error() {
+        assert!(parse_mtl("Kd 1 1 1\n", Path::new("")).is_err());
+        assert!(parse_mtl("newmtl a\nKd 1 x 1\n", Path::new("")).is_err());
+        assert!(parse_mtl("newmtl a\nmap_Kd\n", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn obj_face_materials() {
+        let dir = env::temp_dir();
+        fs::write(dir.join("obj_face_materials.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+
+        let obj = "mtllib obj_face_materials.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nusemtl \
+                   red\nf 1 2 3\nf 1 2 3\nusemtl missing\nf 1 2 3\nusemtl red\nf 1 2 3\n";
+        let obj_materials = parse_obj_materials(obj, &dir).unwrap();
+
+        let names: Vec<&str> = obj_materials
+            .materials
+            .iter()
+            .map(|material| material.name.as_str())
+            .collect();
+        assert_eq!(names, [DEFAULT_MATERIAL_NAME, "red", "missing"]);
+        assert_eq!(obj_materials.materials[1].diffuse, Vec3::X);
+        assert_eq!(obj_materials.face_materials, [0, 1, 1, 2, 1]);
+    }
+}
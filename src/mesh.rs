@@ -1,11 +1,21 @@
-use std::{mem, path::Path, ptr::NonNull};
+use std::{mem, ops::Range, path::Path};
 
-use anyhow::Result;
+use anyhow::{bail, ensure, Context, Result};
 use bytemuck::{Pod, Zeroable};
-use glam::{Vec2, Vec3};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 use meshopt::VertexDataAdapter;
-use objc2::{rc::Retained, runtime::ProtocolObject};
-use objc2_metal::{MTLBuffer, MTLDevice, MTLResourceOptions};
+
+use crate::{
+    index_packing::IndexFormat,
+    lod::{build_lod_chain, Lod, LodLevel, DEFAULT_LOD_LEVELS},
+    material::{load_obj_materials, Material},
+    mesh_repair::{repair_triangles, ImportLog, RepairedTriangles},
+    meshlet_order::MeshletOrder,
+    morph::{self, MorphTarget},
+    normals::generate_normals,
+    shader_emulation::THREAD_GROUP_SIZE,
+    tangents::generate_tangents,
+};
 
 #[derive(Copy, Clone, Debug, Default)]
 #[repr(C)]
@@ -13,6 +23,13 @@ pub struct Vertex {
     pub position: Vec3,
     pub tex_coord: Vec2,
     pub normal: Vec3,
+    /// Tangent with the bitangent sign in `w`, zero unless [`ImportOptions::generate_tangents`]
+    /// is set.
+    pub tangent: Vec4,
+    /// Joints of the skeleton influencing the vertex, see [`Skeleton`](crate::skeleton::Skeleton).
+    pub joints: [u16; 4],
+    /// Unorm16 weights of `joints`, all zero if the vertex is not skinned.
+    pub weights: [u16; 4],
 }
 
 unsafe impl Zeroable for Vertex {}
@@ -25,8 +42,16 @@ impl Vertex {
             position,
             tex_coord,
             normal,
+            tangent: Vec4::ZERO,
+            joints: [0; 4],
+            weights: [0; 4],
         }
     }
+
+    #[inline]
+    pub fn is_skinned(&self) -> bool {
+        self.weights != [0; 4]
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -35,6 +60,11 @@ pub struct Meshlet {
     pub data_offset: u32,
     pub vertex_count: u32,
     pub triangle_count: u32,
+    pub center: Vec3,
+    pub radius: f32,
+    pub cone_apex: Vec3,
+    pub cone_axis: [i8; 3],
+    pub cone_cutoff: i8,
 }
 
 unsafe impl Zeroable for Meshlet {}
@@ -47,177 +77,1068 @@ impl Meshlet {
             data_offset,
             vertex_count,
             triangle_count,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn with_bounds(self, bounds: &meshopt::Bounds) -> Self {
+        Self {
+            center: Vec3::from(bounds.center),
+            radius: bounds.radius,
+            cone_apex: Vec3::from(bounds.cone_apex),
+            cone_axis: bounds.cone_axis_s8,
+            cone_cutoff: bounds.cone_cutoff_s8,
+            ..self
         }
     }
+
+    #[inline]
+    pub fn cone_axis(&self) -> Vec3 {
+        Vec3::new(
+            self.cone_axis[0] as f32,
+            self.cone_axis[1] as f32,
+            self.cone_axis[2] as f32,
+        ) / 127.0
+    }
+
+    #[inline]
+    pub fn cone_cutoff(&self) -> f32 {
+        self.cone_cutoff as f32 / 127.0
+    }
+
+    /// Number of `u32`s the meshlet occupies in `meshlet_data`: its vertex indices followed by
+    /// the local triangle indices packed in `index_format`. Fails if the data is out of range.
+    pub fn data_len(&self, index_format: IndexFormat, meshlet_data: &[u32]) -> Result<usize> {
+        let packed_len = meshlet_data
+            .get(self.data_offset as usize + self.vertex_count as usize..)
+            .and_then(|packed| index_format.packed_len(packed, self.triangle_count))
+            .context("Truncated meshlet data")?;
+
+        Ok(self.vertex_count as usize + packed_len)
+    }
+
+    /// Unpacks the triangles of the meshlet into indices of the mesh vertices.
+    pub fn indices(&self, index_format: IndexFormat, meshlet_data: &[u32]) -> Result<Vec<u32>> {
+        let data_len = self.data_len(index_format, meshlet_data)?;
+        let (vertices, packed_triangles) = meshlet_data[self.data_offset as usize..][..data_len]
+            .split_at(self.vertex_count as usize);
+
+        index_format
+            .unpack(packed_triangles, self.triangle_count)?
+            .iter()
+            .map(|local_index| {
+                vertices
+                    .get(*local_index as usize)
+                    .copied()
+                    .with_context(|| format!("Local vertex {local_index} is out of range"))
+            })
+            .collect()
+    }
+
+    /// Returns true if every triangle of the meshlet faces away from `camera_pos`.
+    /// The quantized cone is conservative, so a visible meshlet is never reported as backfacing.
+    pub fn is_backfacing(&self, camera_pos: Vec3) -> bool {
+        let view = (self.cone_apex - camera_pos).normalize_or_zero();
+        view.dot(self.cone_axis()) >= self.cone_cutoff()
+    }
+
+    /// Returns true if the bounding sphere lies completely behind one of the `planes`.
+    /// Planes are stored as `(normal, distance)` with the normal pointing inwards, see
+    /// [`frustum_planes`].
+    pub fn is_outside_frustum(&self, planes: &[Vec4; 6]) -> bool {
+        planes
+            .iter()
+            .any(|plane| plane.truncate().dot(self.center) + plane.w < -self.radius)
+    }
 }
 
-const MAX_VERTICES: usize = 64;
-const MAX_TRIANGLES: usize = 124;
-const CONE_WEIGHT: f32 = 0.0;
+/// Extracts the normalized left, right, bottom, top, near and far planes from a left handed
+/// view projection matrix with a `[0, 1]` depth range, as produced by [`Mat4::perspective_lh`].
+pub fn frustum_planes(view_projection: &Mat4) -> [Vec4; 6] {
+    let row_0 = view_projection.row(0);
+    let row_1 = view_projection.row(1);
+    let row_2 = view_projection.row(2);
+    let row_3 = view_projection.row(3);
+
+    [
+        row_3 + row_0,
+        row_3 - row_0,
+        row_3 + row_1,
+        row_3 - row_1,
+        row_2,
+        row_3 - row_2,
+    ]
+    .map(|plane| plane / plane.truncate().length())
+}
+
+/// Local triangle indices are stored as bytes, which also is the limit of `meshopt`.
+pub const MAX_MESHLET_VERTICES: usize = 255;
+/// Maximum number of primitives a single mesh shader threadgroup may output.
+pub const MAX_MESHLET_TRIANGLES: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshletBuildConfig {
+    pub max_vertices: usize,
+    pub max_triangles: usize,
+    pub cone_weight: f32,
+    /// Simplified levels generated in addition to the full resolution mesh.
+    pub lods: Vec<LodLevel>,
+    pub index_format: IndexFormat,
+    pub meshlet_order: MeshletOrder,
+}
+
+impl Default for MeshletBuildConfig {
+    fn default() -> Self {
+        Self {
+            max_vertices: 64,
+            max_triangles: 124,
+            cone_weight: 0.25,
+            lods: Vec::new(),
+            index_format: IndexFormat::default(),
+            meshlet_order: MeshletOrder::default(),
+        }
+    }
+}
+
+impl MeshletBuildConfig {
+    pub fn new(max_vertices: usize, max_triangles: usize, cone_weight: f32) -> Result<Self> {
+        let config = Self {
+            max_vertices,
+            max_triangles,
+            cone_weight,
+            lods: Vec::new(),
+            index_format: IndexFormat::default(),
+            meshlet_order: MeshletOrder::default(),
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            (3..=MAX_MESHLET_VERTICES).contains(&self.max_vertices),
+            "max_vertices must be in 3..={MAX_MESHLET_VERTICES}, got {}",
+            self.max_vertices
+        );
+        ensure!(
+            (4..=MAX_MESHLET_TRIANGLES).contains(&self.max_triangles),
+            "max_triangles must be in 4..={MAX_MESHLET_TRIANGLES}, got {}",
+            self.max_triangles
+        );
+        ensure!(
+            self.max_triangles % 4 == 0,
+            "max_triangles must be a multiple of 4, got {}",
+            self.max_triangles
+        );
+        ensure!(
+            (0.0..=1.0).contains(&self.cone_weight),
+            "cone_weight must be in 0.0..=1.0, got {}",
+            self.cone_weight
+        );
+
+        for lod in &self.lods {
+            lod.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Defines that size the output arrays and thread group of `geometry_mesh` and select how it
+    /// unpacks triangles.
+    pub fn shader_defines(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("THREAD_GROUP_SIZE", THREAD_GROUP_SIZE.to_string()),
+            ("MAX_VERTICES", self.max_vertices.to_string()),
+            ("MAX_TRIANGLES", self.max_triangles.to_string()),
+            ("INDEX_FORMAT", self.index_format.id().to_string()),
+        ]
+    }
+}
+
+/// A range of meshlets that share a material.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Submesh {
+    pub material: u32,
+    pub meshlet_offset: u32,
+    pub meshlet_count: u32,
+}
+
+unsafe impl Zeroable for Submesh {}
+unsafe impl Pod for Submesh {}
+
+impl Submesh {
+    #[inline]
+    pub fn new(material: u32, meshlet_offset: u32, meshlet_count: u32) -> Self {
+        Self {
+            material,
+            meshlet_offset,
+            meshlet_count,
+        }
+    }
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub meshlets: Vec<Meshlet>,
     pub meshlet_data: Vec<u32>,
+    pub submeshes: Vec<Submesh>,
+    pub materials: Vec<Material>,
+    /// Always starts with the full resolution mesh, followed by the levels requested in
+    /// [`MeshletBuildConfig::lods`].
+    pub lods: Vec<Lod>,
+    pub index_format: IndexFormat,
+    /// Deltas are per vertex. The meshlet bounds hold for any weights between 0 and 1.
+    pub morph_targets: Vec<MorphTarget>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImportOptions {
+    /// Maximum angle in radians between faces that get smoothed together when normals are
+    /// generated for a mesh without `vn` entries.
+    pub crease_angle: f32,
+    pub default_tex_coord: Vec2,
+    /// Generates MikkTSpace tangents, which also keeps vertices with different tangents apart.
+    pub generate_tangents: bool,
+    /// Welds positions within `weld_tolerance` of each other and removes degenerate and duplicate
+    /// triangles before the vertices are built.
+    pub repair: bool,
+    /// In the units after `unit_scale` is applied.
+    pub weld_tolerance: f32,
+    /// Axis pointing up in the source file, which becomes +Y.
+    pub up_axis: UpAxis,
+    /// Mirrors Z to convert between right and left handed coordinates, and reverses the winding
+    /// so the triangles keep facing outwards.
+    pub flip_handedness: bool,
+    /// Multiplied with the positions, e.g. 0.01 for files in centimeters.
+    pub unit_scale: f32,
+    /// OBJ puts the texture origin at the bottom left, the shaders sample with it at the top left.
+    /// Ignored for glTF, which always puts it at the top left.
+    pub flip_tex_coord_v: bool,
+}
+
+/// Up axis of a model, the other two axes keep their orientation around it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z,
+}
+
+impl UpAxis {
+    pub const ALL: [Self; 2] = [Self::Y, Self::Z];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Y => "y",
+            Self::Z => "z",
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::Y => 0,
+            Self::Z => 1,
+        }
+    }
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            crease_angle: 60.0f32.to_radians(),
+            default_tex_coord: Vec2::ZERO,
+            generate_tangents: false,
+            repair: true,
+            weld_tolerance: 1e-5,
+            up_axis: UpAxis::default(),
+            flip_handedness: false,
+            unit_scale: 1.0,
+            flip_tex_coord_v: true,
+        }
+    }
+}
+
+impl ImportOptions {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.weld_tolerance.is_finite() && self.weld_tolerance >= 0.0,
+            "weld_tolerance must be finite and at least 0.0, got {}",
+            self.weld_tolerance
+        );
+        ensure!(
+            self.unit_scale.is_finite() && self.unit_scale > 0.0,
+            "unit_scale must be finite and greater than 0.0, got {}",
+            self.unit_scale
+        );
+
+        Ok(())
+    }
+
+    /// Converts a direction from the coordinate system of the source file into the one of the
+    /// mesh, without `unit_scale`.
+    pub fn convert_direction(&self, direction: Vec3) -> Vec3 {
+        // Rotating Z up to Y up keeps the handedness.
+        let direction = match self.up_axis {
+            UpAxis::Y => direction,
+            UpAxis::Z => Vec3::new(direction.x, direction.z, -direction.y),
+        };
+
+        if self.flip_handedness {
+            direction * Vec3::new(1.0, 1.0, -1.0)
+        } else {
+            direction
+        }
+    }
+
+    #[inline]
+    pub fn convert_position(&self, position: Vec3) -> Vec3 {
+        self.convert_direction(position) * self.unit_scale
+    }
+
+    #[inline]
+    pub fn convert_tex_coord(&self, tex_coord: Vec2) -> Vec2 {
+        if self.flip_tex_coord_v {
+            Vec2::new(tex_coord.x, 1.0 - tex_coord.y)
+        } else {
+            tex_coord
+        }
+    }
+}
+
+/// Import options the viewer loads its models with. `meshletize` defaults to them and to
+/// [`viewer_build_config`], so the caches it writes have the same key and are picked up by the
+/// viewer.
+pub fn viewer_import_options() -> ImportOptions {
+    ImportOptions::default()
+}
+
+/// Meshlet build config the viewer loads its models with.
+pub fn viewer_build_config() -> MeshletBuildConfig {
+    MeshletBuildConfig {
+        lods: DEFAULT_LOD_LEVELS.to_vec(),
+        ..Default::default()
+    }
 }
 
 impl Mesh {
-    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
-        let mesh = fast_obj::Mesh::new(path)?;
+    pub fn new(
+        path: impl AsRef<Path>,
+        options: &ImportOptions,
+        config: &MeshletBuildConfig,
+    ) -> Result<Self> {
+        Ok(Self::import(path, options, config)?.0)
+    }
 
-        let mut vertices = vec![Default::default(); mesh.indices().len()];
+    /// Like [`Mesh::new`], but also returns what the repair pass found, see
+    /// [`ImportOptions::repair`].
+    pub fn import(
+        path: impl AsRef<Path>,
+        options: &ImportOptions,
+        config: &MeshletBuildConfig,
+    ) -> Result<(Self, ImportLog)> {
+        options.validate()?;
+
+        let path = path.as_ref();
+        let mesh = fast_obj::Mesh::new(path)?;
+        let obj_materials = load_obj_materials(path)?;
 
-        let positions = mesh.positions();
-        let tex_coords = mesh.texcoords();
-        let normals = mesh.normals();
+        // fast_obj reserves index 0 of every attribute for a dummy entry, which is what faces
+        // without texture coordinates or normals point to.
+        let positions: Vec<Vec3> = mesh
+            .positions()
+            .chunks_exact(3)
+            .map(|position| options.convert_position(Vec3::from_slice(position)))
+            .collect();
+        let tex_coords: Vec<Vec2> = mesh
+            .texcoords()
+            .chunks_exact(2)
+            .map(|tex_coord| options.convert_tex_coord(Vec2::from_slice(tex_coord)))
+            .collect();
+        let normals: Vec<Vec3> = mesh
+            .normals()
+            .chunks_exact(3)
+            .map(|normal| options.convert_direction(Vec3::from_slice(normal)))
+            .collect();
         let indices = mesh.indices();
 
+        ensure!(
+            indices.len() % 3 == 0,
+            "{:?} is not triangulated, index count {} is not a multiple of 3",
+            path,
+            indices.len()
+        );
+
         for (i, index) in indices.iter().enumerate() {
-            let position_idx = 3 * index.p as usize;
-            let tex_coord_idx = 2 * index.t as usize;
-            let normal_idx = 3 * index.n as usize;
-
-            vertices[i] = Vertex::new(
-                Vec3::new(
-                    positions[position_idx],
-                    positions[position_idx + 1],
-                    positions[position_idx + 2],
-                ),
-                Vec2::new(tex_coords[tex_coord_idx], tex_coords[tex_coord_idx + 1]),
-                Vec3::new(
-                    normals[normal_idx],
-                    normals[normal_idx + 1],
-                    normals[normal_idx + 2],
-                ),
+            ensure!(
+                index.p != 0 && (index.p as usize) < positions.len(),
+                "Face {} of {:?} references position {} but there are only {}",
+                i / 3,
+                path,
+                index.p,
+                positions.len() - 1
+            );
+            ensure!(
+                (index.t as usize) < tex_coords.len(),
+                "Face {} of {:?} references texture coordinate {} but there are only {}",
+                i / 3,
+                path,
+                index.t,
+                tex_coords.len() - 1
+            );
+            ensure!(
+                (index.n as usize) < normals.len(),
+                "Face {} of {:?} references normal {} but there are only {}",
+                i / 3,
+                path,
+                index.n,
+                normals.len() - 1
             );
         }
 
-        let (vertex_count, remap) = meshopt::generate_vertex_remap(&vertices, None);
-        vertices.shrink_to(vertex_count);
+        let position_indices: Vec<u32> = indices.iter().map(|index| index.p).collect();
+        let repaired = if options.repair {
+            repair_triangles(&positions, &position_indices, options.weld_tolerance)
+        } else {
+            RepairedTriangles {
+                position_indices,
+                triangles: (0..indices.len() / 3).collect(),
+                log: ImportLog::default(),
+            }
+        };
+        // Corners of the kept triangles, welded positions are used from here on.
+        let corners: Vec<usize> = repaired
+            .triangles
+            .iter()
+            .flat_map(|triangle| {
+                if options.flip_handedness {
+                    [3 * triangle, 3 * triangle + 2, 3 * triangle + 1]
+                } else {
+                    [3 * triangle, 3 * triangle + 1, 3 * triangle + 2]
+                }
+            })
+            .collect();
+        let position_indices: Vec<u32> = corners
+            .iter()
+            .map(|corner| repaired.position_indices[*corner])
+            .collect();
 
-        let mut vertices = meshopt::remap_vertex_buffer(&vertices, vertex_count, &remap);
-        let mut indices = meshopt::remap_index_buffer(None, indices.len(), &remap);
+        let generated_normals = if indices.iter().any(|index| index.n == 0) {
+            generate_normals(&positions, &position_indices, options.crease_angle)
+        } else {
+            Vec::new()
+        };
 
-        meshopt::optimize_vertex_cache_in_place(&mut indices, vertices.len());
-        meshopt::optimize_overdraw_in_place(
-            &mut indices,
-            &VertexDataAdapter::new(bytemuck::cast_slice(&vertices), mem::size_of::<Vertex>(), 0)?,
-            1.01,
-        );
-        meshopt::optimize_vertex_fetch_in_place(&mut indices, &mut vertices);
-
-        let meshlets = meshopt::build_meshlets(
-            &indices,
-            &VertexDataAdapter::new(bytemuck::cast_slice(&vertices), mem::size_of::<Vertex>(), 0)?,
-            MAX_VERTICES,
-            MAX_TRIANGLES,
-            CONE_WEIGHT,
-        );
+        let mut vertices: Vec<Vertex> = corners
+            .iter()
+            .zip(&position_indices)
+            .enumerate()
+            .map(|(i, (corner, position_index))| {
+                let index = &indices[*corner];
+                let tex_coord = match index.t {
+                    0 => options.default_tex_coord,
+                    t => tex_coords[t as usize],
+                };
+                let normal = match index.n {
+                    0 => generated_normals[i],
+                    n => normals[n as usize],
+                };
+
+                Vertex::new(positions[*position_index as usize], tex_coord, normal)
+            })
+            .collect();
+
+        if options.generate_tangents {
+            let tangents = generate_tangents(&vertices);
+            for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                vertex.tangent = tangent;
+            }
+        }
 
-        let num_meshlet_data = meshlets
+        ensure!(
+            obj_materials.face_materials.len() == indices.len() / 3,
+            "Got {} face materials for {} faces of {:?}",
+            obj_materials.face_materials.len(),
+            indices.len() / 3,
+            path
+        );
+        let face_materials: Vec<u32> = repaired
+            .triangles
             .iter()
-            .map(|meshlet| meshlet.vertices.len() + ((meshlet.triangles.len() * 3 + 3) >> 2))
-            .sum();
+            .map(|triangle| obj_materials.face_materials[*triangle])
+            .collect();
+        let mesh = Self::from_submeshes(
+            &vertices,
+            None,
+            &face_materials,
+            obj_materials.materials,
+            Vec::new(),
+            config,
+        )?;
+
+        Ok((mesh, repaired.log))
+    }
+
+    /// Deduplicates, optimizes and meshletizes a triangle list. Without `indices` every three
+    /// consecutive vertices form a triangle.
+    pub fn from_vertices(
+        vertices: &[Vertex],
+        indices: Option<&[u32]>,
+        config: &MeshletBuildConfig,
+    ) -> Result<Self> {
+        let index_count = indices.map_or(vertices.len(), |indices| indices.len());
 
-        let mut meshlet_data = vec![0; num_meshlet_data];
+        Self::from_submeshes(
+            vertices,
+            indices,
+            &vec![0; index_count / 3],
+            vec![Material::default()],
+            Vec::new(),
+            config,
+        )
+    }
 
-        let mut index = 0;
-        let meshlets = meshlets
+    /// Like [`Mesh::from_vertices`], but groups the triangles by their entry in
+    /// `triangle_materials` into one submesh per material, each with its own range of meshlets.
+    /// Every LOD gets its own set of submeshes. `morph_targets` have one delta per entry of
+    /// `vertices` and are deduplicated along with them.
+    pub fn from_submeshes(
+        vertices: &[Vertex],
+        indices: Option<&[u32]>,
+        triangle_materials: &[u32],
+        materials: Vec<Material>,
+        morph_targets: Vec<MorphTarget>,
+        config: &MeshletBuildConfig,
+    ) -> Result<Self> {
+        config.validate()?;
+
+        let index_count = indices.map_or(vertices.len(), |indices| indices.len());
+        ensure!(
+            index_count % 3 == 0,
+            "Index count {index_count} is not a multiple of 3"
+        );
+        ensure!(
+            triangle_materials.len() == index_count / 3,
+            "Got {} triangle materials for {} triangles",
+            triangle_materials.len(),
+            index_count / 3
+        );
+        if let Some(material) = triangle_materials
             .iter()
-            .map(|meshlet| {
-                let data_offset = index;
+            .find(|material| **material as usize >= materials.len())
+        {
+            bail!(
+                "Material {material} out of range for {} materials",
+                materials.len()
+            );
+        }
+        for (index, target) in morph_targets.iter().enumerate() {
+            ensure!(
+                target.deltas.len() == vertices.len(),
+                "Morph target {index} has {} deltas for {} vertices",
+                target.deltas.len(),
+                vertices.len()
+            );
+        }
 
-                for vertex in meshlet.vertices {
-                    meshlet_data[index] = *vertex;
-                    index += 1;
-                }
+        let (vertex_count, remap) = morph::generate_vertex_remap(vertices, indices, &morph_targets);
 
-                let num_packed_indices = (meshlet.triangles.len() + 3) >> 2;
-                for j in 0..num_packed_indices {
-                    let triangle_offset = j << 2;
-                    meshlet_data[index] = (meshlet.triangles[triangle_offset] as u32) << 0
-                        | (meshlet
-                            .triangles
-                            .get(triangle_offset + 1)
-                            .copied()
-                            .unwrap_or_default() as u32)
-                            << 8
-                        | (meshlet
-                            .triangles
-                            .get(triangle_offset + 2)
-                            .copied()
-                            .unwrap_or_default() as u32)
-                            << 16
-                        | (meshlet
-                            .triangles
-                            .get(triangle_offset + 3)
-                            .copied()
-                            .unwrap_or_default() as u32)
-                            << 24;
-                    index += 1;
-                }
+        let vertices = meshopt::remap_vertex_buffer(vertices, vertex_count, &remap);
+        let remapped_indices = meshopt::remap_index_buffer(indices, index_count, &remap);
+        let morph_targets = morph::remap_morph_targets(morph_targets, vertex_count, &remap);
 
-                Meshlet::new(
-                    data_offset as _,
-                    meshlet.vertices.len() as _,
-                    (meshlet.triangles.len() / 3) as _,
-                )
+        // Degenerate triangles cover no pixels and are rejected by `Mesh::validate`.
+        // A stable sort keeps the triangles of each material in their original order.
+        let mut triangles: Vec<usize> = (0..index_count / 3)
+            .filter(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|corner| remapped_indices[3 * triangle + corner]);
+                a != b && b != c && c != a
             })
             .collect();
+        triangles.sort_by_key(|triangle| triangle_materials[*triangle]);
+
+        let mut indices: Vec<u32> = triangles
+            .iter()
+            .flat_map(|triangle| {
+                remapped_indices[3 * triangle..3 * triangle + 3]
+                    .iter()
+                    .copied()
+            })
+            .collect();
+
+        let mut submesh_ranges: Vec<(u32, Range<usize>)> = Vec::new();
+        for (i, triangle) in triangles.iter().enumerate() {
+            let material = triangle_materials[*triangle];
+            match submesh_ranges.last_mut() {
+                Some((last, range)) if *last == material => range.end = 3 * (i + 1),
+                _ => submesh_ranges.push((material, 3 * i..3 * (i + 1))),
+            }
+        }
 
-        Ok(Self {
+        for (_, range) in &submesh_ranges {
+            meshopt::optimize_vertex_cache_in_place(&mut indices[range.clone()], vertices.len());
+            meshopt::optimize_overdraw_in_place(
+                &mut indices[range.clone()],
+                &VertexDataAdapter::new(
+                    bytemuck::cast_slice(&vertices),
+                    mem::size_of::<Vertex>(),
+                    0,
+                )?,
+                1.01,
+            );
+        }
+
+        // Same as `optimize_vertex_fetch_in_place`, but the morph targets have to follow the
+        // vertices.
+        let fetch_remap = meshopt::optimize_vertex_fetch_remap(&indices, vertices.len());
+        let vertex_count = fetch_remap
+            .iter()
+            .filter(|index| **index != u32::MAX)
+            .count();
+        let indices = meshopt::remap_index_buffer(Some(&indices), indices.len(), &fetch_remap);
+        let vertices = meshopt::remap_vertex_buffer(&vertices, vertex_count, &fetch_remap);
+        let morph_targets = morph::remap_morph_targets(morph_targets, vertex_count, &fetch_remap);
+
+        let vertex_data_adapter =
+            VertexDataAdapter::new(bytemuck::cast_slice(&vertices), mem::size_of::<Vertex>(), 0)?;
+
+        let mut meshlets = Vec::new();
+        let mut meshlet_data = Vec::new();
+        let mut submeshes = Vec::with_capacity(submesh_ranges.len());
+
+        for (material, range) in &submesh_ranges {
+            let meshlet_offset = meshlets.len();
+
+            append_meshlets(
+                &meshopt::build_meshlets(
+                    &indices[range.clone()],
+                    &vertex_data_adapter,
+                    config.max_vertices,
+                    config.max_triangles,
+                    config.cone_weight,
+                ),
+                &vertex_data_adapter,
+                config.index_format,
+                &mut meshlets,
+                &mut meshlet_data,
+            );
+
+            submeshes.push(Submesh::new(
+                *material,
+                meshlet_offset as _,
+                (meshlets.len() - meshlet_offset) as _,
+            ));
+        }
+
+        let mut lods = vec![Lod::new(0.0, 0, submeshes.len() as _)];
+
+        build_lod_chain(
+            submesh_ranges
+                .into_iter()
+                .map(|(material, range)| (material, indices[range].to_vec()))
+                .collect(),
+            &vertex_data_adapter,
+            config,
+            &mut meshlets,
+            &mut meshlet_data,
+            &mut submeshes,
+            &mut lods,
+        );
+        morph::expand_meshlet_bounds(&mut meshlets, &meshlet_data, &vertices, &morph_targets);
+
+        let mut mesh = Self {
             vertices,
             meshlets,
             meshlet_data,
-        })
+            submeshes,
+            materials,
+            lods,
+            index_format: config.index_format,
+            morph_targets,
+        };
+        mesh.reorder_meshlets(config.meshlet_order)?;
+        if cfg!(debug_assertions) {
+            mesh.validate()?;
+        }
+
+        Ok(mesh)
     }
 }
 
-#[derive(Clone)]
-pub struct MeshBuffers {
-    pub vertex_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
-    pub meshlet_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
-    pub meshlet_data_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
-    pub num_meshlets: usize,
-}
+pub(crate) fn append_meshlets(
+    built_meshlets: &meshopt::Meshlets,
+    vertex_data_adapter: &VertexDataAdapter,
+    index_format: IndexFormat,
+    meshlets: &mut Vec<Meshlet>,
+    meshlet_data: &mut Vec<u32>,
+) {
+    for meshlet in built_meshlets.iter() {
+        let data_offset = meshlet_data.len();
 
-impl MeshBuffers {
-    pub unsafe fn new(
-        device: &ProtocolObject<dyn MTLDevice>,
-        path: impl AsRef<Path>,
-    ) -> Result<Self> {
-        let mut mesh = Mesh::new(path)?;
+        meshlet_data.extend_from_slice(meshlet.vertices);
+        meshlet_data.extend(index_format.pack(meshlet.triangles));
 
-        let vertex_buffer = device
-            .newBufferWithBytes_length_options(
-                NonNull::new(mesh.vertices.as_mut_ptr().cast()).unwrap(),
-                (mesh.vertices.len() * mem::size_of::<Vertex>()) as _,
-                MTLResourceOptions::StorageModeShared,
+        meshlets.push(
+            Meshlet::new(
+                data_offset as _,
+                meshlet.vertices.len() as _,
+                (meshlet.triangles.len() / 3) as _,
             )
-            .unwrap();
+            .with_bounds(&meshopt::compute_meshlet_bounds(
+                meshlet,
+                vertex_data_adapter,
+            )),
+        );
+    }
+}
 
-        let meshlet_buffer = device
-            .newBufferWithBytes_length_options(
-                NonNull::new(mesh.meshlets.as_mut_ptr().cast()).unwrap(),
-                (mesh.meshlets.len() * mem::size_of::<Meshlet>()) as _,
-                MTLResourceOptions::StorageModeShared,
-            )
-            .unwrap();
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
 
-        let meshlet_data_buffer = device
-            .newBufferWithBytes_length_options(
-                NonNull::new(mesh.meshlet_data.as_mut_ptr().cast()).unwrap(),
-                (mesh.meshlet_data.len() * mem::size_of::<u32>()) as _,
-                MTLResourceOptions::StorageModeShared,
-            )
-            .unwrap();
-
-        Ok(Self {
-            vertex_buffer,
-            meshlet_buffer,
-            meshlet_data_buffer,
-            num_meshlets: mesh.meshlets.len(),
-        })
+    use glam::{Mat4, Vec2, Vec3, Vec4};
+
+    use crate::{
+        material::DEFAULT_MATERIAL_NAME,
+        mesh::{frustum_planes, ImportOptions, Mesh, Meshlet, MeshletBuildConfig, Submesh, UpAxis},
+    };
+
+    const QUAD_OBJ: &str = "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 \
+                            1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/4/1\n";
+
+    fn cone_meshlet(center: Vec3, radius: f32, axis: Vec3, cutoff: f32) -> Meshlet {
+        let axis = (axis.normalize() * 127.0).round();
+
+        Meshlet {
+            center,
+            radius,
+            cone_apex: center,
+            cone_axis: [axis.x as i8, axis.y as i8, axis.z as i8],
+            cone_cutoff: (cutoff * 127.0).ceil() as i8,
+            ..Meshlet::new(0, 0, 0)
+        }
+    }
+
+    #[test]
+    fn backfacing_meshlet() {
+        let meshlet = cone_meshlet(Vec3::ZERO, 1.0, Vec3::Z, 0.5);
+
+        assert!(meshlet.is_backfacing(Vec3::new(0.0, 0.0, -10.0)));
+        assert!(!meshlet.is_backfacing(Vec3::new(0.0, 0.0, 10.0)));
+        assert!(!meshlet.is_backfacing(Vec3::new(10.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn degenerate_cone_is_never_backfacing() {
+        let meshlet = Meshlet {
+            cone_axis: [0; 3],
+            cone_cutoff: 127,
+            ..cone_meshlet(Vec3::ZERO, 1.0, Vec3::Z, 1.0)
+        };
+
+        assert!(!meshlet.is_backfacing(Vec3::new(0.0, 0.0, -10.0)));
+        assert!(!meshlet.is_backfacing(Vec3::new(0.0, 0.0, 10.0)));
+    }
+
+    #[test]
+    fn frustum_culling() {
+        let view_projection = Mat4::perspective_lh(90.0f32.to_radians(), 1.0, 0.1, 100.0)
+            * Mat4::look_at_lh(Vec3::ZERO, Vec3::Z, Vec3::Y);
+        let planes: [Vec4; 6] = frustum_planes(&view_projection);
+
+        let visible = cone_meshlet(Vec3::new(0.0, 0.0, 10.0), 1.0, Vec3::Z, 1.0);
+        let behind = cone_meshlet(Vec3::new(0.0, 0.0, -10.0), 1.0, Vec3::Z, 1.0);
+        let far_away = cone_meshlet(Vec3::new(0.0, 0.0, 200.0), 1.0, Vec3::Z, 1.0);
+        let left = cone_meshlet(Vec3::new(-20.0, 0.0, 10.0), 1.0, Vec3::Z, 1.0);
+        let intersecting = cone_meshlet(Vec3::new(-10.5, 0.0, 10.0), 1.0, Vec3::Z, 1.0);
+
+        assert!(!visible.is_outside_frustum(&planes));
+        assert!(behind.is_outside_frustum(&planes));
+        assert!(far_away.is_outside_frustum(&planes));
+        assert!(left.is_outside_frustum(&planes));
+        assert!(!intersecting.is_outside_frustum(&planes));
+    }
+
+    #[test]
+    fn meshlet_build_config_limits() {
+        assert!(MeshletBuildConfig::default().validate().is_ok());
+        assert!(MeshletBuildConfig::new(255, 256, 0.0).is_ok());
+        assert!(MeshletBuildConfig::new(256, 124, 0.25).is_err());
+        assert!(MeshletBuildConfig::new(64, 512, 0.25).is_err());
+        assert!(MeshletBuildConfig::new(64, 126, 0.25).is_err());
+        assert!(MeshletBuildConfig::new(64, 124, 1.5).is_err());
+        assert!(MeshletBuildConfig::new(0, 124, 0.25).is_err());
+    }
+
+    #[test]
+    fn import_options_limits() {
+        assert!(ImportOptions::default().validate().is_ok());
+        for options in [
+            ImportOptions {
+                weld_tolerance: 0.0,
+                ..Default::default()
+            },
+            ImportOptions {
+                unit_scale: 0.01,
+                ..Default::default()
+            },
+        ] {
+            assert!(options.validate().is_ok(), "{options:?}");
+        }
+        for options in [
+            ImportOptions {
+                weld_tolerance: -1e-5,
+                ..Default::default()
+            },
+            ImportOptions {
+                weld_tolerance: f32::NAN,
+                ..Default::default()
+            },
+            ImportOptions {
+                unit_scale: 0.0,
+                ..Default::default()
+            },
+            ImportOptions {
+                unit_scale: -1.0,
+                ..Default::default()
+            },
+            ImportOptions {
+                unit_scale: f32::INFINITY,
+                ..Default::default()
+            },
+        ] {
+            assert!(options.validate().is_err(), "{options:?}");
+        }
+    }
+
+    #[test]
+    fn meshlets_respect_build_config() {
+        let path = env::temp_dir().join("meshlets_respect_build_config.obj");
+        fs::write(&path, QUAD_OBJ).unwrap();
+
+        let config = MeshletBuildConfig::new(3, 4, 0.0).unwrap();
+        let mesh = Mesh::new(&path, &ImportOptions::default(), &config).unwrap();
+
+        assert_eq!(mesh.meshlets.len(), 2);
+        for meshlet in &mesh.meshlets {
+            assert!(meshlet.vertex_count as usize <= config.max_vertices);
+            assert!(meshlet.triangle_count as usize <= config.max_triangles);
+        }
+    }
+
+    #[test]
+    fn meshlet_bounds_contain_vertices() {
+        let path = env::temp_dir().join("meshlet_bounds_contain_vertices.obj");
+        fs::write(&path, QUAD_OBJ).unwrap();
+
+        let mesh = Mesh::new(
+            &path,
+            &ImportOptions::default(),
+            &MeshletBuildConfig::default(),
+        )
+        .unwrap();
+
+        for meshlet in &mesh.meshlets {
+            let offset = meshlet.data_offset as usize;
+            for &vertex in &mesh.meshlet_data[offset..offset + meshlet.vertex_count as usize] {
+                let position = mesh.vertices[vertex as usize].position;
+                assert!(position.distance(meshlet.center) <= meshlet.radius + 1e-5);
+            }
+
+            assert!(meshlet.is_backfacing(Vec3::new(0.0, 0.0, -10.0)));
+            assert!(!meshlet.is_backfacing(Vec3::new(0.0, 0.0, 10.0)));
+        }
+    }
+
+    #[test]
+    fn missing_attributes_are_generated() {
+        let path = env::temp_dir().join("missing_attributes_are_generated.obj");
+        fs::write(
+            &path,
+            "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nf 1 2 3\nf 1 3 4\n",
+        )
+        .unwrap();
+
+        let mesh = Mesh::new(
+            &path,
+            &ImportOptions::default(),
+            &MeshletBuildConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(mesh.vertices.len(), 4);
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.tex_coord, ImportOptions::default().default_tex_coord);
+            assert!(vertex.normal.abs_diff_eq(Vec3::Z, 1e-6));
+        }
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let options = ImportOptions::default();
+        let config = MeshletBuildConfig::default();
+
+        for (name, obj) in [
+            ("position", "v 0 0 0\nv 1 0 0\nf 1 2 3\n"),
+            (
+                "tex_coord",
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/1 2/2 3/1\n",
+            ),
+            (
+                "normal",
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//2\n",
+            ),
+            ("quad", "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 4 3\n"),
+        ] {
+            let path = env::temp_dir().join(format!("out_of_range_{name}.obj"));
+            fs::write(&path, obj).unwrap();
+
+            assert!(Mesh::new(&path, &options, &config).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn noisy_positions_are_welded() {
+        // The second triangle uses its own, slightly off copies of the shared edge and is listed
+        // twice.
+        let path = env::temp_dir().join("noisy_positions_are_welded.obj");
+        fs::write(
+            &path,
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0.000001 0 0\nv 1 1.000002 0\nf 1 2 3\nf 5 \
+             6 4\nf 5 6 4\n",
+        )
+        .unwrap();
+
+        let config = MeshletBuildConfig::default();
+        let (mesh, log) = Mesh::import(&path, &ImportOptions::default(), &config).unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(log.welded_positions, 2);
+        assert_eq!(log.duplicate_triangles, 1);
+        assert_eq!(log.non_manifold_edges, 0);
+        assert_eq!(log.flipped_triangles, 0);
+
+        let (unrepaired, log) = Mesh::import(
+            &path,
+            &ImportOptions {
+                repair: false,
+                ..Default::default()
+            },
+            &config,
+        )
+        .unwrap();
+        assert_eq!(unrepaired.vertices.len(), 6);
+        assert!(log.is_clean());
+    }
+
+    #[test]
+    fn coordinate_conversion() {
+        // A triangle facing +Z in a Z up, right handed file in centimeters.
+        let path = env::temp_dir().join("coordinate_conversion.obj");
+        fs::write(
+            &path,
+            "v 0 0 0\nv 100 0 0\nv 0 100 0\nvt 0 0\nvt 1 0\nvt 0 0.25\nvn 0 0 1\nf 1/1/1 2/2/1 \
+             3/3/1\n",
+        )
+        .unwrap();
+
+        let options = ImportOptions {
+            up_axis: UpAxis::Z,
+            flip_handedness: true,
+            unit_scale: 0.01,
+            ..Default::default()
+        };
+        let mesh = Mesh::new(&path, &options, &MeshletBuildConfig::default()).unwrap();
+
+        let vertex = |position: Vec3| {
+            mesh.vertices
+                .iter()
+                .find(|vertex| vertex.position.abs_diff_eq(position, 1e-6))
+                .unwrap()
+        };
+        // Z up becomes Y up, the former Y axis ends up along +Z after the mirror.
+        assert_eq!(vertex(Vec3::X).tex_coord, Vec2::new(1.0, 1.0));
+        assert_eq!(vertex(Vec3::Z).tex_coord, Vec2::new(0.0, 0.75));
+        assert!(vertex(Vec3::ZERO).normal.abs_diff_eq(Vec3::Y, 1e-6));
+
+        // The winding still agrees with the normal.
+        let triangle = mesh.emulate_draw(0).unwrap()[0];
+        let [a, b, c] = triangle.map(|index| mesh.vertices[index as usize].position);
+        assert!((b - a).cross(c - a).normalize().abs_diff_eq(Vec3::Y, 1e-6));
+    }
+
+    #[test]
+    fn submeshes_per_material() {
+        let path = env::temp_dir().join("submeshes_per_material.obj");
+        fs::write(
+            &path,
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\nusemtl a\nf 2 4 3\nusemtl b\nf 1 3 \
+             2\nusemtl a\nf 1 2 4\n",
+        )
+        .unwrap();
+
+        let config = MeshletBuildConfig::new(3, 4, 0.0).unwrap();
+        let mesh = Mesh::new(&path, &ImportOptions::default(), &config).unwrap();
+
+        let names: Vec<&str> = mesh
+            .materials
+            .iter()
+            .map(|material| material.name.as_str())
+            .collect();
+        assert_eq!(names, [DEFAULT_MATERIAL_NAME, "a", "b"]);
+
+        let triangle_counts: Vec<u32> = mesh
+            .submeshes
+            .iter()
+            .map(|submesh| {
+                let range = submesh.meshlet_offset as usize
+                    ..(submesh.meshlet_offset + submesh.meshlet_count) as usize;
+                mesh.meshlets[range]
+                    .iter()
+                    .map(|meshlet| meshlet.triangle_count)
+                    .sum()
+            })
+            .collect();
+        assert_eq!(triangle_counts, [1, 2, 1]);
+        assert_eq!(
+            mesh.submeshes,
+            [
+                Submesh::new(0, 0, 1),
+                Submesh::new(1, 1, 2),
+                Submesh::new(2, 3, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn tangents_split_vertices() {
+        // The second face mirrors the texture, so the shared edge needs two sets of vertices.
+        let path = env::temp_dir().join("tangents_split_vertices.obj");
+        fs::write(
+            &path,
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\nf \
+             1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/2/1\n",
+        )
+        .unwrap();
+
+        let config = MeshletBuildConfig::default();
+        let without_tangents = Mesh::new(&path, &ImportOptions::default(), &config).unwrap();
+        let with_tangents = Mesh::new(
+            &path,
+            &ImportOptions {
+                generate_tangents: true,
+                ..Default::default()
+            },
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(without_tangents.vertices.len(), 4);
+        assert_eq!(with_tangents.vertices.len(), 6);
+        for vertex in &with_tangents.vertices {
+            assert_eq!(vertex.tangent.w.abs(), 1.0);
+        }
     }
 }
@@ -0,0 +1,795 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    mem,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Result};
+use bytemuck::{Pod, Zeroable};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+use crate::{
+    index_packing::IndexFormat,
+    material::{load_obj_materials, Material},
+    mesh::{ImportOptions, Mesh, Meshlet, MeshletBuildConfig, Vertex, MAX_MESHLET_VERTICES},
+    mesh_repair::ImportLog,
+    morph::MorphTarget,
+};
+
+pub const MAGIC: [u8; 4] = *b"MLTC";
+pub const VERSION: u32 = 7;
+pub const EXTENSION: &str = "meshlets";
+
+/// Buffers are read in pieces of at most this many bytes, so a corrupt count in the header fails
+/// on the missing data instead of allocating the whole buffer up front.
+const READ_CHUNK_SIZE: usize = 1 << 20;
+/// Far more materials than any source has. Materials are not stored in the cache, the header
+/// count only sizes the placeholders.
+const MAX_MATERIALS: u64 = 1 << 16;
+/// Far more morph targets than any source has. Targets of a mesh without vertices take no bytes,
+/// so their count is not bounded by the data.
+const MAX_MORPH_TARGETS: u32 = 1 << 16;
+/// Upper bound of how much the meshopt codecs expand their input. The vertex codec spends at
+/// least two bits on 16 bytes and the index codec a byte on a triangle, so valid sections stay
+/// well below it.
+const MAX_CODEC_RATIO: u64 = 256;
+
+/// How the buffers after the header of a cache are stored.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CacheCompression {
+    /// Raw buffers, which are the largest but need no decoding.
+    #[default]
+    None,
+    /// Vertices, meshlets and morph deltas go through the meshopt vertex codec and the meshlet
+    /// triangles through the index codec. Decodes at several GB/s.
+    Meshopt,
+    /// The meshopt codec output deflated once more. Usually noticeably smaller than
+    /// [`CacheCompression::Meshopt`], but inflating dominates the decode time.
+    MeshoptDeflate,
+}
+
+impl CacheCompression {
+    pub const ALL: [Self; 3] = [Self::None, Self::Meshopt, Self::MeshoptDeflate];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Meshopt => "meshopt",
+            Self::MeshoptDeflate => "meshopt-deflate",
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Meshopt => 1,
+            Self::MeshoptDeflate => 2,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Result<Self> {
+        match Self::ALL
+            .into_iter()
+            .find(|compression| compression.id() == id)
+        {
+            Some(compression) => Ok(compression),
+            None => bail!("Unknown cache compression {id}"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+    config_hash: u64,
+    source_hash: u64,
+    vertex_size: u32,
+    meshlet_size: u32,
+    vertex_count: u64,
+    meshlet_count: u64,
+    meshlet_data_count: u64,
+    submesh_count: u64,
+    material_count: u64,
+    lod_count: u64,
+    index_format: u32,
+    morph_target_count: u32,
+    compression: u32,
+    _padding: u32,
+}
+
+unsafe impl Zeroable for Header {}
+unsafe impl Pod for Header {}
+
+/// What [`Mesh::load`] found next to the source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The mesh was read from the cache.
+    #[default]
+    Hit,
+    Missing,
+    /// Built from a different source, build config or format version, or with a different number
+    /// of materials than the source has now.
+    Stale,
+    /// The cache could not be read, with the error.
+    Corrupt(String),
+}
+
+impl fmt::Display for CacheStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hit => write!(f, "read from cache"),
+            Self::Missing => write!(f, "no cache, rebuilt"),
+            Self::Stale => write!(f, "stale cache, rebuilt"),
+            Self::Corrupt(e) => write!(f, "corrupt cache ({e}), rebuilt"),
+        }
+    }
+}
+
+/// How [`Mesh::load`] got the mesh, for the caller to report.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    pub cache: CacheStatus,
+    /// Only set if the mesh was imported because the cache could not be used.
+    pub import_log: Option<ImportLog>,
+}
+
+/// Identifies the inputs a cache was built from. A cache is only used if both hashes match.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CacheKey {
+    pub config_hash: u64,
+    pub source_hash: u64,
+}
+
+impl CacheKey {
+    pub fn new(source: &[u8], options: &ImportOptions, config: &MeshletBuildConfig) -> Self {
+        Self {
+            config_hash: config_hash(options, config),
+            source_hash: fnv1a(source),
+        }
+    }
+
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        options: &ImportOptions,
+        config: &MeshletBuildConfig,
+    ) -> Result<Self> {
+        Ok(Self::new(&fs::read(path)?, options, config))
+    }
+}
+
+/// 64 bit FNV-1a, which unlike `DefaultHasher` is guaranteed to be stable between builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn config_hash(options: &ImportOptions, config: &MeshletBuildConfig) -> u64 {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&options.crease_angle.to_bits().to_le_bytes());
+    for component in options.default_tex_coord.to_array() {
+        bytes.extend_from_slice(&component.to_bits().to_le_bytes());
+    }
+    bytes.push(options.generate_tangents as u8);
+    bytes.push(options.repair as u8);
+    bytes.extend_from_slice(&options.weld_tolerance.to_bits().to_le_bytes());
+    bytes.extend_from_slice(&options.up_axis.id().to_le_bytes());
+    bytes.push(options.flip_handedness as u8);
+    bytes.extend_from_slice(&options.unit_scale.to_bits().to_le_bytes());
+    bytes.push(options.flip_tex_coord_v as u8);
+    bytes.extend_from_slice(&(config.max_vertices as u64).to_le_bytes());
+    bytes.extend_from_slice(&(config.max_triangles as u64).to_le_bytes());
+    bytes.extend_from_slice(&config.cone_weight.to_bits().to_le_bytes());
+    for lod in &config.lods {
+        bytes.extend_from_slice(&lod.target_ratio.to_bits().to_le_bytes());
+        bytes.extend_from_slice(&lod.target_error.to_bits().to_le_bytes());
+    }
+    bytes.extend_from_slice(&config.index_format.id().to_le_bytes());
+    bytes.extend_from_slice(&config.meshlet_order.id().to_le_bytes());
+
+    fnv1a(&bytes)
+}
+
+pub fn cache_path(source: impl AsRef<Path>) -> PathBuf {
+    source.as_ref().with_extension(EXTENSION)
+}
+
+fn write_section(writer: &mut impl Write, section: &[u8]) -> Result<()> {
+    writer.write_all(&(section.len() as u64).to_le_bytes())?;
+    writer.write_all(section)?;
+    Ok(())
+}
+
+/// Reads `count` items of a raw buffer, growing the buffer as the data arrives.
+fn read_items<T: Pod + Default>(reader: &mut impl Read, count: u64) -> Result<Vec<T>> {
+    let count = usize::try_from(count)?;
+    let chunk_len = (READ_CHUNK_SIZE / mem::size_of::<T>()).max(1);
+
+    let mut items = Vec::new();
+    while items.len() < count {
+        let start = items.len();
+        items.resize(start + chunk_len.min(count - start), T::default());
+        reader.read_exact(bytemuck::cast_slice_mut(&mut items[start..]))?;
+    }
+
+    Ok(items)
+}
+
+fn placeholder_materials(count: u64) -> Result<Vec<Material>> {
+    ensure!(
+        count <= MAX_MATERIALS,
+        "Meshlet cache claims {count} materials, more than {MAX_MATERIALS}"
+    );
+    Ok(vec![Material::default(); count as _])
+}
+
+fn read_section(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+
+    // Read through `take` so a corrupt length fails on the missing bytes instead of allocating.
+    let mut section = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut section)?;
+    ensure!(
+        section.len() as u64 == len,
+        "Truncated meshlet cache section, expected {len} bytes but got {}",
+        section.len()
+    );
+
+    Ok(section)
+}
+
+/// Reads a codec section and checks that it can decode to `decoded_len` bytes before the decoder
+/// allocates them.
+fn read_encoded_section(reader: &mut impl Read, decoded_len: u64) -> Result<Vec<u8>> {
+    let section = read_section(reader)?;
+    ensure!(
+        decoded_len / MAX_CODEC_RATIO <= section.len() as u64,
+        "Meshlet cache section of {} bytes cannot decode to {decoded_len} bytes",
+        section.len()
+    );
+
+    Ok(section)
+}
+
+fn decode_vertices<T: Pod>(reader: &mut impl Read, count: u64) -> Result<Vec<T>> {
+    let decoded_len = count.saturating_mul(mem::size_of::<T>() as u64);
+    let section = read_encoded_section(reader, decoded_len)?;
+    Ok(meshopt::decode_vertex_buffer(&section, count as _)?)
+}
+
+fn decode_indices(reader: &mut impl Read, count: u64) -> Result<Vec<u32>> {
+    let decoded_len = count.saturating_mul(mem::size_of::<u32>() as u64);
+    let section = read_encoded_section(reader, decoded_len)?;
+    Ok(meshopt::decode_index_buffer(&section, count as _)?)
+}
+
+impl Mesh {
+    pub fn write_cache(
+        &self,
+        writer: &mut impl Write,
+        key: &CacheKey,
+        compression: CacheCompression,
+    ) -> Result<()> {
+        let header = Header {
+            magic: MAGIC,
+            version: VERSION,
+            config_hash: key.config_hash,
+            source_hash: key.source_hash,
+            vertex_size: mem::size_of::<Vertex>() as _,
+            meshlet_size: mem::size_of::<Meshlet>() as _,
+            vertex_count: self.vertices.len() as _,
+            meshlet_count: self.meshlets.len() as _,
+            meshlet_data_count: self.meshlet_data.len() as _,
+            submesh_count: self.submeshes.len() as _,
+            material_count: self.materials.len() as _,
+            lod_count: self.lods.len() as _,
+            index_format: self.index_format.id(),
+            morph_target_count: self.morph_targets.len() as _,
+            compression: compression.id(),
+            _padding: 0,
+        };
+
+        writer.write_all(bytemuck::bytes_of(&header))?;
+        match compression {
+            CacheCompression::None => self.write_raw_buffers(writer),
+            CacheCompression::Meshopt => self.write_encoded_buffers(writer),
+            CacheCompression::MeshoptDeflate => {
+                let mut encoder = DeflateEncoder::new(writer, flate2::Compression::default());
+                self.write_encoded_buffers(&mut encoder)?;
+                encoder.finish()?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_raw_buffers(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(bytemuck::cast_slice(&self.vertices))?;
+        writer.write_all(bytemuck::cast_slice(&self.meshlets))?;
+        writer.write_all(bytemuck::cast_slice(&self.meshlet_data))?;
+        writer.write_all(bytemuck::cast_slice(&self.submeshes))?;
+        writer.write_all(bytemuck::cast_slice(&self.lods))?;
+        for target in &self.morph_targets {
+            writer.write_all(bytemuck::cast_slice(&target.deltas))?;
+        }
+
+        Ok(())
+    }
+
+    /// Encoded buffers are written as length prefixed sections, the small submesh and LOD
+    /// buffers as is. The meshlet data is split into the vertex indices of all meshlets and their
+    /// unpacked local triangles, which the index codec handles much better than packed words.
+    fn write_encoded_buffers(&self, writer: &mut impl Write) -> Result<()> {
+        write_section(writer, &meshopt::encode_vertex_buffer(&self.vertices)?)?;
+
+        // The data offsets are restored when the meshlet data is laid out again on read.
+        let meshlets: Vec<Meshlet> = self
+            .meshlets
+            .iter()
+            .map(|meshlet| {
+                Meshlet {
+                    data_offset: 0,
+                    ..*meshlet
+                }
+            })
+            .collect();
+        write_section(writer, &meshopt::encode_vertex_buffer(&meshlets)?)?;
+
+        let mut meshlet_vertices = Vec::new();
+        let mut triangles = Vec::new();
+        for meshlet in &self.meshlets {
+            let data_len = meshlet.data_len(self.index_format, &self.meshlet_data)?;
+            let (vertices, packed_triangles) = self.meshlet_data[meshlet.data_offset as usize..]
+                [..data_len]
+                .split_at(meshlet.vertex_count as usize);
+            meshlet_vertices.extend_from_slice(vertices);
+            triangles.extend(
+                self.index_format
+                    .unpack(packed_triangles, meshlet.triangle_count)?,
+            );
+        }
+        write_section(writer, &meshopt::encode_vertex_buffer(&meshlet_vertices)?)?;
+        write_section(
+            writer,
+            &meshopt::encode_index_buffer(&triangles, MAX_MESHLET_VERTICES)?,
+        )?;
+
+        writer.write_all(bytemuck::cast_slice(&self.submeshes))?;
+        writer.write_all(bytemuck::cast_slice(&self.lods))?;
+        for target in &self.morph_targets {
+            write_section(writer, &meshopt::encode_vertex_buffer(&target.deltas)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns `None` if the cache was built from a different source, build config or format
+    /// version and has to be rebuilt. Materials and morph target names are not cached and have to
+    /// be restored from the source, the returned mesh contains default materials in their place.
+    /// In debug builds a cache that fails [`Mesh::validate`] is an error. Compressed caches are
+    /// decoded one buffer at a time while reading, without loading the whole file first.
+    pub fn read_cache(reader: &mut impl Read, key: &CacheKey) -> Result<Option<Self>> {
+        let mut header = Header::default();
+        reader.read_exact(bytemuck::bytes_of_mut(&mut header))?;
+
+        if header.magic != MAGIC {
+            bail!("Invalid meshlet cache magic {:?}", header.magic);
+        }
+
+        if header.version != VERSION
+            || header.config_hash != key.config_hash
+            || header.source_hash != key.source_hash
+            || header.vertex_size as usize != mem::size_of::<Vertex>()
+            || header.meshlet_size as usize != mem::size_of::<Meshlet>()
+        {
+            return Ok(None);
+        }
+
+        ensure!(
+            header.morph_target_count <= MAX_MORPH_TARGETS,
+            "Meshlet cache claims {} morph targets, more than {MAX_MORPH_TARGETS}",
+            header.morph_target_count
+        );
+
+        let mesh = match CacheCompression::from_id(header.compression)? {
+            CacheCompression::None => Self::read_raw_buffers(reader, &header)?,
+            CacheCompression::Meshopt => Self::read_encoded_buffers(reader, &header)?,
+            CacheCompression::MeshoptDeflate => {
+                Self::read_encoded_buffers(&mut DeflateDecoder::new(reader), &header)?
+            }
+        };
+        if cfg!(debug_assertions) {
+            mesh.validate()?;
+        }
+
+        Ok(Some(mesh))
+    }
+
+    fn read_raw_buffers(reader: &mut impl Read, header: &Header) -> Result<Self> {
+        let vertices: Vec<Vertex> = read_items(reader, header.vertex_count)?;
+        let meshlets = read_items(reader, header.meshlet_count)?;
+        let meshlet_data = read_items(reader, header.meshlet_data_count)?;
+        let submeshes = read_items(reader, header.submesh_count)?;
+        let lods = read_items(reader, header.lod_count)?;
+
+        let mut morph_targets = Vec::new();
+        for _ in 0..header.morph_target_count {
+            let deltas = read_items(reader, header.vertex_count)?;
+            morph_targets.push(MorphTarget { name: None, deltas });
+        }
+
+        Ok(Self {
+            vertices,
+            meshlets,
+            meshlet_data,
+            submeshes,
+            materials: placeholder_materials(header.material_count)?,
+            lods,
+            index_format: IndexFormat::from_id(header.index_format)?,
+            morph_targets,
+        })
+    }
+
+    fn read_encoded_buffers(reader: &mut impl Read, header: &Header) -> Result<Self> {
+        let index_format = IndexFormat::from_id(header.index_format)?;
+
+        let vertices = decode_vertices(reader, header.vertex_count)?;
+        let mut meshlets: Vec<Meshlet> = decode_vertices(reader, header.meshlet_count)?;
+
+        let vertex_count = meshlets
+            .iter()
+            .map(|meshlet| meshlet.vertex_count as u64)
+            .sum();
+        let index_count = meshlets
+            .iter()
+            .map(|meshlet| 3 * meshlet.triangle_count as u64)
+            .sum();
+        let meshlet_vertices: Vec<u32> = decode_vertices(reader, vertex_count)?;
+        let triangles = decode_indices(reader, index_count)?;
+
+        // The index codec may rotate triangles, which can change how many words the strip format
+        // packs them into, so the meshlet data is laid out again in meshlet order.
+        let mut meshlet_data = Vec::with_capacity(meshlet_vertices.len() + triangles.len());
+        let (mut meshlet_vertices, mut triangles) =
+            (meshlet_vertices.as_slice(), triangles.as_slice());
+        for meshlet in &mut meshlets {
+            let vertices;
+            let local_triangles;
+            (vertices, meshlet_vertices) = meshlet_vertices.split_at(meshlet.vertex_count as usize);
+            (local_triangles, triangles) = triangles.split_at(3 * meshlet.triangle_count as usize);
+            ensure!(
+                local_triangles
+                    .iter()
+                    .all(|index| *index < meshlet.vertex_count),
+                "Meshlet cache contains a triangle outside its meshlet"
+            );
+
+            meshlet.data_offset = meshlet_data.len() as u32;
+            meshlet_data.extend_from_slice(vertices);
+            meshlet_data.extend(
+                index_format.pack(
+                    &local_triangles
+                        .iter()
+                        .map(|index| *index as u8)
+                        .collect::<Vec<_>>(),
+                ),
+            );
+        }
+
+        let submeshes = read_items(reader, header.submesh_count)?;
+        let lods = read_items(reader, header.lod_count)?;
+
+        let mut morph_targets = Vec::new();
+        for _ in 0..header.morph_target_count {
+            let deltas = decode_vertices(reader, header.vertex_count)?;
+            morph_targets.push(MorphTarget { name: None, deltas });
+        }
+
+        Ok(Self {
+            vertices,
+            meshlets,
+            meshlet_data,
+            submeshes,
+            materials: placeholder_materials(header.material_count)?,
+            lods,
+            index_format,
+            morph_targets,
+        })
+    }
+
+    /// Loads the mesh from the cache next to `path` and rebuilds the cache if it is missing or
+    /// stale. Nothing is printed, the report says what happened.
+    pub fn load(
+        path: impl AsRef<Path>,
+        options: &ImportOptions,
+        config: &MeshletBuildConfig,
+    ) -> Result<(Self, LoadReport)> {
+        let path = path.as_ref();
+        let cache_path = cache_path(path);
+        let key = CacheKey::from_file(path, options, config)?;
+
+        let mut report = LoadReport {
+            cache: CacheStatus::Missing,
+            import_log: None,
+        };
+        if let Ok(file) = File::open(&cache_path) {
+            report.cache = match Self::read_cache(&mut BufReader::new(file), &key) {
+                Ok(Some(mut mesh)) => {
+                    let materials = load_obj_materials(path)?.materials;
+                    if materials.len() == mesh.materials.len() {
+                        mesh.materials = materials;
+                        report.cache = CacheStatus::Hit;
+                        return Ok((mesh, report));
+                    }
+                    CacheStatus::Stale
+                }
+                Ok(None) => CacheStatus::Stale,
+                Err(e) => CacheStatus::Corrupt(e.to_string()),
+            };
+        }
+
+        let (mesh, log) = Self::import(path, options, config)?;
+        report.import_log = Some(log);
+
+        let mut writer = BufWriter::new(File::create(&cache_path)?);
+        mesh.write_cache(&mut writer, &key, CacheCompression::default())?;
+        writer.flush()?;
+
+        Ok((mesh, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Cursor, mem};
+
+    use glam::{Vec2, Vec3};
+
+    use crate::{
+        index_packing::IndexFormat,
+        mesh::{ImportOptions, Mesh, Meshlet, MeshletBuildConfig},
+        mesh_cache::{cache_path, CacheCompression, CacheKey, CacheStatus, Header},
+        morph::{MorphDelta, MorphTarget},
+        primitives::Primitive,
+    };
+
+    const TRIANGLE_OBJ: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 \
+                                1\nf 1/1/1 2/2/1 3/3/1\n";
+
+    fn test_mesh(name: &str) -> (std::path::PathBuf, Mesh) {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, TRIANGLE_OBJ).unwrap();
+
+        let mesh = Mesh::new(
+            &path,
+            &ImportOptions::default(),
+            &MeshletBuildConfig::default(),
+        )
+        .unwrap();
+        (path, mesh)
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let (_, mut mesh) = test_mesh("cache_round_trip.obj");
+        mesh.morph_targets.push(MorphTarget {
+            name: None,
+            deltas: vec![
+                MorphDelta {
+                    position: Vec3::Z,
+                    normal: Vec3::X,
+                };
+                mesh.vertices.len()
+            ],
+        });
+        let key = CacheKey::new(
+            TRIANGLE_OBJ.as_bytes(),
+            &ImportOptions::default(),
+            &MeshletBuildConfig::default(),
+        );
+
+        let mut bytes = Vec::new();
+        mesh.write_cache(&mut bytes, &key, CacheCompression::None)
+            .unwrap();
+
+        let cached = Mesh::read_cache(&mut Cursor::new(bytes), &key)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&cached.vertices),
+            bytemuck::cast_slice::<_, u8>(&mesh.vertices)
+        );
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&cached.meshlets),
+            bytemuck::cast_slice::<_, u8>(&mesh.meshlets)
+        );
+        assert_eq!(cached.meshlet_data, mesh.meshlet_data);
+        assert_eq!(cached.submeshes, mesh.submeshes);
+        assert_eq!(cached.lods, mesh.lods);
+        assert_eq!(cached.materials.len(), mesh.materials.len());
+        assert_eq!(cached.morph_targets, mesh.morph_targets);
+    }
+
+    #[test]
+    fn compressed_cache_round_trip() {
+        // Each triangle rotated to start at its smallest index, per meshlet, since the index
+        // codec may rotate triangles and the strip format may reorder them.
+        let triangles = |mesh: &Mesh| {
+            mesh.meshlets
+                .iter()
+                .map(|meshlet| {
+                    let mut triangles: Vec<[u32; 3]> = meshlet
+                        .indices(mesh.index_format, &mesh.meshlet_data)
+                        .unwrap()
+                        .chunks(3)
+                        .map(|triangle| {
+                            let first = (0..3).min_by_key(|i| triangle[*i]).unwrap();
+                            [0, 1, 2].map(|i| triangle[(first + i) % 3])
+                        })
+                        .collect();
+                    triangles.sort_unstable();
+                    triangles
+                })
+                .collect::<Vec<_>>()
+        };
+        let without_offsets = |mesh: &Mesh| {
+            mesh.meshlets
+                .iter()
+                .map(|meshlet| {
+                    Meshlet {
+                        data_offset: 0,
+                        ..*meshlet
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for index_format in IndexFormat::ALL {
+            let config = MeshletBuildConfig {
+                index_format,
+                ..MeshletBuildConfig::new(32, 32, 0.0).unwrap()
+            };
+            let mut mesh = Primitive::plane(Vec2::splat(4.0), 16)
+                .to_mesh(&config)
+                .unwrap();
+            mesh.morph_targets.push(MorphTarget {
+                name: None,
+                deltas: mesh
+                    .vertices
+                    .iter()
+                    .map(|vertex| {
+                        MorphDelta {
+                            position: Vec3::Y * vertex.position.x,
+                            normal: Vec3::ZERO,
+                        }
+                    })
+                    .collect(),
+            });
+            let key = CacheKey::new(b"plane", &ImportOptions::default(), &config);
+
+            let mut sizes = Vec::new();
+            for compression in CacheCompression::ALL {
+                let mut bytes = Vec::new();
+                mesh.write_cache(&mut bytes, &key, compression).unwrap();
+                sizes.push(bytes.len());
+
+                let cached = Mesh::read_cache(&mut Cursor::new(bytes), &key)
+                    .unwrap()
+                    .unwrap();
+                cached.validate().unwrap();
+
+                assert_eq!(
+                    bytemuck::cast_slice::<_, u8>(&cached.vertices),
+                    bytemuck::cast_slice::<_, u8>(&mesh.vertices),
+                    "{compression:?}"
+                );
+                assert_eq!(
+                    bytemuck::cast_slice::<_, u8>(&without_offsets(&cached)),
+                    bytemuck::cast_slice::<_, u8>(&without_offsets(&mesh)),
+                    "{compression:?}"
+                );
+                assert_eq!(triangles(&cached), triangles(&mesh), "{compression:?}");
+                assert_eq!(cached.submeshes, mesh.submeshes);
+                assert_eq!(cached.lods, mesh.lods);
+                assert_eq!(cached.index_format, index_format);
+                assert_eq!(cached.morph_targets, mesh.morph_targets);
+            }
+
+            assert!(sizes[2] < sizes[0], "{index_format:?}: {sizes:?}");
+
+            // Counts that the sections cannot hold are an error before anything is decoded.
+            let mut bytes = Vec::new();
+            mesh.write_cache(&mut bytes, &key, CacheCompression::Meshopt)
+                .unwrap();
+            let header: &mut Header =
+                bytemuck::from_bytes_mut(&mut bytes[..mem::size_of::<Header>()]);
+            header.vertex_count = u64::MAX / 2;
+            assert!(Mesh::read_cache(&mut Cursor::new(&bytes), &key).is_err());
+        }
+    }
+
+    #[test]
+    fn stale_cache_is_rejected() {
+        let (_, mesh) = test_mesh("stale_cache_is_rejected.obj");
+        let options = ImportOptions::default();
+        let config = MeshletBuildConfig::default();
+        let key = CacheKey::new(TRIANGLE_OBJ.as_bytes(), &options, &config);
+
+        let mut bytes = Vec::new();
+        mesh.write_cache(&mut bytes, &key, CacheCompression::None)
+            .unwrap();
+
+        let changed_source = CacheKey::new(b"v 0 0 0", &options, &config);
+        let changed_config = CacheKey::new(
+            TRIANGLE_OBJ.as_bytes(),
+            &options,
+            &MeshletBuildConfig::new(128, 128, 0.0).unwrap(),
+        );
+
+        assert!(Mesh::read_cache(&mut Cursor::new(&bytes), &changed_source)
+            .unwrap()
+            .is_none());
+        assert!(Mesh::read_cache(&mut Cursor::new(&bytes), &changed_config)
+            .unwrap()
+            .is_none());
+
+        // A truncated cache with huge counts is an error, not an allocation failure.
+        let mut truncated = bytes[..mem::size_of::<Header>()].to_vec();
+        let header: &mut Header = bytemuck::from_bytes_mut(&mut truncated);
+        header.vertex_count = u64::MAX / 2;
+        header.morph_target_count = u32::MAX;
+        assert!(Mesh::read_cache(&mut Cursor::new(&truncated), &key).is_err());
+        let header: &mut Header = bytemuck::from_bytes_mut(&mut truncated);
+        header.vertex_count = 0;
+        header.meshlet_count = 0;
+        header.meshlet_data_count = 0;
+        header.submesh_count = 0;
+        header.lod_count = 0;
+        header.morph_target_count = 0;
+        header.material_count = u64::MAX;
+        assert!(Mesh::read_cache(&mut Cursor::new(&truncated), &key).is_err());
+        // Empty morph targets take no bytes, so only their count can be checked.
+        let header: &mut Header = bytemuck::from_bytes_mut(&mut truncated);
+        header.material_count = 0;
+        header.morph_target_count = u32::MAX;
+        assert!(Mesh::read_cache(&mut Cursor::new(&truncated), &key).is_err());
+
+        bytes[0] = b'X';
+        assert!(Mesh::read_cache(&mut Cursor::new(&bytes), &key).is_err());
+    }
+
+    #[test]
+    fn load_writes_and_invalidates_cache() {
+        let (path, _) = test_mesh("load_writes_and_invalidates_cache.obj");
+        let options = ImportOptions::default();
+        let config = MeshletBuildConfig::default();
+        let _ = fs::remove_file(cache_path(&path));
+
+        let (mesh, report) = Mesh::load(&path, &options, &config).unwrap();
+        assert!(cache_path(&path).exists());
+        assert_eq!(report.cache, CacheStatus::Missing);
+        assert!(report.import_log.unwrap().is_clean());
+        assert_eq!(mesh.meshlets.len(), 1);
+
+        let (_, report) = Mesh::load(&path, &options, &config).unwrap();
+        assert_eq!(report.cache, CacheStatus::Hit);
+        assert_eq!(report.import_log, None);
+
+        fs::write(
+            &path,
+            format!("{TRIANGLE_OBJ}v 1 1 0\nvt 1 1\nf 2/2/1 4/4/1 3/3/1\n"),
+        )
+        .unwrap();
+
+        let (mesh, report) = Mesh::load(&path, &options, &config).unwrap();
+        assert_eq!(report.cache, CacheStatus::Stale);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.meshlets[0].triangle_count, 2);
+    }
+}
@@ -0,0 +1,343 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use glam::{I64Vec3, Vec3};
+use serde::Serialize;
+
+/// What the repair pass of an import found and fixed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ImportLog {
+    /// Positions merged into another position within the weld tolerance.
+    pub welded_positions: usize,
+    /// Removed triangles with two corners at the same welded position.
+    pub degenerate_triangles: usize,
+    /// Removed triangles covering the same welded positions with the same winding as an earlier
+    /// one.
+    pub duplicate_triangles: usize,
+    /// Edges shared by more than two triangles. Kept as is.
+    pub non_manifold_edges: usize,
+    /// Triangles wound against the majority of their connected surface. Kept as is.
+    pub flipped_triangles: usize,
+}
+
+impl ImportLog {
+    /// Whether the mesh was imported without any change or warning.
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for ImportLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "welded {} positions, removed {} degenerate and {} duplicate triangles, found {} \
+             non-manifold edges and {} flipped triangles",
+            self.welded_positions,
+            self.degenerate_triangles,
+            self.duplicate_triangles,
+            self.non_manifold_edges,
+            self.flipped_triangles
+        )
+    }
+}
+
+/// Result of [`repair_triangles`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepairedTriangles {
+    /// Three position indices per input triangle, each pointing at the first position of its weld
+    /// group.
+    pub position_indices: Vec<u32>,
+    /// Input triangles that are kept, in their original order.
+    pub triangles: Vec<usize>,
+    pub log: ImportLog,
+}
+
+/// Welds positions closer than `weld_tolerance`, drops degenerate and duplicate triangles and
+/// checks the topology of what is left. Positions that no triangle references are ignored.
+pub fn repair_triangles(
+    positions: &[Vec3],
+    position_indices: &[u32],
+    weld_tolerance: f32,
+) -> RepairedTriangles {
+    let (position_indices, welded_positions) =
+        weld_positions(positions, position_indices, weld_tolerance);
+    let mut log = ImportLog {
+        welded_positions,
+        ..Default::default()
+    };
+
+    let mut unique = HashSet::new();
+    let triangles: Vec<usize> = (0..position_indices.len() / 3)
+        .filter(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|corner| position_indices[3 * triangle + corner]);
+            if a == b || b == c || c == a {
+                log.degenerate_triangles += 1;
+                return false;
+            }
+
+            // Rotated to start at the smallest index, which keeps the winding.
+            let key = match a.min(b).min(c) {
+                min if min == a => [a, b, c],
+                min if min == b => [b, c, a],
+                _ => [c, a, b],
+            };
+            if !unique.insert(key) {
+                log.duplicate_triangles += 1;
+                return false;
+            }
+
+            true
+        })
+        .collect();
+
+    let kept_indices: Vec<u32> = triangles
+        .iter()
+        .flat_map(|triangle| {
+            position_indices[3 * triangle..3 * triangle + 3]
+                .iter()
+                .copied()
+        })
+        .collect();
+    (log.non_manifold_edges, log.flipped_triangles) = check_topology(&kept_indices);
+
+    RepairedTriangles {
+        position_indices,
+        triangles,
+        log,
+    }
+}
+
+/// Maps every referenced position to the first referenced position within `tolerance` of it,
+/// using a grid with cells the size of the tolerance. Returns the new indices and how many
+/// positions were merged.
+fn weld_positions(positions: &[Vec3], indices: &[u32], tolerance: f32) -> (Vec<u32>, usize) {
+    // Cells are computed in f64 and i64, so only positions near the end of the f32 range saturate.
+    // Those share a cell and are still welded by their distance.
+    let cell = |position: Vec3| {
+        if tolerance > 0.0 {
+            (position.as_dvec3() / f64::from(tolerance))
+                .floor()
+                .as_i64vec3()
+        } else {
+            // Only identical positions are welded.
+            I64Vec3::from_array(
+                position
+                    .to_array()
+                    .map(|component| i64::from(component.to_bits())),
+            )
+        }
+    };
+    let neighbours = if tolerance > 0.0 { -1..=1 } else { 0..=0 };
+
+    let mut remap = vec![u32::MAX; positions.len()];
+    let mut grid: HashMap<I64Vec3, Vec<u32>> = HashMap::new();
+    let mut welded = 0;
+
+    for index in indices {
+        if remap[*index as usize] != u32::MAX {
+            continue;
+        }
+
+        let position = positions[*index as usize];
+        let center = cell(position);
+        let existing = neighbours
+            .clone()
+            .flat_map(|x| neighbours.clone().map(move |y| (x, y)))
+            .flat_map(|(x, y)| neighbours.clone().map(move |z| I64Vec3::new(x, y, z)))
+            // Wrapping, as the cells of saturated positions have no neighbours past them.
+            .map(|offset| {
+                I64Vec3::new(
+                    center.x.wrapping_add(offset.x),
+                    center.y.wrapping_add(offset.y),
+                    center.z.wrapping_add(offset.z),
+                )
+            })
+            .filter_map(|cell| grid.get(&cell))
+            .flatten()
+            .find(|other| positions[**other as usize].distance(position) <= tolerance);
+
+        remap[*index as usize] = match existing {
+            Some(other) => {
+                welded += 1;
+                *other
+            }
+            None => {
+                grid.entry(center).or_default().push(*index);
+                *index
+            }
+        };
+    }
+
+    (
+        indices.iter().map(|index| remap[*index as usize]).collect(),
+        welded,
+    )
+}
+
+/// Counts the edges shared by more than two triangles, and the triangles whose winding disagrees
+/// with the majority of the surface they are connected to over manifold edges.
+fn check_topology(indices: &[u32]) -> (usize, usize) {
+    let triangle_count = indices.len() / 3;
+
+    // Triangles of every undirected edge, and whether they run along it from the smaller index.
+    let mut edges: HashMap<(u32, u32), Vec<(usize, bool)>> = HashMap::new();
+    for triangle in 0..triangle_count {
+        for corner in 0..3 {
+            let from = indices[3 * triangle + corner];
+            let to = indices[3 * triangle + (corner + 1) % 3];
+            edges
+                .entry((from.min(to), from.max(to)))
+                .or_default()
+                .push((triangle, from < to));
+        }
+    }
+
+    let non_manifold_edges = edges.values().filter(|edge| edge.len() > 2).count();
+
+    // Consistently wound neighbours run along their shared edge in opposite directions.
+    let mut neighbours = vec![Vec::new(); triangle_count];
+    for edge in edges.values() {
+        if let [(a, a_forward), (b, b_forward)] = edge[..] {
+            let flipped = a_forward == b_forward;
+            neighbours[a].push((b, flipped));
+            neighbours[b].push((a, flipped));
+        }
+    }
+
+    // Flood fill every connected surface, assigning each triangle its winding relative to the
+    // first one.
+    let mut flipped = vec![None; triangle_count];
+    let mut flipped_triangles = 0;
+    for start in 0..triangle_count {
+        if flipped[start].is_some() {
+            continue;
+        }
+
+        flipped[start] = Some(false);
+        let mut stack = vec![start];
+        let mut counts = [1, 0];
+        while let Some(triangle) = stack.pop() {
+            let triangle_flipped = flipped[triangle].unwrap();
+            for (neighbour, edge_flipped) in &neighbours[triangle] {
+                if flipped[*neighbour].is_none() {
+                    let neighbour_flipped = triangle_flipped != *edge_flipped;
+                    flipped[*neighbour] = Some(neighbour_flipped);
+                    counts[neighbour_flipped as usize] += 1;
+                    stack.push(*neighbour);
+                }
+            }
+        }
+
+        flipped_triangles += counts[0].min(counts[1]);
+    }
+
+    (non_manifold_edges, flipped_triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::mesh_repair::{repair_triangles, ImportLog};
+
+    /// A 3x3 grid of positions in the XY plane.
+    fn grid() -> Vec<Vec3> {
+        (0..9)
+            .map(|i| Vec3::new((i % 3) as f32, (i / 3) as f32, 0.0))
+            .collect()
+    }
+
+    /// Two triangles per cell of [`grid`], all wound counter-clockwise.
+    fn grid_indices() -> Vec<u32> {
+        let mut indices = Vec::new();
+        for y in 0..2 {
+            for x in 0..2 {
+                let corner = |dx: u32, dy: u32| (y + dy) * 3 + x + dx;
+                indices.extend([corner(0, 0), corner(1, 0), corner(1, 1)]);
+                indices.extend([corner(0, 0), corner(1, 1), corner(0, 1)]);
+            }
+        }
+        indices
+    }
+
+    #[test]
+    fn clean_grid() {
+        let repaired = repair_triangles(&grid(), &grid_indices(), 1e-5);
+
+        assert!(repaired.log.is_clean(), "{}", repaired.log);
+        assert_eq!(repaired.triangles, (0..8).collect::<Vec<_>>());
+        assert_eq!(repaired.position_indices, grid_indices());
+    }
+
+    #[test]
+    fn noisy_seam_is_welded() {
+        // The right column of cells uses its own copies of the middle column, slightly off.
+        let mut positions = grid();
+        let copies: Vec<u32> = [1, 4, 7]
+            .map(|original| {
+                positions.push(positions[original as usize] + Vec3::new(3e-6, -2e-6, 1e-6));
+                positions.len() as u32 - 1
+            })
+            .to_vec();
+        let indices: Vec<u32> = grid_indices()
+            .chunks(3)
+            .enumerate()
+            .flat_map(|(triangle, corners)| {
+                let right = triangle % 4 >= 2;
+                corners
+                    .iter()
+                    .map(|index| {
+                        match [1, 4, 7].iter().position(|original| original == index) {
+                            Some(copy) if right => copies[copy],
+                            _ => *index,
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let unwelded = repair_triangles(&positions, &indices, 0.0);
+        assert_eq!(unwelded.log.welded_positions, 0);
+
+        let welded = repair_triangles(&positions, &indices, 1e-5);
+        assert_eq!(welded.log.welded_positions, 3);
+        assert_eq!(welded.position_indices, grid_indices());
+
+        // Far from the origin the cells don't fit in 32 bits anymore.
+        let far: Vec<Vec3> = positions
+            .iter()
+            .map(|position| *position + Vec3::splat(1e6))
+            .collect();
+        let welded = repair_triangles(&far, &indices, 1e-5);
+        assert_eq!(welded.log.welded_positions, 3);
+        assert_eq!(welded.position_indices, grid_indices());
+    }
+
+    #[test]
+    fn broken_triangles() {
+        let mut indices = grid_indices();
+        // A degenerate triangle, a duplicate and the back face of the first triangle, which adds
+        // a third triangle to two of its edges.
+        indices.extend([0, 1, 1]);
+        indices.extend([1, 2, 5]);
+        indices.extend([4, 1, 0]);
+        // Flip the winding of the last triangle of the grid.
+        indices.swap(22, 23);
+
+        let repaired = repair_triangles(&grid(), &indices, 1e-5);
+        assert_eq!(
+            repaired.log,
+            ImportLog {
+                welded_positions: 0,
+                degenerate_triangles: 1,
+                duplicate_triangles: 1,
+                non_manifold_edges: 2,
+                flipped_triangles: 1,
+            }
+        );
+        assert_eq!(repaired.triangles, [0, 1, 2, 3, 4, 5, 6, 7, 10]);
+    }
+}
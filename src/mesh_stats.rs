@@ -0,0 +1,264 @@
+use std::{fmt, mem};
+
+use anyhow::Result;
+use meshopt::VertexDataAdapter;
+use serde::Serialize;
+
+use crate::mesh::{Mesh, Meshlet, MeshletBuildConfig, Vertex};
+
+/// Cache size of the vertex cache model, the same generic FIFO meshoptimizer's demo analyzes with.
+const VERTEX_CACHE_SIZE: u32 = 16;
+
+/// Quality statistics of the full resolution LOD of a [`Mesh`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MeshStats {
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    pub meshlet_count: usize,
+    /// Average cache miss ratio, transformed vertices per triangle. 0.5 is the optimum for grids.
+    pub acmr: f32,
+    /// Average transformed vertex ratio, transformed vertices per vertex. 1.0 is the optimum.
+    pub atvr: f32,
+    /// Shaded pixels per covered pixel, averaged over several view directions.
+    pub overdraw: f32,
+    /// Fetched vertex bytes per vertex buffer byte. 1.0 means every vertex is fetched once.
+    pub overfetch: f32,
+    /// Average meshlet vertex count relative to [`MeshletBuildConfig::max_vertices`].
+    pub meshlet_vertex_fill: f32,
+    /// Average meshlet triangle count relative to [`MeshletBuildConfig::max_triangles`].
+    pub meshlet_triangle_fill: f32,
+    pub meshlet_radius: Distribution,
+    /// Average distance between the centers of consecutive meshlets of a submesh, in average
+    /// meshlet radii. Lower means neighbouring meshlets tend to be culled together.
+    pub meshlet_center_step: f32,
+    /// Average number of vertices a meshlet shares with the previous meshlet of its submesh.
+    pub meshlet_shared_vertices: f32,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Distribution {
+    pub min: f32,
+    pub p50: f32,
+    pub p90: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl Distribution {
+    pub fn new(mut values: Vec<f32>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+
+        values.sort_unstable_by(f32::total_cmp);
+        let percentile = |p: f32| values[((values.len() - 1) as f32 * p).round() as usize];
+
+        Self {
+            min: values[0],
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f32>() / values.len() as f32,
+        }
+    }
+}
+
+impl MeshStats {
+    pub fn new(mesh: &Mesh, config: &MeshletBuildConfig) -> Result<Self> {
+        let meshlets: Vec<_> = mesh
+            .lod_submeshes(0)
+            .iter()
+            .flat_map(|submesh| {
+                let start = submesh.meshlet_offset as usize;
+                &mesh.meshlets[start..start + submesh.meshlet_count as usize]
+            })
+            .collect();
+
+        let indices: Vec<u32> = meshlets
+            .iter()
+            .map(|meshlet| meshlet.indices(mesh.index_format, &mesh.meshlet_data))
+            .collect::<Result<Vec<_>>>()?
+            .concat();
+        let triangle_count = indices.len() / 3;
+
+        let vertex_cache =
+            meshopt::analyze_vertex_cache(&indices, mesh.vertices.len(), VERTEX_CACHE_SIZE, 0, 0);
+        let overdraw = meshopt::analyze_overdraw(
+            &indices,
+            &VertexDataAdapter::new(
+                bytemuck::cast_slice(&mesh.vertices),
+                mem::size_of::<Vertex>(),
+                0,
+            )?,
+        );
+        let vertex_fetch =
+            meshopt::analyze_vertex_fetch(&indices, mesh.vertices.len(), mem::size_of::<Vertex>());
+
+        let meshlet_count = meshlets.len().max(1) as f32;
+        let vertex_fill = meshlets
+            .iter()
+            .map(|meshlet| meshlet.vertex_count as f32 / config.max_vertices as f32)
+            .sum::<f32>()
+            / meshlet_count;
+        let triangle_fill = meshlets
+            .iter()
+            .map(|meshlet| meshlet.triangle_count as f32 / config.max_triangles as f32)
+            .sum::<f32>()
+            / meshlet_count;
+
+        let (center_step, shared_vertices) = meshlet_coherence(mesh);
+
+        Ok(Self {
+            vertex_count: mesh.vertices.len(),
+            triangle_count,
+            meshlet_count: meshlets.len(),
+            acmr: vertex_cache.acmr,
+            atvr: vertex_cache.atvr,
+            overdraw: overdraw.overdraw,
+            overfetch: vertex_fetch.overfetch,
+            meshlet_vertex_fill: vertex_fill,
+            meshlet_triangle_fill: triangle_fill,
+            meshlet_radius: Distribution::new(
+                meshlets.iter().map(|meshlet| meshlet.radius).collect(),
+            ),
+            meshlet_center_step: center_step,
+            meshlet_shared_vertices: shared_vertices,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Center step and shared vertices of consecutive meshlets of the full resolution LOD, see
+/// [`MeshStats::meshlet_center_step`] and [`MeshStats::meshlet_shared_vertices`].
+fn meshlet_coherence(mesh: &Mesh) -> (f32, f32) {
+    let meshlet_vertices = |meshlet: &Meshlet| {
+        let start = meshlet.data_offset as usize;
+        &mesh.meshlet_data[start..start + meshlet.vertex_count as usize]
+    };
+
+    let (mut radius, mut meshlet_count) = (0.0, 0);
+    let (mut center_step, mut shared_vertices, mut pair_count) = (0.0, 0, 0);
+    for submesh in mesh.lod_submeshes(0) {
+        let start = submesh.meshlet_offset as usize;
+        let meshlets = &mesh.meshlets[start..start + submesh.meshlet_count as usize];
+
+        radius += meshlets.iter().map(|meshlet| meshlet.radius).sum::<f32>();
+        meshlet_count += meshlets.len();
+        for pair in meshlets.windows(2) {
+            center_step += pair[0].center.distance(pair[1].center);
+
+            let previous = meshlet_vertices(&pair[0]);
+            shared_vertices += meshlet_vertices(&pair[1])
+                .iter()
+                .filter(|vertex| previous.contains(vertex))
+                .count();
+            pair_count += 1;
+        }
+    }
+
+    if pair_count == 0 {
+        return (0.0, 0.0);
+    }
+
+    let mean_radius = radius / meshlet_count as f32;
+    (
+        center_step / pair_count as f32 / mean_radius.max(f32::MIN_POSITIVE),
+        shared_vertices as f32 / pair_count as f32,
+    )
+}
+
+impl fmt::Display for MeshStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} vertices, {} triangles, {} meshlets",
+            self.vertex_count, self.triangle_count, self.meshlet_count
+        )?;
+        writeln!(f, "ACMR {:.3}, ATVR {:.3}", self.acmr, self.atvr)?;
+        writeln!(
+            f,
+            "overdraw {:.3}, overfetch {:.3}",
+            self.overdraw, self.overfetch
+        )?;
+        writeln!(
+            f,
+            "meshlet fill {:.1}% vertices, {:.1}% triangles",
+            self.meshlet_vertex_fill * 100.0,
+            self.meshlet_triangle_fill * 100.0
+        )?;
+        let radius = &self.meshlet_radius;
+        writeln!(
+            f,
+            "meshlet radius min {:.4}, p50 {:.4}, p90 {:.4}, max {:.4}, mean {:.4}",
+            radius.min, radius.p50, radius.p90, radius.max, radius.mean
+        )?;
+        write!(
+            f,
+            "meshlet order center step {:.2} radii, {:.1} shared vertices",
+            self.meshlet_center_step, self.meshlet_shared_vertices
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use crate::{
+        mesh::{Mesh, MeshletBuildConfig, Vertex},
+        mesh_stats::{Distribution, MeshStats},
+    };
+
+    #[test]
+    fn distribution() {
+        let distribution = Distribution::new((1..=10).rev().map(|value| value as f32).collect());
+
+        assert_eq!(distribution.min, 1.0);
+        assert_eq!(distribution.p50, 6.0);
+        assert_eq!(distribution.p90, 9.0);
+        assert_eq!(distribution.max, 10.0);
+        assert_eq!(distribution.mean, 5.5);
+        assert_eq!(Distribution::new(Vec::new()), Distribution::default());
+    }
+
+    #[test]
+    fn grid_stats() {
+        let size = 16;
+        let vertices: Vec<Vertex> = (0..=size)
+            .flat_map(|y| {
+                (0..=size).map(move |x| {
+                    Vertex::new(Vec3::new(x as f32, y as f32, 0.0), Vec2::ZERO, Vec3::Z)
+                })
+            })
+            .collect();
+        let mut indices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let corner = |dx: u32, dy: u32| (y + dy) * (size + 1) + x + dx;
+                indices.extend([corner(0, 0), corner(1, 0), corner(1, 1)]);
+                indices.extend([corner(0, 0), corner(1, 1), corner(0, 1)]);
+            }
+        }
+        let config = MeshletBuildConfig::default();
+        let mesh = Mesh::from_vertices(&vertices, Some(&indices), &config).unwrap();
+
+        let stats = MeshStats::new(&mesh, &config).unwrap();
+        assert_eq!(stats.vertex_count, 17 * 17);
+        assert_eq!(stats.triangle_count, 2 * 16 * 16);
+        assert_eq!(stats.meshlet_count, mesh.meshlets.len());
+        assert!(stats.acmr >= 0.5 && stats.acmr <= 3.0);
+        assert!(stats.atvr >= 1.0);
+        assert!(stats.meshlet_vertex_fill > 0.0 && stats.meshlet_vertex_fill <= 1.0);
+        assert!(stats.meshlet_triangle_fill > 0.0 && stats.meshlet_triangle_fill <= 1.0);
+        assert!(stats.meshlet_radius.min > 0.0);
+        assert!(stats.meshlet_radius.max >= stats.meshlet_radius.p90);
+
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json().unwrap()).unwrap();
+        assert_eq!(json["triangle_count"], 512);
+        assert!(json["meshlet_radius"]["p50"].is_number());
+        assert!(stats.to_string().contains("512 triangles"));
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+
+use crate::mesh::{Mesh, MAX_MESHLET_TRIANGLES, MAX_MESHLET_VERTICES};
+
+/// Issues beyond this are only counted, a broken mesh easily has thousands.
+const MAX_REPORTED_ISSUES: usize = 32;
+
+impl Mesh {
+    /// Checks that meshlets, submeshes and LODs only reference data that exists, that meshlets do
+    /// not share data, that no triangle is degenerate and that every morph target has a delta per
+    /// vertex. The error lists every issue found.
+    pub fn validate(&self) -> Result<()> {
+        let mut issues = Vec::new();
+
+        self.validate_meshlets(&mut issues);
+        self.validate_submeshes(&mut issues);
+        for (index, target) in self.morph_targets.iter().enumerate() {
+            if target.deltas.len() != self.vertices.len() {
+                issues.push(format!(
+                    "Morph target {index} has {} deltas for {} vertices",
+                    target.deltas.len(),
+                    self.vertices.len()
+                ));
+            }
+        }
+
+        if issues.is_empty() {
+            return Ok(());
+        }
+
+        let mut report = format!("Invalid mesh with {} issues:", issues.len());
+        for issue in issues.iter().take(MAX_REPORTED_ISSUES) {
+            write!(report, "\n  {issue}")?;
+        }
+        if issues.len() > MAX_REPORTED_ISSUES {
+            write!(
+                report,
+                "\n  ... and {} more",
+                issues.len() - MAX_REPORTED_ISSUES
+            )?;
+        }
+
+        bail!(report)
+    }
+
+    fn validate_meshlets(&self, issues: &mut Vec<String>) {
+        let mut data_ranges = Vec::with_capacity(self.meshlets.len());
+
+        for (index, meshlet) in self.meshlets.iter().enumerate() {
+            if meshlet.vertex_count as usize > MAX_MESHLET_VERTICES {
+                issues.push(format!(
+                    "Meshlet {index} has {} vertices, more than {MAX_MESHLET_VERTICES}",
+                    meshlet.vertex_count
+                ));
+            }
+            if meshlet.triangle_count as usize > MAX_MESHLET_TRIANGLES {
+                issues.push(format!(
+                    "Meshlet {index} has {} triangles, more than {MAX_MESHLET_TRIANGLES}",
+                    meshlet.triangle_count
+                ));
+            }
+            if meshlet.triangle_count == 0 {
+                issues.push(format!("Meshlet {index} has no triangles"));
+            }
+
+            // In usize, a corrupt offset close to `u32::MAX` must not overflow.
+            let triangle_offset = meshlet.data_offset as usize + meshlet.vertex_count as usize;
+            let Some(packed_len) = self
+                .meshlet_data
+                .get(triangle_offset..)
+                .and_then(|packed| self.index_format.packed_len(packed, meshlet.triangle_count))
+            else {
+                issues.push(format!(
+                    "Meshlet {index} data at {} is out of range for {} meshlet data",
+                    meshlet.data_offset,
+                    self.meshlet_data.len()
+                ));
+                continue;
+            };
+            data_ranges.push((
+                meshlet.data_offset as usize..triangle_offset + packed_len,
+                index,
+            ));
+
+            let data = &self.meshlet_data[meshlet.data_offset as usize..];
+            for (local_index, vertex) in data[..meshlet.vertex_count as usize].iter().enumerate() {
+                if *vertex as usize >= self.vertices.len() {
+                    issues.push(format!(
+                        "Meshlet {index} vertex {local_index} references vertex {vertex} out of \
+                         range for {} vertices",
+                        self.vertices.len()
+                    ));
+                }
+            }
+
+            let local_indices = match self.index_format.unpack(
+                &self.meshlet_data[triangle_offset..],
+                meshlet.triangle_count,
+            ) {
+                Ok(local_indices) => local_indices,
+                Err(e) => {
+                    issues.push(format!("Meshlet {index} triangles are invalid: {e}"));
+                    continue;
+                }
+            };
+            for (triangle, corners) in local_indices.chunks(3).enumerate() {
+                if let Some(corner) = corners
+                    .iter()
+                    .find(|corner| **corner >= meshlet.vertex_count)
+                {
+                    issues.push(format!(
+                        "Meshlet {index} triangle {triangle} references local vertex {corner} out \
+                         of range for {} vertices",
+                        meshlet.vertex_count
+                    ));
+                } else if corners[0] == corners[1]
+                    || corners[1] == corners[2]
+                    || corners[2] == corners[0]
+                {
+                    issues.push(format!(
+                        "Meshlet {index} triangle {triangle} is degenerate {corners:?}"
+                    ));
+                }
+            }
+        }
+
+        data_ranges.sort_by_key(|(range, _)| range.start);
+        for pair in data_ranges.windows(2) {
+            let ((previous_range, previous), (range, index)) = (&pair[0], &pair[1]);
+            if range.start < previous_range.end {
+                issues.push(format!(
+                    "Meshlet {index} data {range:?} overlaps meshlet {previous} data \
+                     {previous_range:?}"
+                ));
+            }
+        }
+    }
+
+    fn validate_submeshes(&self, issues: &mut Vec<String>) {
+        for (index, submesh) in self.submeshes.iter().enumerate() {
+            let end = submesh.meshlet_offset as usize + submesh.meshlet_count as usize;
+            if end > self.meshlets.len() {
+                issues.push(format!(
+                    "Submesh {index} meshlets {}..{end} are out of range for {} meshlets",
+                    submesh.meshlet_offset,
+                    self.meshlets.len()
+                ));
+            }
+            if submesh.material as usize >= self.materials.len() {
+                issues.push(format!(
+                    "Submesh {index} material {} is out of range for {} materials",
+                    submesh.material,
+                    self.materials.len()
+                ));
+            }
+        }
+
+        if self.lods.is_empty() {
+            issues.push("Mesh has no LODs".to_string());
+        }
+        for (index, lod) in self.lods.iter().enumerate() {
+            let end = lod.submesh_offset as usize + lod.submesh_count as usize;
+            if end > self.submeshes.len() {
+                issues.push(format!(
+                    "LOD {index} submeshes {}..{end} are out of range for {} submeshes",
+                    lod.submesh_offset,
+                    self.submeshes.len()
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use crate::mesh::{Mesh, MeshletBuildConfig, Vertex};
+
+    fn quad() -> Mesh {
+        let vertices = [
+            Vertex::new(Vec3::new(0.0, 0.0, 0.0), Vec2::ZERO, Vec3::Z),
+            Vertex::new(Vec3::new(1.0, 0.0, 0.0), Vec2::ZERO, Vec3::Z),
+            Vertex::new(Vec3::new(1.0, 1.0, 0.0), Vec2::ZERO, Vec3::Z),
+            Vertex::new(Vec3::new(0.0, 1.0, 0.0), Vec2::ZERO, Vec3::Z),
+        ];
+        let config = MeshletBuildConfig::new(3, 4, 0.0).unwrap();
+
+        Mesh::from_vertices(&vertices, Some(&[0, 1, 2, 0, 2, 3]), &config).unwrap()
+    }
+
+    fn validation_error(mesh: &Mesh) -> String {
+        mesh.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn valid_mesh() {
+        quad().validate().unwrap();
+    }
+
+    #[test]
+    fn out_of_range_references() {
+        let mut mesh = quad();
+        let vertex_offset = mesh.meshlets[0].data_offset as usize;
+        mesh.meshlet_data[vertex_offset] = 100;
+        assert!(validation_error(&mesh).contains("references vertex 100 out of range"));
+
+        let mut mesh = quad();
+        let triangle_offset =
+            (mesh.meshlets[0].data_offset + mesh.meshlets[0].vertex_count) as usize;
+        mesh.meshlet_data[triangle_offset] = 0x00_01_02_05;
+        assert!(validation_error(&mesh).contains("references local vertex 5 out of range"));
+
+        let mut mesh = quad();
+        mesh.meshlets[1].data_offset = mesh.meshlet_data.len() as u32;
+        assert!(validation_error(&mesh).contains("out of range for 8 meshlet data"));
+
+        let mut mesh = quad();
+        mesh.meshlets[1].data_offset = u32::MAX;
+        assert!(validation_error(&mesh).contains("out of range for 8 meshlet data"));
+
+        let mut mesh = quad();
+        mesh.submeshes[0].meshlet_count = 3;
+        mesh.submeshes[0].material = 1;
+        let error = validation_error(&mesh);
+        assert!(error.contains("2 issues"));
+        assert!(error.contains("meshlets 0..3 are out of range"));
+        assert!(error.contains("material 1 is out of range"));
+    }
+
+    #[test]
+    fn degenerate_and_overlapping_meshlets() {
+        let mut mesh = quad();
+        let triangle_offset =
+            (mesh.meshlets[0].data_offset + mesh.meshlets[0].vertex_count) as usize;
+        mesh.meshlet_data[triangle_offset] = 0x00_01_01_00;
+        assert!(validation_error(&mesh).contains("triangle 0 is degenerate [0, 1, 1]"));
+
+        let mut mesh = quad();
+        mesh.meshlets[1].data_offset = 1;
+        assert!(
+            validation_error(&mesh).contains("Meshlet 1 data 1..5 overlaps meshlet 0 data 0..4")
+        );
+    }
+
+    #[test]
+    fn degenerate_input_triangles_are_dropped() {
+        let vertices = [
+            Vertex::new(Vec3::ZERO, Vec2::ZERO, Vec3::Z),
+            Vertex::new(Vec3::X, Vec2::ZERO, Vec3::Z),
+            Vertex::new(Vec3::Y, Vec2::ZERO, Vec3::Z),
+        ];
+        let mesh = Mesh::from_vertices(
+            &vertices,
+            Some(&[0, 1, 2, 0, 0, 1]),
+            &MeshletBuildConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(mesh.meshlets.len(), 1);
+        assert_eq!(mesh.meshlets[0].triangle_count, 1);
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::Result;
+use glam::{UVec3, Vec3};
+
+use crate::mesh::{Mesh, Meshlet};
+
+/// Bits per axis of the grid meshlet centers are snapped to before computing their curve index.
+const CURVE_BITS: u32 = 10;
+
+/// Order of the meshlets within each submesh.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MeshletOrder {
+    /// As built by meshopt, which follows the triangle order after vertex cache optimization.
+    #[default]
+    Build,
+    /// Sorted along a Z-order curve through the meshlet centers.
+    Morton,
+    /// Sorted along a Hilbert curve through the meshlet centers. Unlike Morton order consecutive
+    /// meshlets are always in neighbouring grid cells.
+    Hilbert,
+}
+
+impl MeshletOrder {
+    pub const ALL: [Self; 3] = [Self::Build, Self::Morton, Self::Hilbert];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Build => "build",
+            Self::Morton => "morton",
+            Self::Hilbert => "hilbert",
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::Build => 0,
+            Self::Morton => 1,
+            Self::Hilbert => 2,
+        }
+    }
+
+    fn curve_index(&self, cell: UVec3) -> u64 {
+        match self {
+            Self::Build => 0,
+            Self::Morton => morton_index(cell),
+            Self::Hilbert => hilbert_index(cell),
+        }
+    }
+}
+
+impl Mesh {
+    /// Sorts the meshlets of every submesh along the curve of `order`, then lays out the meshlet
+    /// data and the vertices in the order the meshlets use them. Meshlets that are close in
+    /// memory end up close in space, so they tend to pass or fail culling together, and the
+    /// vertices of a meshlet are close in the vertex buffer.
+    pub fn reorder_meshlets(&mut self, order: MeshletOrder) -> Result<()> {
+        if order == MeshletOrder::Build || self.meshlets.is_empty() {
+            return Ok(());
+        }
+
+        let (min, max) = self.meshlets.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), meshlet| (min.min(meshlet.center), max.max(meshlet.center)),
+        );
+        let scale = ((1 << CURVE_BITS) - 1) as f32 / (max - min).max(Vec3::splat(f32::EPSILON));
+        let cell = |meshlet: &Meshlet| ((meshlet.center - min) * scale).round().as_uvec3();
+
+        for submesh in &self.submeshes {
+            let start = submesh.meshlet_offset as usize;
+            self.meshlets[start..start + submesh.meshlet_count as usize]
+                .sort_by_cached_key(|meshlet| order.curve_index(cell(meshlet)));
+        }
+
+        self.compact_meshlet_data()?;
+        self.reorder_vertices();
+
+        Ok(())
+    }
+
+    /// Copies the data of every meshlet into a new buffer in meshlet order.
+    fn compact_meshlet_data(&mut self) -> Result<()> {
+        let mut meshlet_data = Vec::with_capacity(self.meshlet_data.len());
+
+        for meshlet in &mut self.meshlets {
+            let data_len = meshlet.data_len(self.index_format, &self.meshlet_data)?;
+            let start = meshlet.data_offset as usize;
+
+            meshlet.data_offset = meshlet_data.len() as u32;
+            meshlet_data.extend_from_slice(&self.meshlet_data[start..start + data_len]);
+        }
+
+        self.meshlet_data = meshlet_data;
+
+        Ok(())
+    }
+
+    /// Renumbers the vertices in the order the meshlets first reference them. The full resolution
+    /// meshlets come first, so the LODs mostly reuse vertices from the start of the buffer.
+    fn reorder_vertices(&mut self) {
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        let mut order = Vec::with_capacity(self.vertices.len());
+
+        for meshlet in &self.meshlets {
+            let start = meshlet.data_offset as usize;
+            for vertex in &mut self.meshlet_data[start..start + meshlet.vertex_count as usize] {
+                if remap[*vertex as usize] == u32::MAX {
+                    remap[*vertex as usize] = order.len() as u32;
+                    order.push(*vertex as usize);
+                }
+                *vertex = remap[*vertex as usize];
+            }
+        }
+        // Keep vertices no meshlet references at the end, nothing is dropped.
+        order.extend((0..self.vertices.len()).filter(|vertex| remap[*vertex] == u32::MAX));
+
+        self.vertices = order.iter().map(|vertex| self.vertices[*vertex]).collect();
+        for target in &mut self.morph_targets {
+            target.deltas = order.iter().map(|vertex| target.deltas[*vertex]).collect();
+        }
+    }
+}
+
+fn morton_index(cell: UVec3) -> u64 {
+    let spread = |value: u32| {
+        (0..CURVE_BITS).fold(0u64, |spread, bit| {
+            spread | (((value >> bit) & 1) as u64) << (3 * bit)
+        })
+    };
+
+    spread(cell.x) << 2 | spread(cell.y) << 1 | spread(cell.z)
+}
+
+/// Skilling's transpose algorithm from "Programming the Hilbert curve".
+fn hilbert_index(cell: UVec3) -> u64 {
+    let mut axes = cell.to_array();
+
+    let mut q = 1 << (CURVE_BITS - 1);
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..3 {
+            if axes[i] & q != 0 {
+                axes[0] ^= p;
+            } else {
+                let t = (axes[0] ^ axes[i]) & p;
+                axes[0] ^= t;
+                axes[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+
+    for i in 1..3 {
+        axes[i] ^= axes[i - 1];
+    }
+    let mut t = 0;
+    let mut q = 1 << (CURVE_BITS - 1);
+    while q > 1 {
+        if axes[2] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for axis in &mut axes {
+        *axis ^= t;
+    }
+
+    (0..CURVE_BITS).rev().fold(0, |index, bit| {
+        axes.iter()
+            .fold(index, |index, axis| index << 1 | ((axis >> bit) & 1) as u64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{UVec3, Vec2};
+
+    use crate::{
+        mesh::{Mesh, MeshletBuildConfig},
+        mesh_stats::MeshStats,
+        meshlet_order::{hilbert_index, MeshletOrder},
+        primitives::Primitive,
+    };
+
+    #[test]
+    fn hilbert_curve_steps_to_neighbours() {
+        // The curve through the whole grid starts with the curve through its lowest 8^3 cells.
+        let size = 8;
+        let mut cells: Vec<UVec3> = (0..size * size * size)
+            .map(|i| UVec3::new(i % size, i / size % size, i / (size * size)))
+            .collect();
+        cells.sort_by_key(|cell| hilbert_index(*cell));
+
+        assert_eq!(cells[0], UVec3::ZERO);
+        assert_eq!(
+            hilbert_index(cells[cells.len() - 1]),
+            (cells.len() - 1) as u64
+        );
+        for pair in cells.windows(2) {
+            let step = (pair[0].as_ivec3() - pair[1].as_ivec3()).abs();
+            assert_eq!(step.x + step.y + step.z, 1, "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn reordering_keeps_triangles_and_improves_coherence() {
+        let config = MeshletBuildConfig::new(32, 32, 0.0).unwrap();
+        let mesh = Primitive::plane(Vec2::splat(10.0), 64)
+            .to_mesh(&config)
+            .unwrap();
+        // Interleave both halves of the meshlets as a poor starting order.
+        let mut shuffled = mesh.clone();
+        let half = mesh.meshlets.len() / 2;
+        shuffled.meshlets = (0..half)
+            .flat_map(|i| [mesh.meshlets[i], mesh.meshlets[half + i]])
+            .chain(mesh.meshlets[2 * half..].iter().copied())
+            .collect();
+        let shuffled_stats = MeshStats::new(&shuffled, &config).unwrap();
+
+        let triangles = |mesh: &Mesh| {
+            let mut triangles: Vec<[[u32; 3]; 3]> = mesh
+                .emulate_draw(0)
+                .unwrap()
+                .into_iter()
+                .map(|triangle| {
+                    triangle.map(|index| {
+                        mesh.vertices[index as usize]
+                            .position
+                            .to_array()
+                            .map(f32::to_bits)
+                    })
+                })
+                .collect();
+            triangles.sort_unstable();
+            triangles
+        };
+
+        for order in [MeshletOrder::Morton, MeshletOrder::Hilbert] {
+            let mut reordered = shuffled.clone();
+            reordered.reorder_meshlets(order).unwrap();
+            reordered.validate().unwrap();
+            assert_eq!(triangles(&reordered), triangles(&mesh));
+            // Vertices are numbered in the order the meshlets use them.
+            assert_eq!(reordered.meshlet_data[0], 0);
+
+            let stats = MeshStats::new(&reordered, &config).unwrap();
+            assert!(
+                stats.meshlet_center_step < shuffled_stats.meshlet_center_step,
+                "{order:?}: {} >= {}",
+                stats.meshlet_center_step,
+                shuffled_stats.meshlet_center_step
+            );
+            assert!(stats.meshlet_shared_vertices > shuffled_stats.meshlet_shared_vertices);
+            assert!(stats.overfetch <= shuffled_stats.overfetch);
+        }
+    }
+}
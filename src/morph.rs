@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+use crate::mesh::{Mesh, Meshlet, Vertex};
+
+/// Offset of a single vertex in a [`MorphTarget`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct MorphDelta {
+    pub position: Vec3,
+    pub normal: Vec3,
+}
+
+unsafe impl Zeroable for MorphDelta {}
+unsafe impl Pod for MorphDelta {}
+
+/// Blend shape of a mesh with one delta per vertex, which is scaled by the weight of the target
+/// and added to the vertex.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MorphTarget {
+    pub name: Option<String>,
+    pub deltas: Vec<MorphDelta>,
+}
+
+impl Mesh {
+    /// Applies the morph targets with their `weights` on the CPU. Missing weights count as zero.
+    pub fn morphed_vertices(&self, weights: &[f32]) -> Vec<Vertex> {
+        let mut vertices = self.vertices.clone();
+
+        for (target, weight) in self.morph_targets.iter().zip(weights) {
+            if *weight == 0.0 {
+                continue;
+            }
+
+            for (vertex, delta) in vertices.iter_mut().zip(&target.deltas) {
+                vertex.position += delta.position * *weight;
+                vertex.normal += delta.normal * *weight;
+            }
+        }
+
+        if weights.iter().any(|weight| *weight != 0.0) {
+            for vertex in &mut vertices {
+                vertex.normal = vertex.normal.normalize_or_zero();
+            }
+        }
+
+        vertices
+    }
+}
+
+/// Like `meshopt::generate_vertex_remap`, but only merges vertices that are also moved the same
+/// by every target.
+pub(crate) fn generate_vertex_remap(
+    vertices: &[Vertex],
+    indices: Option<&[u32]>,
+    morph_targets: &[MorphTarget],
+) -> (usize, Vec<u32>) {
+    if morph_targets.is_empty() {
+        return meshopt::generate_vertex_remap(vertices, indices);
+    }
+
+    let mut remap = vec![u32::MAX; vertices.len()];
+    let mut unique: HashMap<Vec<u8>, u32> = HashMap::new();
+
+    let mut visit = |index: usize| {
+        if remap[index] != u32::MAX {
+            return;
+        }
+
+        let mut key = bytemuck::bytes_of(&vertices[index]).to_vec();
+        for target in morph_targets {
+            key.extend_from_slice(bytemuck::bytes_of(&target.deltas[index]));
+        }
+
+        let next = unique.len() as u32;
+        remap[index] = *unique.entry(key).or_insert(next);
+    };
+
+    match indices {
+        Some(indices) => indices.iter().for_each(|index| visit(*index as usize)),
+        None => (0..vertices.len()).for_each(visit),
+    }
+
+    (unique.len(), remap)
+}
+
+pub(crate) fn remap_morph_targets(
+    morph_targets: Vec<MorphTarget>,
+    vertex_count: usize,
+    remap: &[u32],
+) -> Vec<MorphTarget> {
+    morph_targets
+        .into_iter()
+        .map(|target| {
+            MorphTarget {
+                deltas: meshopt::remap_vertex_buffer(&target.deltas, vertex_count, remap),
+                ..target
+            }
+        })
+        .collect()
+}
+
+/// Grows the bounding spheres of the meshlets to contain their vertices for any combination of
+/// weights between 0 and 1. Meshlets with moving vertices also lose their normal cone, it no
+/// longer holds once the triangles are morphed.
+pub(crate) fn expand_meshlet_bounds(
+    meshlets: &mut [Meshlet],
+    meshlet_data: &[u32],
+    vertices: &[Vertex],
+    morph_targets: &[MorphTarget],
+) {
+    if morph_targets.is_empty() {
+        return;
+    }
+
+    for meshlet in meshlets {
+        let start = meshlet.data_offset as usize;
+        let mut morphed = false;
+
+        for vertex in &meshlet_data[start..start + meshlet.vertex_count as usize] {
+            let vertex = *vertex as usize;
+            let reach: f32 = morph_targets
+                .iter()
+                .map(|target| target.deltas[vertex].position.length())
+                .sum();
+
+            morphed |= morph_targets
+                .iter()
+                .any(|target| target.deltas[vertex] != MorphDelta::default());
+            meshlet.radius = meshlet
+                .radius
+                .max(vertices[vertex].position.distance(meshlet.center) + reach);
+        }
+
+        if morphed {
+            meshlet.cone_axis = [0; 3];
+            meshlet.cone_cutoff = 127;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+
+    use crate::{
+        mesh::{Mesh, MeshletBuildConfig, Vertex},
+        morph::{MorphDelta, MorphTarget},
+        primitives::Primitive,
+    };
+
+    #[test]
+    fn morphed_vertices_stay_in_meshlet_bounds() {
+        let sphere = Primitive::uv_sphere(1.0, 32, 16);
+        let deltas = |f: &dyn Fn(Vec3) -> Vec3| -> Vec<MorphDelta> {
+            sphere
+                .vertices
+                .iter()
+                .map(|vertex| {
+                    MorphDelta {
+                        position: f(vertex.position),
+                        normal: Vec3::ZERO,
+                    }
+                })
+                .collect()
+        };
+        let targets = vec![
+            // Inflate the top half, pull the sides out along X.
+            MorphTarget {
+                name: Some("inflate".to_string()),
+                deltas: deltas(&|position| position * position.y.max(0.0)),
+            },
+            MorphTarget {
+                name: Some("stretch".to_string()),
+                deltas: deltas(&|position| Vec3::X * position.x * 2.0),
+            },
+        ];
+
+        let config = MeshletBuildConfig::default();
+        let mesh = Mesh::from_submeshes(
+            &sphere.vertices,
+            Some(&sphere.indices),
+            &vec![0; sphere.indices.len() / 3],
+            vec![Default::default()],
+            targets,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(mesh.morph_targets.len(), 2);
+        assert!(mesh
+            .morph_targets
+            .iter()
+            .all(|target| target.deltas.len() == mesh.vertices.len()));
+
+        for weights in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0], [0.3, 0.7]] {
+            let vertices = mesh.morphed_vertices(&weights);
+
+            for meshlet in &mesh.meshlets {
+                let start = meshlet.data_offset as usize;
+                for vertex in &mesh.meshlet_data[start..start + meshlet.vertex_count as usize] {
+                    let position = vertices[*vertex as usize].position;
+                    assert!(position.distance(meshlet.center) <= meshlet.radius + 1e-4);
+                }
+            }
+        }
+
+        let stretched = mesh.morphed_vertices(&[0.0, 1.0]);
+        let max_x = stretched
+            .iter()
+            .map(|vertex| vertex.position.x)
+            .fold(f32::MIN, f32::max);
+        assert!((max_x - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn vertices_with_different_deltas_are_kept_apart() {
+        // Two triangles sharing an edge whose vertices are identical but morph differently.
+        let corner = |x: f32, y: f32| Vertex::new(Vec3::new(x, y, 0.0), Vec2::ZERO, Vec3::Z);
+        let vertices = [
+            corner(0.0, 0.0),
+            corner(1.0, 0.0),
+            corner(0.0, 1.0),
+            corner(1.0, 0.0),
+            corner(1.0, 1.0),
+            corner(0.0, 1.0),
+        ];
+        let up = MorphDelta {
+            position: Vec3::Z,
+            normal: Vec3::ZERO,
+        };
+        let target = MorphTarget {
+            name: None,
+            deltas: vec![MorphDelta::default(), up, up, MorphDelta::default(), up, up],
+        };
+
+        let without = Mesh::from_vertices(&vertices, None, &MeshletBuildConfig::default()).unwrap();
+        let with = Mesh::from_submeshes(
+            &vertices,
+            None,
+            &[0, 0],
+            vec![Default::default()],
+            vec![target],
+            &MeshletBuildConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(without.vertices.len(), 4);
+        assert_eq!(with.vertices.len(), 5);
+        assert_eq!(with.morph_targets[0].deltas.len(), 5);
+        // The lifted corners keep their normal cone from culling the meshlet.
+        assert_eq!(with.meshlets[0].cone_cutoff, 127);
+    }
+}
@@ -0,0 +1,104 @@
+use glam::Vec3;
+
+/// Generates one normal per triangle corner. Faces sharing a position are smoothed together if
+/// the angle between them is at most `crease_angle` radians, so `0.0` yields flat shading and
+/// `PI` fully smooth shading.
+pub fn generate_normals(positions: &[Vec3], indices: &[u32], crease_angle: f32) -> Vec<Vec3> {
+    let face_normals: Vec<Vec3> = indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let p0 = positions[triangle[0] as usize];
+            let p1 = positions[triangle[1] as usize];
+            let p2 = positions[triangle[2] as usize];
+
+            (p1 - p0).cross(p2 - p0).normalize_or_zero()
+        })
+        .collect();
+
+    // Weighting by the corner angle keeps the result independent of how a surface is triangulated.
+    let corner_angles: Vec<f32> = indices
+        .iter()
+        .enumerate()
+        .map(|(corner, index)| {
+            let triangle = corner - corner % 3;
+            let position = positions[*index as usize];
+            let next = positions[indices[triangle + (corner + 1) % 3] as usize];
+            let previous = positions[indices[triangle + (corner + 2) % 3] as usize];
+
+            (next - position).angle_between(previous - position)
+        })
+        .collect();
+
+    let mut corners_by_position = vec![Vec::new(); positions.len()];
+    for (corner, index) in indices.iter().enumerate() {
+        corners_by_position[*index as usize].push(corner);
+    }
+
+    let cos_crease_angle = crease_angle.cos();
+
+    indices
+        .iter()
+        .enumerate()
+        .map(|(corner, index)| {
+            let face_normal = face_normals[corner / 3];
+
+            let normal = corners_by_position[*index as usize]
+                .iter()
+                .filter(|other| face_normals[*other / 3].dot(face_normal) >= cos_crease_angle)
+                .filter(|other| corner_angles[**other].is_finite())
+                .map(|other| face_normals[*other / 3] * corner_angles[*other])
+                .sum::<Vec3>()
+                .normalize_or_zero();
+
+            if normal == Vec3::ZERO {
+                face_normal
+            } else {
+                normal
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use glam::Vec3;
+
+    use crate::normals::generate_normals;
+
+    // Two faces of a unit cube meeting at the edge x = 1, z = 1.
+    const POSITIONS: [Vec3; 6] = [
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+    ];
+    const INDICES: [u32; 12] = [0, 1, 2, 0, 2, 3, 1, 4, 5, 1, 5, 2];
+
+    #[test]
+    fn flat_normals_below_crease_angle() {
+        let normals = generate_normals(&POSITIONS, &INDICES, 45.0f32.to_radians());
+
+        for normal in &normals[0..6] {
+            assert!(normal.abs_diff_eq(Vec3::Z, 1e-6));
+        }
+        for normal in &normals[6..12] {
+            assert!(normal.abs_diff_eq(Vec3::X, 1e-6));
+        }
+    }
+
+    #[test]
+    fn smooth_normals_above_crease_angle() {
+        let normals = generate_normals(&POSITIONS, &INDICES, PI);
+        let shared = Vec3::new(1.0, 0.0, 1.0).normalize();
+
+        // Corners on the shared edge average both faces, the others keep their face normal.
+        assert!(normals[1].abs_diff_eq(shared, 1e-6));
+        assert!(normals[6].abs_diff_eq(shared, 1e-6));
+        assert!(normals[0].abs_diff_eq(Vec3::Z, 1e-6));
+        assert!(normals[7].abs_diff_eq(Vec3::X, 1e-6));
+    }
+}
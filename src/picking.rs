@@ -0,0 +1,442 @@
+use anyhow::Result;
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::{bounds::Aabb, mesh::Mesh};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+/// Where a ray hits a triangle.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TriangleHit {
+    /// Distance along the ray in units of its direction.
+    pub t: f32,
+    /// Weights of the second and third corner, the first one gets `1 - x - y`.
+    pub barycentrics: Vec2,
+}
+
+impl Ray {
+    /// Ray from the camera through `position`, in pixels from the top left corner of the viewport.
+    /// Starts on the near plane of `view_projection`, which maps depth to `0..1` like the
+    /// projection of the viewer's `FreeCam` does.
+    pub fn from_screen(position: Vec2, viewport_size: Vec2, view_projection: &Mat4) -> Self {
+        let ndc = Vec2::new(
+            position.x / viewport_size.x * 2.0 - 1.0,
+            1.0 - position.y / viewport_size.y * 2.0,
+        );
+        let inverse = view_projection.inverse();
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+
+        Self {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    #[inline]
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// The same ray in the space `transform` maps to. The direction is not normalized, so
+    /// distances along the ray stay comparable to the untransformed ray.
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        Self {
+            origin: transform.transform_point3(self.origin),
+            direction: transform.transform_vector3(self.direction),
+        }
+    }
+
+    /// Möller-Trumbore intersection. Triangles are hit from both sides.
+    pub fn intersect_triangle(&self, [a, b, c]: [Vec3; 3]) -> Option<TriangleHit> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant == 0.0 {
+            return None;
+        }
+        let inverse_determinant = determinant.recip();
+
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inverse_determinant;
+        (t >= 0.0).then_some(TriangleHit {
+            t,
+            barycentrics: Vec2::new(u, v),
+        })
+    }
+
+    /// Distance to the first point of `aabb` in front of the origin, zero if the origin is inside.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let inverse_direction = self.direction.recip();
+        let t0 = (aabb.min - self.origin) * inverse_direction;
+        let t1 = (aabb.max - self.origin) * inverse_direction;
+
+        let near = t0.min(t1).max_element().max(0.0);
+        let far = t0.max(t1).min_element();
+        (near <= far).then_some(near)
+    }
+}
+
+/// Closest triangle of a [`MeshletBvh`] hit by a ray.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MeshletHit {
+    /// Index into [`Mesh::meshlets`].
+    pub meshlet: usize,
+    /// Index of the triangle within the meshlet.
+    pub triangle: usize,
+    /// Indices into [`Mesh::vertices`] of the corners of the triangle.
+    pub vertices: [u32; 3],
+    pub hit: TriangleHit,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct BvhNode {
+    aabb: Aabb,
+    /// First leaf of a leaf node, or the index of the second child of an inner node. The first
+    /// child directly follows its parent.
+    start: u32,
+    /// Zero for inner nodes.
+    leaf_count: u32,
+}
+
+/// A meshlet with the triangles it is tested with.
+#[derive(Copy, Clone, Debug)]
+struct BvhLeaf {
+    aabb: Aabb,
+    meshlet: u32,
+    triangle_offset: u32,
+    triangle_count: u32,
+}
+
+/// Bounding volume hierarchy over the meshlets of one LOD of a [`Mesh`], with the triangles of
+/// every meshlet in its leaves. Picks against the rest pose, morph targets and skinning are
+/// ignored.
+#[derive(Clone, Debug)]
+pub struct MeshletBvh {
+    nodes: Vec<BvhNode>,
+    leaves: Vec<BvhLeaf>,
+    triangles: Vec<[u32; 3]>,
+    positions: Vec<Vec3>,
+}
+
+impl MeshletBvh {
+    pub fn new(mesh: &Mesh, lod: usize) -> Result<Self> {
+        let mut leaves = Vec::new();
+        let mut triangles = Vec::new();
+
+        for submesh in mesh.lod_submeshes(lod) {
+            let start = submesh.meshlet_offset as usize;
+            for index in start..start + submesh.meshlet_count as usize {
+                let indices =
+                    mesh.meshlets[index].indices(mesh.index_format, &mesh.meshlet_data)?;
+
+                leaves.push(BvhLeaf {
+                    aabb: Aabb::from_points(
+                        indices
+                            .iter()
+                            .map(|vertex| mesh.vertices[*vertex as usize].position),
+                    ),
+                    meshlet: index as u32,
+                    triangle_offset: triangles.len() as u32,
+                    triangle_count: (indices.len() / 3) as u32,
+                });
+                triangles.extend(
+                    indices
+                        .chunks_exact(3)
+                        .map(|triangle| [triangle[0], triangle[1], triangle[2]]),
+                );
+            }
+        }
+
+        let mut nodes = Vec::with_capacity(2 * leaves.len());
+        if !leaves.is_empty() {
+            build_node(&mut nodes, &mut leaves, 0);
+        }
+
+        Ok(Self {
+            nodes,
+            leaves,
+            triangles,
+            positions: mesh.vertices.iter().map(|vertex| vertex.position).collect(),
+        })
+    }
+
+    /// Bounds of everything in the hierarchy.
+    pub fn aabb(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::default(), |node| node.aabb)
+    }
+
+    /// Closest triangle hit by `ray`, in mesh space.
+    pub fn intersect(&self, ray: &Ray) -> Option<MeshletHit> {
+        let mut closest: Option<MeshletHit> = None;
+        let closest_t =
+            |closest: &Option<MeshletHit>| closest.map_or(f32::INFINITY, |closest| closest.hit.t);
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            match ray.intersect_aabb(&node.aabb) {
+                Some(t) if t <= closest_t(&closest) => {}
+                _ => continue,
+            }
+
+            if node.leaf_count == 0 {
+                stack.extend([node.start as usize, index + 1]);
+                continue;
+            }
+
+            let start = node.start as usize;
+            for leaf in &self.leaves[start..start + node.leaf_count as usize] {
+                let offset = leaf.triangle_offset as usize;
+                for (triangle, vertices) in self.triangles
+                    [offset..offset + leaf.triangle_count as usize]
+                    .iter()
+                    .enumerate()
+                {
+                    let Some(hit) = ray
+                        .intersect_triangle(vertices.map(|vertex| self.positions[vertex as usize]))
+                    else {
+                        continue;
+                    };
+
+                    if hit.t < closest_t(&closest) {
+                        closest = Some(MeshletHit {
+                            meshlet: leaf.meshlet as usize,
+                            triangle,
+                            vertices: *vertices,
+                            hit,
+                        });
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+/// Splits `leaves` at the median of the longest axis of their centers until every node holds a
+/// single meshlet.
+fn build_node(nodes: &mut Vec<BvhNode>, leaves: &mut [BvhLeaf], offset: usize) {
+    let index = nodes.len();
+    nodes.push(BvhNode {
+        aabb: leaves
+            .iter()
+            .map(|leaf| leaf.aabb)
+            .reduce(|a, b| a.union(&b))
+            .unwrap(),
+        start: offset as u32,
+        leaf_count: leaves.len() as u32,
+    });
+    if leaves.len() == 1 {
+        return;
+    }
+
+    let size = Aabb::from_points(leaves.iter().map(|leaf| leaf.aabb.center())).size();
+    let axis = if size.x >= size.y && size.x >= size.z {
+        0
+    } else if size.y >= size.z {
+        1
+    } else {
+        2
+    };
+    leaves.sort_unstable_by(|a, b| a.aabb.center()[axis].total_cmp(&b.aabb.center()[axis]));
+
+    let middle = leaves.len() / 2;
+    let (first, second) = leaves.split_at_mut(middle);
+    build_node(nodes, first, offset);
+    nodes[index].start = nodes.len() as u32;
+    nodes[index].leaf_count = 0;
+    build_node(nodes, second, offset + middle);
+}
+
+/// Closest hit among several meshes, each placed with a model matrix. Returns the index of the
+/// mesh that was hit.
+pub fn pick<'a>(
+    ray: &Ray,
+    meshes: impl IntoIterator<Item = (&'a MeshletBvh, Mat4)>,
+) -> Option<(usize, MeshletHit)> {
+    meshes
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, (bvh, model_matrix))| {
+            bvh.intersect(&ray.transform(&model_matrix.inverse()))
+                .map(|hit| (index, hit))
+        })
+        .min_by(|(_, a), (_, b)| a.hit.t.total_cmp(&b.hit.t))
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec2, Vec3};
+
+    use crate::{
+        mesh::MeshletBuildConfig,
+        picking::{pick, MeshletBvh, Ray},
+        primitives::Primitive,
+    };
+
+    #[test]
+    fn ray_triangle() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let ray = |origin: Vec3| {
+            Ray {
+                origin,
+                direction: -Vec3::Z,
+            }
+        };
+
+        let hit = ray(Vec3::new(0.25, 0.5, 2.0))
+            .intersect_triangle(triangle)
+            .unwrap();
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(hit.barycentrics, Vec2::new(0.25, 0.5));
+
+        // Back faces are hit too, but nothing behind the origin or outside the triangle.
+        assert!(ray(Vec3::new(0.25, 0.25, -1.0))
+            .intersect_triangle(triangle)
+            .is_none());
+        assert!(Ray {
+            origin: Vec3::new(0.25, 0.25, -1.0),
+            direction: Vec3::Z,
+        }
+        .intersect_triangle(triangle)
+        .is_some());
+        assert!(ray(Vec3::new(0.75, 0.75, 1.0))
+            .intersect_triangle(triangle)
+            .is_none());
+        assert!(Ray {
+            origin: Vec3::new(0.0, 0.0, 1.0),
+            direction: Vec3::X,
+        }
+        .intersect_triangle(triangle)
+        .is_none());
+    }
+
+    #[test]
+    fn screen_rays_pass_through_projected_points() {
+        let viewport_size = Vec2::new(1920.0, 1080.0);
+        let eye = Vec3::new(1.0, 2.0, -5.0);
+        let view_projection = Mat4::perspective_lh(
+            90.0f32.to_radians(),
+            viewport_size.x / viewport_size.y,
+            0.1,
+            1000.0,
+        ) * Mat4::look_at_lh(eye, Vec3::ZERO, Vec3::Y);
+
+        let center = Ray::from_screen(viewport_size * 0.5, viewport_size, &view_projection);
+        assert!(center
+            .direction
+            .abs_diff_eq((Vec3::ZERO - eye).normalize(), 1e-5));
+
+        for point in [
+            Vec3::ZERO,
+            Vec3::new(3.0, -1.0, 2.0),
+            Vec3::new(-2.0, 4.0, 0.5),
+        ] {
+            let ndc = view_projection.project_point3(point);
+            let screen = Vec2::new(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * viewport_size;
+            let ray = Ray::from_screen(screen, viewport_size, &view_projection);
+
+            let t = (point - ray.origin).dot(ray.direction);
+            assert!(ray.at(t).distance(point) < 1e-3, "{point}");
+        }
+    }
+
+    #[test]
+    fn bvh_matches_brute_force() {
+        let config = MeshletBuildConfig::new(32, 32, 0.0).unwrap();
+        let mesh = Primitive::uv_sphere(1.0, 32, 16).to_mesh(&config).unwrap();
+        let bvh = MeshletBvh::new(&mesh, 0).unwrap();
+
+        let brute_force =
+            |ray: &Ray| {
+                mesh.meshlets
+                    .iter()
+                    .flat_map(|meshlet| {
+                        meshlet
+                            .indices(mesh.index_format, &mesh.meshlet_data)
+                            .unwrap()
+                            .chunks_exact(3)
+                            .filter_map(|triangle| {
+                                ray.intersect_triangle([0, 1, 2].map(|corner| {
+                                    mesh.vertices[triangle[corner] as usize].position
+                                }))
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .map(|hit| hit.t)
+                    .fold(f32::INFINITY, f32::min)
+            };
+
+        for i in 0..64 {
+            let angle = i as f32 * 0.7;
+            let ray = Ray {
+                origin: Vec3::new(
+                    angle.cos() * 3.0,
+                    (i as f32 / 32.0) - 1.0,
+                    angle.sin() * 3.0,
+                ),
+                direction: Vec3::new(-angle.cos(), 0.1, -angle.sin()).normalize(),
+            };
+
+            match bvh.intersect(&ray) {
+                Some(hit) => {
+                    assert_eq!(hit.hit.t, brute_force(&ray));
+
+                    let meshlet = &mesh.meshlets[hit.meshlet];
+                    let indices = meshlet
+                        .indices(mesh.index_format, &mesh.meshlet_data)
+                        .unwrap();
+                    assert_eq!(
+                        indices[3 * hit.triangle..3 * hit.triangle + 3],
+                        hit.vertices
+                    );
+                }
+                None => assert_eq!(brute_force(&ray), f32::INFINITY),
+            }
+        }
+    }
+
+    #[test]
+    fn pick_closest_mesh() {
+        let cube = Primitive::cube(1.0)
+            .to_mesh(&MeshletBuildConfig::default())
+            .unwrap();
+        let bvh = MeshletBvh::new(&cube, 0).unwrap();
+        assert!(bvh.aabb().max.abs_diff_eq(Vec3::splat(0.5), 1e-6));
+
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, -10.0),
+            direction: Vec3::Z,
+        };
+        let far = Mat4::from_translation(Vec3::Z * 5.0);
+        let near = Mat4::from_scale(Vec3::splat(2.0));
+
+        let (index, hit) = pick(&ray, [(&bvh, far), (&bvh, near)]).unwrap();
+        assert_eq!(index, 1);
+        // The scaled cube's front face is at z = -1.
+        assert!((hit.hit.t - 9.0).abs() < 1e-5);
+        assert!(pick(&ray, [(&bvh, Mat4::from_translation(Vec3::X * 5.0))]).is_none());
+    }
+}
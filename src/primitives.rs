@@ -0,0 +1,407 @@
+//! Procedurally generated shapes for tests and debug scenes. All of them are centered on the
+//! origin with Y up, wind their front faces counter clockwise like OBJ files and go through the same
+//! meshlet building path as imported meshes.
+
+use std::{collections::HashMap, f32::consts::TAU};
+
+use anyhow::Result;
+use glam::{Vec2, Vec3};
+
+use crate::mesh::{Mesh, MeshletBuildConfig, Vertex};
+
+/// Indexed triangle list of a generated shape.
+#[derive(Clone, Debug, Default)]
+pub struct Primitive {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+/// Sine and cosine of an angle in turns, exact at multiples of a quarter turn so that seams and
+/// poles land on identical positions.
+fn sin_cos_turns(turns: f32) -> (f32, f32) {
+    let quarters = turns * 4.0;
+    if quarters.fract() != 0.0 {
+        return (turns * TAU).sin_cos();
+    }
+
+    match (quarters as i32).rem_euclid(4) {
+        0 => (0.0, 1.0),
+        1 => (1.0, 0.0),
+        2 => (0.0, -1.0),
+        _ => (-1.0, 0.0),
+    }
+}
+
+impl Primitive {
+    /// Appends a `u_segments` by `v_segments` grid of quads. `surface` maps texture coordinates
+    /// in `[0, 1]` to a position and normal, the front face is on the side of `d/du x d/dv`.
+    /// Triangles that collapse onto a point, like the ones at the poles of a sphere, are skipped.
+    fn push_surface(
+        &mut self,
+        u_segments: u32,
+        v_segments: u32,
+        surface: impl Fn(Vec2) -> (Vec3, Vec3),
+    ) {
+        let first = self.vertices.len() as u32;
+        for y in 0..=v_segments {
+            for x in 0..=u_segments {
+                let tex_coord =
+                    Vec2::new(x as f32 / u_segments as f32, y as f32 / v_segments as f32);
+                let (position, normal) = surface(tex_coord);
+                self.vertices.push(Vertex::new(position, tex_coord, normal));
+            }
+        }
+
+        let index = |x: u32, y: u32| first + y * (u_segments + 1) + x;
+        for y in 0..v_segments {
+            for x in 0..u_segments {
+                let quad = [
+                    index(x, y),
+                    index(x + 1, y),
+                    index(x + 1, y + 1),
+                    index(x, y + 1),
+                ];
+                for triangle in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+                    let [a, b, c] = triangle.map(|index| self.vertices[index as usize].position);
+                    if a != b && b != c && c != a {
+                        self.indices.extend(triangle);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Appends a disk facing `normal`, which is either up or down.
+    fn push_disk(&mut self, radius: f32, y: f32, normal: Vec3, segments: u32) {
+        self.push_surface(segments, 1, |tex_coord| {
+            let (sin, cos) = sin_cos_turns(tex_coord.x.fract());
+            let scale = if normal.y > 0.0 {
+                1.0 - tex_coord.y
+            } else {
+                tex_coord.y
+            };
+
+            (
+                Vec3::new(scale * radius * sin, y, scale * radius * cos),
+                normal,
+            )
+        });
+    }
+
+    pub fn cube(size: f32) -> Self {
+        let mut primitive = Self::default();
+        let half_size = size * 0.5;
+
+        // Normal and the two axes spanning the face, with `u x v = normal`.
+        for (normal, u, v) in [
+            (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+            (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+            (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+        ] {
+            primitive.push_surface(1, 1, |tex_coord| {
+                let offset = tex_coord * 2.0 - 1.0;
+                ((normal + u * offset.x + v * offset.y) * half_size, normal)
+            });
+        }
+
+        primitive
+    }
+
+    /// Plane in XZ facing up, split into `subdivisions` quads along each side.
+    pub fn plane(size: Vec2, subdivisions: u32) -> Self {
+        let mut primitive = Self::default();
+        primitive.push_surface(subdivisions, subdivisions, |tex_coord| {
+            let position = (tex_coord - 0.5) * size;
+            (Vec3::new(position.x, 0.0, -position.y), Vec3::Y)
+        });
+
+        primitive
+    }
+
+    pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Self {
+        let mut primitive = Self::default();
+        primitive.push_surface(segments, rings, |tex_coord| {
+            let (sin_longitude, cos_longitude) = sin_cos_turns(tex_coord.x.fract());
+            let (sin_latitude, cos_latitude) = sin_cos_turns((tex_coord.y - 0.5) * 0.5);
+            let normal = Vec3::new(
+                cos_latitude * sin_longitude,
+                sin_latitude,
+                cos_latitude * cos_longitude,
+            );
+
+            (normal * radius, normal)
+        });
+
+        primitive
+    }
+
+    /// Sphere made of an icosahedron whose triangles are split in four `subdivisions` times, which
+    /// unlike [`Primitive::uv_sphere`] has evenly sized triangles.
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+        let mut directions: Vec<Vec3> = [
+            [-1.0, t, 0.0],
+            [1.0, t, 0.0],
+            [-1.0, -t, 0.0],
+            [1.0, -t, 0.0],
+            [0.0, -1.0, t],
+            [0.0, 1.0, t],
+            [0.0, -1.0, -t],
+            [0.0, 1.0, -t],
+            [t, 0.0, -1.0],
+            [t, 0.0, 1.0],
+            [-t, 0.0, -1.0],
+            [-t, 0.0, 1.0],
+        ]
+        .into_iter()
+        .map(|direction| Vec3::from_array(direction).normalize())
+        .collect();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            let mut midpoints = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    directions.push((directions[a as usize] + directions[b as usize]).normalize());
+                    directions.len() as u32 - 1
+                })
+            };
+
+            triangles = triangles
+                .into_iter()
+                .flat_map(|[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        let tex_coord = |direction: Vec3| {
+            Vec2::new(
+                direction.x.atan2(direction.z) / TAU + 0.5,
+                direction.y.asin() / (TAU * 0.5) + 0.5,
+            )
+        };
+        let mut primitive = Self {
+            vertices: directions
+                .iter()
+                .map(|direction| {
+                    Vertex::new(*direction * radius, tex_coord(*direction), *direction)
+                })
+                .collect(),
+            indices: Vec::with_capacity(triangles.len() * 3),
+        };
+
+        // Triangles crossing the seam would interpolate across the whole texture, so they get
+        // copies of their vertices on the low side with the texture coordinate wrapped around.
+        let mut wrapped = HashMap::new();
+        for triangle in triangles {
+            let u = triangle.map(|index| primitive.vertices[index as usize].tex_coord.x);
+            let crosses_seam = u.iter().fold(0.0f32, |max, u| max.max(*u))
+                - u.iter().fold(1.0f32, |min, u| min.min(*u))
+                > 0.5;
+
+            for (index, u) in triangle.into_iter().zip(u) {
+                let index = if crosses_seam && u < 0.5 {
+                    *wrapped.entry(index).or_insert_with(|| {
+                        let mut vertex = primitive.vertices[index as usize];
+                        vertex.tex_coord.x += 1.0;
+                        primitive.vertices.push(vertex);
+                        primitive.vertices.len() as u32 - 1
+                    })
+                } else {
+                    index
+                };
+                primitive.indices.push(index);
+            }
+        }
+
+        primitive
+    }
+
+    /// Capped cylinder along Y.
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> Self {
+        let mut primitive = Self::default();
+        let half_height = height * 0.5;
+
+        primitive.push_surface(segments, 1, |tex_coord| {
+            let (sin, cos) = sin_cos_turns(tex_coord.x.fract());
+            (
+                Vec3::new(radius * sin, (tex_coord.y - 0.5) * height, radius * cos),
+                Vec3::new(sin, 0.0, cos),
+            )
+        });
+        primitive.push_disk(radius, half_height, Vec3::Y, segments);
+        primitive.push_disk(radius, -half_height, Vec3::NEG_Y, segments);
+
+        primitive
+    }
+
+    /// Capped cone along Y with the tip at the top.
+    pub fn cone(radius: f32, height: f32, segments: u32) -> Self {
+        let mut primitive = Self::default();
+        let half_height = height * 0.5;
+
+        primitive.push_surface(segments, 1, |tex_coord| {
+            let (sin, cos) = sin_cos_turns(tex_coord.x.fract());
+            let scale = 1.0 - tex_coord.y;
+            (
+                Vec3::new(
+                    scale * radius * sin,
+                    tex_coord.y * height - half_height,
+                    scale * radius * cos,
+                ),
+                Vec3::new(height * sin, radius, height * cos).normalize(),
+            )
+        });
+        primitive.push_disk(radius, -half_height, Vec3::NEG_Y, segments);
+
+        primitive
+    }
+
+    /// Torus around Y. `major_radius` is the distance from the center to the middle of the tube.
+    pub fn torus(
+        major_radius: f32,
+        minor_radius: f32,
+        major_segments: u32,
+        minor_segments: u32,
+    ) -> Self {
+        let mut primitive = Self::default();
+        primitive.push_surface(major_segments, minor_segments, |tex_coord| {
+            let (sin_major, cos_major) = sin_cos_turns(tex_coord.x.fract());
+            let (sin_minor, cos_minor) = sin_cos_turns(tex_coord.y.fract());
+            let normal = Vec3::new(cos_minor * sin_major, sin_minor, cos_minor * cos_major);
+
+            (
+                Vec3::new(sin_major, 0.0, cos_major) * major_radius + normal * minor_radius,
+                normal,
+            )
+        });
+
+        primitive
+    }
+
+    /// Builds meshlets exactly like [`Mesh::new`] does for imported meshes.
+    pub fn to_mesh(&self, config: &MeshletBuildConfig) -> Result<Mesh> {
+        Mesh::from_vertices(&self.vertices, Some(&self.indices), config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::{Vec2, Vec3};
+
+    use crate::{mesh::MeshletBuildConfig, primitives::Primitive};
+
+    fn primitives() -> [(&'static str, Primitive, bool); 7] {
+        [
+            ("cube", Primitive::cube(2.0), true),
+            ("plane", Primitive::plane(Vec2::new(4.0, 2.0), 8), false),
+            ("uv_sphere", Primitive::uv_sphere(1.0, 24, 12), true),
+            ("icosphere", Primitive::icosphere(1.0, 3), true),
+            ("cylinder", Primitive::cylinder(0.5, 2.0, 16), true),
+            ("cone", Primitive::cone(1.0, 2.0, 16), true),
+            ("torus", Primitive::torus(1.0, 0.25, 32, 12), true),
+        ]
+    }
+
+    #[test]
+    fn normals_match_winding() {
+        for (name, primitive, _) in primitives() {
+            for triangle in primitive.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| primitive.vertices[triangle[i] as usize]);
+                let face_normal = (b.position - a.position).cross(c.position - a.position);
+                assert!(
+                    face_normal.length() > 0.0,
+                    "{name} has a degenerate triangle"
+                );
+
+                for vertex in [a, b, c] {
+                    assert!((vertex.normal.length() - 1.0).abs() < 1e-5, "{name}");
+                    assert!(vertex.normal.dot(face_normal) > 0.0, "{name} {triangle:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn closed_primitives_are_watertight() {
+        let key = |position: Vec3| {
+            position
+                .to_array()
+                .map(|component| (component + 0.0).to_bits())
+        };
+
+        for (name, primitive, closed) in primitives() {
+            if !closed {
+                continue;
+            }
+
+            // Every edge of a closed surface is walked once in each direction.
+            let mut edges: HashMap<_, i32> = HashMap::new();
+            for triangle in primitive.indices.chunks_exact(3) {
+                for corner in 0..3 {
+                    let from = key(primitive.vertices[triangle[corner] as usize].position);
+                    let to = key(primitive.vertices[triangle[(corner + 1) % 3] as usize].position);
+                    *edges.entry((from.min(to), from.max(to))).or_default() +=
+                        if from < to { 1 } else { -1 };
+                }
+            }
+            assert!(
+                edges.values().all(|balance| *balance == 0),
+                "{name} has holes"
+            );
+        }
+    }
+
+    #[test]
+    fn primitives_build_meshes() {
+        let config = MeshletBuildConfig::default();
+
+        for (name, primitive, _) in primitives() {
+            let mesh = primitive.to_mesh(&config).unwrap();
+            mesh.validate().unwrap();
+
+            let triangle_count: u32 = mesh
+                .lod_submeshes(0)
+                .iter()
+                .flat_map(|submesh| {
+                    let start = submesh.meshlet_offset as usize;
+                    &mesh.meshlets[start..start + submesh.meshlet_count as usize]
+                })
+                .map(|meshlet| meshlet.triangle_count)
+                .sum();
+            assert_eq!(
+                triangle_count as usize,
+                primitive.indices.len() / 3,
+                "{name}"
+            );
+        }
+    }
+}
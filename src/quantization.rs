@@ -0,0 +1,311 @@
+use anyhow::{ensure, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec3, Vec4};
+use half::f16;
+
+use crate::mesh::Vertex;
+
+/// Layout of the vertex buffer that is uploaded to the GPU.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum VertexFormat {
+    /// [`Vertex`] as is, 64 bytes per vertex.
+    #[default]
+    Full,
+    /// [`QuantizedVertex`], 20 bytes per vertex. Drops the skinning attributes.
+    Quantized,
+}
+
+impl VertexFormat {
+    /// Defines `shaders/geometry.hlsl` reads the vertices with, plus `SKINNING` if `skinning` is
+    /// set. Quantized vertices have no joints or weights, so they can't be skinned.
+    pub fn shader_defines(&self, skinning: bool) -> Result<Vec<(&'static str, String)>> {
+        let mut defines = match self {
+            Self::Full => Vec::new(),
+            Self::Quantized => vec![("QUANTIZED_VERTICES", "1".to_string())],
+        };
+
+        if skinning {
+            ensure!(
+                *self != Self::Quantized,
+                "Quantized vertices drop the joints and weights skinning needs"
+            );
+            defines.push(("SKINNING", "1".to_string()));
+        }
+
+        Ok(defines)
+    }
+}
+
+/// Compact vertex, decoded by `decode_vertex` in `shaders/geometry.hlsl`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct QuantizedVertex {
+    /// Unorm16 position inside the [`QuantizationBounds`] of the mesh.
+    pub position: [u16; 3],
+    /// Bitangent sign of the tangent, zero if the vertex has no tangent.
+    pub tangent_sign: i16,
+    /// Half float texture coordinate.
+    pub tex_coord: [u16; 2],
+    /// Snorm16 octahedral normal.
+    pub normal: [i16; 2],
+    /// Snorm16 octahedral tangent.
+    pub tangent: [i16; 2],
+}
+
+unsafe impl Zeroable for QuantizedVertex {}
+unsafe impl Pod for QuantizedVertex {}
+
+/// Axis aligned bounding box positions are quantized in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QuantizationBounds {
+    pub min: Vec3,
+    pub extent: Vec3,
+}
+
+impl Default for QuantizationBounds {
+    fn default() -> Self {
+        Self {
+            min: Vec3::ZERO,
+            extent: Vec3::ONE,
+        }
+    }
+}
+
+impl QuantizationBounds {
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        if vertices.is_empty() {
+            return Self::default();
+        }
+
+        let (min, max) = vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), vertex| (min.min(vertex.position), max.max(vertex.position)),
+        );
+
+        Self {
+            min,
+            // Flat meshes still need a non zero extent to divide by.
+            extent: (max - min).max(Vec3::splat(f32::MIN_POSITIVE)),
+        }
+    }
+
+    /// Largest distance per axis between a position and its quantized counterpart.
+    pub fn max_position_error(&self) -> Vec3 {
+        self.extent / u16::MAX as f32 * 0.5
+    }
+
+    pub fn encode(&self, vertex: &Vertex) -> QuantizedVertex {
+        let position = ((vertex.position - self.min) / self.extent * u16::MAX as f32)
+            .round()
+            .clamp(Vec3::ZERO, Vec3::splat(u16::MAX as f32));
+        let has_tangent = vertex.tangent != Vec4::ZERO;
+
+        QuantizedVertex {
+            position: position.to_array().map(|component| component as u16),
+            tangent_sign: if !has_tangent {
+                0
+            } else if vertex.tangent.w < 0.0 {
+                -1
+            } else {
+                1
+            },
+            tex_coord: vertex
+                .tex_coord
+                .to_array()
+                .map(|component| f16::from_f32(component).to_bits()),
+            normal: encode_octahedral(vertex.normal),
+            tangent: if has_tangent {
+                encode_octahedral(vertex.tangent.truncate())
+            } else {
+                [0; 2]
+            },
+        }
+    }
+
+    pub fn decode(&self, vertex: &QuantizedVertex) -> Vertex {
+        let position = Vec3::from_array(vertex.position.map(|component| component as f32))
+            / u16::MAX as f32
+            * self.extent
+            + self.min;
+
+        Vertex {
+            position,
+            tex_coord: Vec2::from_array(
+                vertex
+                    .tex_coord
+                    .map(|component| f16::from_bits(component).to_f32()),
+            ),
+            normal: decode_octahedral(vertex.normal),
+            tangent: if vertex.tangent_sign == 0 {
+                Vec4::ZERO
+            } else {
+                decode_octahedral(vertex.tangent).extend(vertex.tangent_sign as f32)
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn encode_vertices(&self, vertices: &[Vertex]) -> Vec<QuantizedVertex> {
+        vertices.iter().map(|vertex| self.encode(vertex)).collect()
+    }
+}
+
+#[inline]
+fn sign_not_zero(value: f32) -> f32 {
+    if value >= 0.0 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+/// Maps a unit vector onto the octahedron and unfolds it into the `[-1, 1]` square.
+pub fn encode_octahedral(direction: Vec3) -> [i16; 2] {
+    let length = direction.x.abs() + direction.y.abs() + direction.z.abs();
+    if length == 0.0 {
+        return [0; 2];
+    }
+
+    let direction = direction / length;
+    let folded = if direction.z < 0.0 {
+        Vec2::new(
+            (1.0 - direction.y.abs()) * sign_not_zero(direction.x),
+            (1.0 - direction.x.abs()) * sign_not_zero(direction.y),
+        )
+    } else {
+        Vec2::new(direction.x, direction.y)
+    };
+
+    (folded * i16::MAX as f32)
+        .round()
+        .to_array()
+        .map(|component| component as i16)
+}
+
+pub fn decode_octahedral(encoded: [i16; 2]) -> Vec3 {
+    let folded = Vec2::from_array(encoded.map(|component| component as f32)) / i16::MAX as f32;
+    let mut direction = Vec3::new(folded.x, folded.y, 1.0 - folded.x.abs() - folded.y.abs());
+
+    let t = (-direction.z).max(0.0);
+    direction.x -= t * sign_not_zero(direction.x);
+    direction.y -= t * sign_not_zero(direction.y);
+
+    direction.normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3, Vec4};
+
+    use crate::{
+        mesh::Vertex,
+        quantization::{decode_octahedral, encode_octahedral, QuantizationBounds, VertexFormat},
+    };
+
+    /// Roughly the size of two snorm16 steps on the unit sphere.
+    const MAX_DIRECTION_ERROR: f32 = 1e-4;
+
+    fn fibonacci_sphere(count: usize) -> impl Iterator<Item = Vec3> {
+        (0..count).map(move |i| {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let radius = (1.0 - y * y).sqrt();
+            let angle = i as f32 * std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+            Vec3::new(angle.cos() * radius, y, angle.sin() * radius)
+        })
+    }
+
+    #[test]
+    fn skinning_needs_full_vertices() {
+        assert_eq!(
+            VertexFormat::Full.shader_defines(true).unwrap(),
+            [("SKINNING", "1".to_string())]
+        );
+        assert_eq!(
+            VertexFormat::Quantized.shader_defines(false).unwrap(),
+            [("QUANTIZED_VERTICES", "1".to_string())]
+        );
+        assert!(VertexFormat::Quantized.shader_defines(true).is_err());
+    }
+
+    #[test]
+    fn octahedral_round_trip() {
+        for direction in fibonacci_sphere(4096).chain([Vec3::X, -Vec3::Y, -Vec3::Z]) {
+            let decoded = decode_octahedral(encode_octahedral(direction));
+            assert!(
+                decoded.distance(direction) < MAX_DIRECTION_ERROR,
+                "{direction} decoded to {decoded}"
+            );
+        }
+    }
+
+    #[test]
+    fn vertex_round_trip() {
+        let vertices: Vec<Vertex> = fibonacci_sphere(1024)
+            .enumerate()
+            .map(|(i, normal)| {
+                Vertex {
+                    position: normal * Vec3::new(10.0, 2.0, 0.5) + Vec3::new(-3.0, 7.0, 100.0),
+                    tex_coord: Vec2::new(i as f32 / 1024.0, 1.0 - normal.y),
+                    normal,
+                    tangent: if i % 2 == 0 {
+                        normal.any_orthonormal_vector().extend(-1.0)
+                    } else {
+                        Vec4::ZERO
+                    },
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let bounds = QuantizationBounds::from_vertices(&vertices);
+        // Half a quantization step, with some slack for f32 rounding in encode and decode.
+        let max_position_error = bounds.max_position_error() * 1.01;
+
+        for (vertex, quantized) in vertices.iter().zip(bounds.encode_vertices(&vertices)) {
+            let decoded = bounds.decode(&quantized);
+
+            assert!(
+                (decoded.position - vertex.position)
+                    .abs()
+                    .cmple(max_position_error)
+                    .all(),
+                "{} decoded to {}",
+                vertex.position,
+                decoded.position
+            );
+            // Half floats keep 11 significant bits.
+            assert!((decoded.tex_coord - vertex.tex_coord)
+                .abs()
+                .cmple(vertex.tex_coord.abs() / 2048.0 + 1e-7)
+                .all());
+            assert!(decoded.normal.distance(vertex.normal) < MAX_DIRECTION_ERROR);
+
+            if vertex.tangent == Vec4::ZERO {
+                assert_eq!(decoded.tangent, Vec4::ZERO);
+            } else {
+                assert_eq!(decoded.tangent.w, vertex.tangent.w);
+                assert!(
+                    decoded
+                        .tangent
+                        .truncate()
+                        .distance(vertex.tangent.truncate())
+                        < MAX_DIRECTION_ERROR
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flat_mesh_bounds() {
+        let vertices = [
+            Vertex::new(Vec3::new(0.0, 1.0, 0.0), Vec2::ZERO, Vec3::Y),
+            Vertex::new(Vec3::new(2.0, 1.0, 0.0), Vec2::ZERO, Vec3::Y),
+        ];
+        let bounds = QuantizationBounds::from_vertices(&vertices);
+
+        for vertex in vertices {
+            let decoded = bounds.decode(&bounds.encode(&vertex));
+            assert!(decoded.position.abs_diff_eq(vertex.position, 1e-4));
+        }
+    }
+}
@@ -58,12 +58,18 @@ pub fn compile(
     path: &str,
     entry_point: &str,
     kind: ShaderKind,
+    defines: &[(&str, String)],
 ) -> (
     Retained<ProtocolObject<dyn MTLLibrary>>,
     Retained<ProtocolObject<dyn MTLFunction>>,
 ) {
     let data = fs::read_to_string(path).unwrap();
-    let dxil_code = compile_hlsl(path, &data, entry_point, kind.into(), &["-Zi"], &[]).unwrap();
+    let defines = defines
+        .iter()
+        .map(|(name, value)| (*name, Some(value.as_str())))
+        .collect::<Vec<_>>();
+    let dxil_code =
+        compile_hlsl(path, &data, entry_point, kind.into(), &["-Zi"], &defines).unwrap();
 
     unsafe {
         let entry_point_cstr = CString::new(entry_point).unwrap();
@@ -146,6 +152,7 @@ pub fn compile(
 
 #[cfg(test)]
 mod tests {
+    use metal_3_example::{mesh::MeshletBuildConfig, quantization::VertexFormat};
     use objc2_metal::MTLCreateSystemDefaultDevice;
 
     use crate::shader_compiler::{compile, ShaderKind};
@@ -153,18 +160,31 @@ mod tests {
     #[test]
     fn compile_shader() {
         let device = MTLCreateSystemDefaultDevice().unwrap();
+        let defines = MeshletBuildConfig::default().shader_defines();
 
         let (_library, _mesh) = compile(
             &device,
             "shaders/geometry.hlsl",
             "geometry_mesh",
             ShaderKind::Mesh,
+            &defines,
         );
         let (_library, _frag) = compile(
             &device,
             "shaders/geometry.hlsl",
             "geometry_pixel",
             ShaderKind::Fragment,
+            &defines,
+        );
+
+        let mut skinning_defines = defines.clone();
+        skinning_defines.extend(VertexFormat::Full.shader_defines(true).unwrap());
+        let (_library, _skinned_mesh) = compile(
+            &device,
+            "shaders/geometry.hlsl",
+            "geometry_mesh",
+            ShaderKind::Mesh,
+            &skinning_defines,
         );
     }
 }
@@ -0,0 +1,358 @@
+//! CPU reimplementation of `geometry_mesh` in `shaders/geometry.hlsl`, so the way the shader walks
+//! `meshlet_data` can be tested without a GPU. Keep both in sync.
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::{
+    index_packing::{unpack_triangle_10, IndexFormat},
+    mesh::{Mesh, Meshlet},
+};
+
+/// `numthreads` of `geometry_mesh`, which is also the step of its vertex and triangle loops. Passed
+/// to the shader as a define by [`MeshletBuildConfig::shader_defines`].
+///
+/// [`MeshletBuildConfig::shader_defines`]: crate::mesh::MeshletBuildConfig::shader_defines
+pub const THREAD_GROUP_SIZE: u32 = 32;
+
+/// What a single `geometry_mesh` thread group writes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MeshShaderOutput {
+    pub meshlet_index: u32,
+    /// Index into the vertex buffer of every output vertex.
+    pub vertices: Vec<u32>,
+    /// Triangles indexing `vertices`.
+    pub triangles: Vec<[u32; 3]>,
+}
+
+/// `meshlet_data[offset + index]`, failing where the shader would read out of bounds.
+fn load(meshlet_data: &[u32], offset: u32, index: u32) -> Result<u32> {
+    offset
+        .checked_add(index)
+        .and_then(|data_index| meshlet_data.get(data_index as usize))
+        .copied()
+        .with_context(|| {
+            format!(
+                "meshlet_data[{offset} + {index}] is out of bounds for {} values",
+                meshlet_data.len()
+            )
+        })
+}
+
+/// Same bit logic as `get_index` in the shader.
+pub fn get_index(meshlet_data: &[u32], index_offset: u32, index: u32) -> Result<u32> {
+    let byte_offset = (index & 3) << 3;
+    Ok((load(meshlet_data, index_offset, index >> 2)? & (0xFF << byte_offset)) >> byte_offset)
+}
+
+/// Same bit logic as `get_strip_code` in the shader.
+fn get_strip_code(meshlet_data: &[u32], code_offset: u32, triangle: u32) -> Result<u32> {
+    Ok((load(meshlet_data, code_offset, triangle >> 4)? >> ((triangle & 15) << 1)) & 3)
+}
+
+/// Runs thread group `group_id` of a `geometry_mesh` dispatch with the given `meshlet_offset`.
+/// Fails if an output slot is written more than once or not at all, which on the GPU would be
+/// wasted work or garbage output, or if `meshlet_data` is read out of bounds.
+pub fn geometry_mesh(
+    meshlets: &[Meshlet],
+    meshlet_data: &[u32],
+    index_format: IndexFormat,
+    meshlet_offset: u32,
+    group_id: u32,
+) -> Result<MeshShaderOutput> {
+    let meshlet_index = meshlet_offset
+        .checked_add(group_id)
+        .context("Meshlet index overflows")?;
+    let meshlet = meshlets.get(meshlet_index as usize).with_context(|| {
+        format!(
+            "Meshlet {meshlet_index} is out of bounds for {} meshlets",
+            meshlets.len()
+        )
+    })?;
+
+    let data_offset = meshlet
+        .data_offset
+        .checked_add(meshlet.vertex_count)
+        .filter(|data_offset| *data_offset as usize <= meshlet_data.len())
+        .with_context(|| format!("Vertices of meshlet {meshlet_index} are out of bounds"))?;
+    // Every triangle takes at least a byte, which bounds the outputs allocated below.
+    ensure!(
+        meshlet.triangle_count as usize <= (meshlet_data.len() - data_offset as usize) * 4,
+        "Triangles of meshlet {meshlet_index} are out of bounds"
+    );
+
+    // SetMeshOutputCounts
+    let mut output_vertices = vec![None; meshlet.vertex_count as usize];
+    let mut output_triangles = vec![None; meshlet.triangle_count as usize];
+
+    for thread_id in 0..THREAD_GROUP_SIZE {
+        let mut i = thread_id;
+        while i < meshlet.vertex_count {
+            let vertex_index = load(meshlet_data, meshlet.data_offset, i)?;
+            write_output(&mut output_vertices, i, vertex_index, "vertex")?;
+
+            i += THREAD_GROUP_SIZE;
+        }
+
+        if index_format == IndexFormat::Strip {
+            // Every triangle depends on the previous one, so a single thread walks the strip.
+            if thread_id == 0 {
+                let byte_offset = data_offset + meshlet.triangle_count.div_ceil(16);
+                let mut byte_index = 0;
+                let mut previous = [0, 0, 0];
+
+                for i in 0..meshlet.triangle_count {
+                    let code = get_strip_code(meshlet_data, data_offset, i)?;
+
+                    let triangle = if code == 0 {
+                        let triangle = [
+                            get_index(meshlet_data, byte_offset, byte_index)?,
+                            get_index(meshlet_data, byte_offset, byte_index + 1)?,
+                            get_index(meshlet_data, byte_offset, byte_index + 2)?,
+                        ];
+                        byte_index += 3;
+                        triangle
+                    } else {
+                        let new_index = get_index(meshlet_data, byte_offset, byte_index)?;
+                        byte_index += 1;
+
+                        if code == 1 {
+                            [previous[1], previous[0], new_index]
+                        } else if code == 2 {
+                            [previous[2], previous[1], new_index]
+                        } else {
+                            [previous[0], previous[2], new_index]
+                        }
+                    };
+
+                    write_output(&mut output_triangles, i, triangle, "triangle")?;
+                    previous = triangle;
+                }
+            }
+            continue;
+        }
+
+        let mut i = thread_id;
+        while i < meshlet.triangle_count {
+            let triangle = match index_format {
+                IndexFormat::Triangle10 => unpack_triangle_10(load(meshlet_data, data_offset, i)?),
+                _ => {
+                    let index_offset = i * 3;
+                    [
+                        get_index(meshlet_data, data_offset, index_offset)?,
+                        get_index(meshlet_data, data_offset, index_offset + 1)?,
+                        get_index(meshlet_data, data_offset, index_offset + 2)?,
+                    ]
+                }
+            };
+            write_output(&mut output_triangles, i, triangle, "triangle")?;
+
+            i += THREAD_GROUP_SIZE;
+        }
+    }
+
+    Ok(MeshShaderOutput {
+        meshlet_index,
+        vertices: unwrap_outputs(output_vertices, meshlet_index, "vertex")?,
+        triangles: unwrap_outputs(output_triangles, meshlet_index, "triangle")?,
+    })
+}
+
+fn write_output<T>(outputs: &mut [Option<T>], index: u32, value: T, kind: &str) -> Result<()> {
+    let output = &mut outputs[index as usize];
+    ensure!(
+        output.is_none(),
+        "Output {kind} {index} is written more than once"
+    );
+    *output = Some(value);
+
+    Ok(())
+}
+
+fn unwrap_outputs<T>(outputs: Vec<Option<T>>, meshlet_index: u32, kind: &str) -> Result<Vec<T>> {
+    outputs
+        .into_iter()
+        .enumerate()
+        .map(|(index, output)| {
+            match output {
+                Some(output) => Ok(output),
+                None => bail!("Output {kind} {index} of meshlet {meshlet_index} is never written"),
+            }
+        })
+        .collect()
+}
+
+impl Mesh {
+    /// Dispatches [`geometry_mesh`] for every submesh of `lod` like the renderer does and returns
+    /// the rasterized triangles as indices into the vertex buffer.
+    pub fn emulate_draw(&self, lod: usize) -> Result<Vec<[u32; 3]>> {
+        let mut triangles = Vec::new();
+
+        for submesh in self.lod_submeshes(lod) {
+            for group_id in 0..submesh.meshlet_count {
+                let output = geometry_mesh(
+                    &self.meshlets,
+                    &self.meshlet_data,
+                    self.index_format,
+                    submesh.meshlet_offset,
+                    group_id,
+                )?;
+
+                for triangle in &output.triangles {
+                    let mut vertices = [0; 3];
+                    for (vertex, index) in vertices.iter_mut().zip(triangle) {
+                        *vertex = *output.vertices.get(*index as usize).with_context(|| {
+                            format!(
+                                "Triangle of meshlet {} indexes vertex {index} of {}",
+                                output.meshlet_index,
+                                output.vertices.len()
+                            )
+                        })?;
+                    }
+                    triangles.push(vertices);
+                }
+            }
+        }
+
+        Ok(triangles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::{
+        index_packing::IndexFormat,
+        mesh::{ImportOptions, Mesh, Meshlet, MeshletBuildConfig},
+        shader_emulation::{geometry_mesh, get_index},
+    };
+
+    #[test]
+    fn get_index_matches_packing() {
+        let meshlet_data = [0x04_03_02_01, 0x08_07_06_05];
+
+        let indices: Vec<u32> = (0..8)
+            .map(|index| get_index(&meshlet_data, 0, index).unwrap())
+            .collect();
+        assert_eq!(indices, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(get_index(&meshlet_data, 1, 2).unwrap(), 7);
+
+        assert!(get_index(&meshlet_data, 1, 4).is_err());
+        assert!(get_index(&meshlet_data, u32::MAX, 4).is_err());
+    }
+
+    #[test]
+    fn out_of_bounds_meshlets_are_rejected() {
+        let meshlet_data = [0, 0x00_02_01_00];
+        let meshlet = Meshlet {
+            data_offset: 0,
+            vertex_count: 1,
+            triangle_count: 1,
+            ..Default::default()
+        };
+        let output = geometry_mesh(&[meshlet], &meshlet_data, IndexFormat::Bytes, 0, 0);
+        assert_eq!(output.unwrap().triangles, [[0, 1, 2]]);
+
+        for meshlet in [
+            Meshlet {
+                data_offset: u32::MAX,
+                ..meshlet
+            },
+            Meshlet {
+                vertex_count: u32::MAX,
+                ..meshlet
+            },
+            Meshlet {
+                triangle_count: u32::MAX,
+                ..meshlet
+            },
+            Meshlet {
+                triangle_count: 2,
+                ..meshlet
+            },
+        ] {
+            for index_format in IndexFormat::ALL {
+                assert!(
+                    geometry_mesh(&[meshlet], &meshlet_data, index_format, 0, 0).is_err(),
+                    "{meshlet:?} {index_format:?}"
+                );
+            }
+        }
+        assert!(geometry_mesh(&[meshlet], &meshlet_data, IndexFormat::Bytes, 0, 1).is_err());
+        assert!(geometry_mesh(&[meshlet], &meshlet_data, IndexFormat::Bytes, u32::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn meshlets_cover_obj_triangles_once() {
+        let size = 16;
+        let mut source = String::new();
+        for y in 0..=size {
+            for x in 0..=size {
+                source += &format!("v {x} {y} {}\n", (x * y) % 3);
+            }
+        }
+        let mut original = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let corner = |dx: usize, dy: usize| (y + dy) * (size + 1) + x + dx + 1;
+                for triangle in [
+                    [corner(0, 0), corner(1, 0), corner(1, 1)],
+                    [corner(0, 0), corner(1, 1), corner(0, 1)],
+                ] {
+                    source += &format!("f {} {} {}\n", triangle[0], triangle[1], triangle[2]);
+                    original.push(triangle.map(|index| index as u32 - 1));
+                }
+            }
+        }
+
+        let path = env::temp_dir().join("meshlets_cover_obj_triangles_once.obj");
+        fs::write(&path, source).unwrap();
+
+        // Vertices are reordered on import, so triangles are compared by their positions, rotated
+        // to start at the smallest one to keep the winding.
+        let canonical = |triangle: [[u32; 3]; 3]| {
+            let first = (0..3).min_by_key(|corner| triangle[*corner]).unwrap();
+            [0, 1, 2].map(|corner| triangle[(first + corner) % 3])
+        };
+        let positions_of_obj = |index: u32| {
+            let index = index as usize;
+            [
+                index % (size + 1),
+                index / (size + 1),
+                (index % (size + 1) * (index / (size + 1))) % 3,
+            ]
+            .map(|coordinate| (coordinate as f32).to_bits())
+        };
+
+        let mut expected: Vec<_> = original
+            .iter()
+            .map(|triangle| canonical(triangle.map(positions_of_obj)))
+            .collect();
+        expected.sort_unstable();
+
+        for index_format in IndexFormat::ALL {
+            let config = MeshletBuildConfig {
+                index_format,
+                ..Default::default()
+            };
+            let mesh = Mesh::new(&path, &ImportOptions::default(), &config).unwrap();
+
+            let mut drawn: Vec<_> = mesh
+                .emulate_draw(0)
+                .unwrap()
+                .iter()
+                .map(|triangle| {
+                    canonical(triangle.map(|index| {
+                        mesh.vertices[index as usize]
+                            .position
+                            .to_array()
+                            .map(f32::to_bits)
+                    }))
+                })
+                .collect();
+
+            drawn.sort_unstable();
+            assert_eq!(drawn, expected, "{index_format:?}");
+        }
+    }
+}
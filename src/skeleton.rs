@@ -0,0 +1,277 @@
+use anyhow::{bail, ensure, Result};
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use crate::mesh::Vertex;
+
+/// Translation, rotation and scale of a joint relative to its parent.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    #[inline]
+    pub fn to_mat4(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Joint {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    /// Local transform when no animation is applied.
+    pub rest: Transform,
+    /// Transforms from mesh space into the space of the joint in the bind pose.
+    pub inverse_bind_matrix: Mat4,
+}
+
+/// Joint hierarchy a skinned mesh is bound to. [`Vertex::joints`] index `joints`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    /// Transform of everything above the root joints.
+    pub root_transform: Mat4,
+    /// Joints ordered so that parents come before their children.
+    order: Vec<usize>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>, root_transform: Mat4) -> Result<Self> {
+        let mut order = Vec::with_capacity(joints.len());
+        let mut visited = vec![false; joints.len()];
+
+        for index in 0..joints.len() {
+            // Walk up to the first visited ancestor, then add the chain top down.
+            let mut chain = Vec::new();
+            let mut current = Some(index);
+            while let Some(joint) = current.filter(|joint| !visited[*joint]) {
+                if chain.contains(&joint) {
+                    bail!("Joint {joint} is its own ancestor");
+                }
+                chain.push(joint);
+
+                current = joints[joint].parent;
+                if let Some(parent) = current {
+                    ensure!(
+                        parent < joints.len(),
+                        "Joint {joint} has parent {parent} out of range for {} joints",
+                        joints.len()
+                    );
+                }
+            }
+
+            for joint in chain.into_iter().rev() {
+                visited[joint] = true;
+                order.push(joint);
+            }
+        }
+
+        Ok(Self {
+            joints,
+            root_transform,
+            order,
+        })
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    /// Transforms of every joint into mesh space for the local transforms in `pose`.
+    pub fn global_transforms(&self, pose: &[Transform]) -> Vec<Mat4> {
+        assert_eq!(pose.len(), self.joints.len());
+
+        let mut global_transforms = vec![Mat4::IDENTITY; self.joints.len()];
+        for &joint in &self.order {
+            let parent_transform = match self.joints[joint].parent {
+                Some(parent) => global_transforms[parent],
+                None => self.root_transform,
+            };
+            global_transforms[joint] = parent_transform * pose[joint].to_mat4();
+        }
+
+        global_transforms
+    }
+
+    /// Matrices moving bind pose vertices along with the joints in `pose`, what the shader skins
+    /// with.
+    pub fn joint_palette(&self, pose: &[Transform]) -> Vec<Mat4> {
+        self.global_transforms(pose)
+            .into_iter()
+            .zip(&self.joints)
+            .map(|(global_transform, joint)| global_transform * joint.inverse_bind_matrix)
+            .collect()
+    }
+}
+
+/// Normalizes up to four weights into unorm16 joint weights.
+pub fn encode_weights(weights: [f32; 4]) -> [u16; 4] {
+    let sum: f32 = weights.iter().sum();
+    if sum <= 0.0 {
+        return [0; 4];
+    }
+
+    weights.map(|weight| (weight / sum * u16::MAX as f32).round() as u16)
+}
+
+/// Blend of the palette matrices of the joints of `vertex`, same as `skin_matrix` in the shader.
+/// Returns `None` for vertices that are not skinned.
+pub fn skin_matrix(vertex: &Vertex, palette: &[Mat4]) -> Option<Mat4> {
+    let weights = vertex.weights.map(|weight| weight as f32);
+    let sum: f32 = weights.iter().sum();
+    if sum == 0.0 {
+        return None;
+    }
+
+    Some(
+        vertex
+            .joints
+            .iter()
+            .zip(weights)
+            .map(|(joint, weight)| palette[*joint as usize] * (weight / sum))
+            .fold(Mat4::ZERO, |matrix, weighted| matrix + weighted),
+    )
+}
+
+/// Linear blend skinning on the CPU, as a reference for the shader. Vertices that are not
+/// skinned are returned unchanged.
+pub fn skin_vertices(vertices: &[Vertex], palette: &[Mat4]) -> Vec<Vertex> {
+    vertices
+        .iter()
+        .map(|vertex| {
+            let Some(matrix) = skin_matrix(vertex, palette) else {
+                return *vertex;
+            };
+            let rotation = Mat3::from_mat4(matrix);
+
+            Vertex {
+                position: matrix.transform_point3(vertex.position),
+                normal: (rotation * vertex.normal).normalize_or_zero(),
+                tangent: (rotation * vertex.tangent.truncate())
+                    .normalize_or_zero()
+                    .extend(vertex.tangent.w),
+                ..*vertex
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use glam::{Mat4, Quat, Vec2, Vec3};
+
+    use crate::{
+        mesh::Vertex,
+        skeleton::{encode_weights, skin_vertices, Joint, Skeleton, Transform},
+    };
+
+    /// Two joints along Y, the second one unit above the first.
+    fn arm() -> Skeleton {
+        let upper = Transform::IDENTITY;
+        let lower = Transform {
+            translation: Vec3::Y,
+            ..Transform::IDENTITY
+        };
+
+        // Children may come before their parents.
+        Skeleton::new(
+            vec![
+                Joint {
+                    name: Some("lower".to_string()),
+                    parent: Some(1),
+                    rest: lower,
+                    inverse_bind_matrix: Mat4::from_translation(-Vec3::Y),
+                },
+                Joint {
+                    name: Some("upper".to_string()),
+                    parent: None,
+                    rest: upper,
+                    inverse_bind_matrix: Mat4::IDENTITY,
+                },
+            ],
+            Mat4::IDENTITY,
+        )
+        .unwrap()
+    }
+
+    fn skinned_vertex(position: Vec3, joints: [u16; 4], weights: [f32; 4]) -> Vertex {
+        Vertex {
+            joints,
+            weights: encode_weights(weights),
+            ..Vertex::new(position, Vec2::ZERO, Vec3::X)
+        }
+    }
+
+    #[test]
+    fn rest_pose_palette_is_identity() {
+        let skeleton = arm();
+
+        for matrix in skeleton.joint_palette(&skeleton.rest_pose()) {
+            assert!(matrix.abs_diff_eq(Mat4::IDENTITY, 1e-6));
+        }
+    }
+
+    #[test]
+    fn skinning_matches_reference_pose() {
+        let skeleton = arm();
+        let mut pose = skeleton.rest_pose();
+        // Bend the elbow by 90 degrees around Z, which swings the lower arm towards -X.
+        pose[0].rotation = Quat::from_rotation_z(FRAC_PI_2);
+
+        let vertices = [
+            skinned_vertex(Vec3::new(0.0, 0.5, 0.0), [1, 0, 0, 0], [1.0, 0.0, 0.0, 0.0]),
+            skinned_vertex(Vec3::new(0.0, 2.0, 0.0), [0, 0, 0, 0], [1.0, 0.0, 0.0, 0.0]),
+            skinned_vertex(Vec3::new(0.0, 2.0, 0.0), [0, 1, 0, 0], [1.0, 1.0, 0.0, 0.0]),
+            Vertex::new(Vec3::new(0.0, 2.0, 0.0), Vec2::ZERO, Vec3::X),
+        ];
+        let skinned = skin_vertices(&vertices, &skeleton.joint_palette(&pose));
+
+        // Moved by the upper joint only, which stays in place.
+        assert!(skinned[0]
+            .position
+            .abs_diff_eq(Vec3::new(0.0, 0.5, 0.0), 1e-5));
+        // One unit above the elbow ends up one unit to the left of it.
+        assert!(skinned[1]
+            .position
+            .abs_diff_eq(Vec3::new(-1.0, 1.0, 0.0), 1e-5));
+        assert!(skinned[1].normal.abs_diff_eq(Vec3::Y, 1e-5));
+        // Halfway between both.
+        assert!(skinned[2]
+            .position
+            .abs_diff_eq(Vec3::new(-0.5, 1.5, 0.0), 1e-4));
+        // Not skinned.
+        assert_eq!(skinned[3].position, vertices[3].position);
+    }
+
+    #[test]
+    fn invalid_hierarchies() {
+        let joint = |parent| {
+            Joint {
+                name: None,
+                parent,
+                rest: Transform::IDENTITY,
+                inverse_bind_matrix: Mat4::IDENTITY,
+            }
+        };
+
+        assert!(Skeleton::new(vec![joint(Some(1)), joint(Some(0))], Mat4::IDENTITY).is_err());
+        assert!(Skeleton::new(vec![joint(Some(2))], Mat4::IDENTITY).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use glam::{Vec3, Vec4};
+
+use crate::mesh::Vertex;
+
+/// Generates a tangent for every vertex of an unindexed triangle list following MikkTSpace:
+/// per triangle tangents are derived from the texture coordinate gradients, projected onto the
+/// tangent plane of each corner and accumulated, weighted by the corner angle, over all corners
+/// that share position, normal, texture coordinate and texture orientation. The `w` component
+/// holds the handedness, so the bitangent is `cross(normal, tangent.xyz) * tangent.w`.
+///
+/// Unlike the reference implementation, corners are not split further into separate fans when
+/// a vertex is shared by disconnected parts of the surface.
+pub fn generate_tangents(vertices: &[Vertex]) -> Vec<Vec4> {
+    let mut accumulated: HashMap<[u32; 9], Vec3> = HashMap::new();
+    let mut corners = Vec::with_capacity(vertices.len());
+
+    for triangle in vertices.chunks_exact(3) {
+        let d2 = triangle[1].position - triangle[0].position;
+        let d3 = triangle[2].position - triangle[0].position;
+        let t21 = triangle[1].tex_coord - triangle[0].tex_coord;
+        let t31 = triangle[2].tex_coord - triangle[0].tex_coord;
+
+        let signed_area = t21.x * t31.y - t21.y * t31.x;
+        let orientation = if signed_area > 0.0 { 1.0 } else { -1.0 };
+
+        // Gradient of u across the triangle, flipped along with the texture orientation.
+        let os = (d2 * t31.y - d3 * t21.y).normalize_or_zero() * orientation;
+
+        for i in 0..3 {
+            let vertex = &triangle[i];
+            let normal = vertex.normal.normalize_or_zero();
+            let project = |v: Vec3| (v - normal * normal.dot(v)).normalize_or_zero();
+
+            let next = project(triangle[(i + 1) % 3].position - vertex.position);
+            let previous = project(triangle[(i + 2) % 3].position - vertex.position);
+            let angle = next.dot(previous).clamp(-1.0, 1.0).acos();
+
+            let key = [
+                vertex.position.x.to_bits(),
+                vertex.position.y.to_bits(),
+                vertex.position.z.to_bits(),
+                vertex.normal.x.to_bits(),
+                vertex.normal.y.to_bits(),
+                vertex.normal.z.to_bits(),
+                vertex.tex_coord.x.to_bits(),
+                vertex.tex_coord.y.to_bits(),
+                (orientation as f32).to_bits(),
+            ];
+
+            *accumulated.entry(key).or_default() += project(os) * angle;
+            corners.push((key, normal, orientation));
+        }
+    }
+
+    corners
+        .into_iter()
+        .map(|(key, normal, orientation)| {
+            let tangent = accumulated[&key].normalize_or_zero();
+            let tangent = if tangent == Vec3::ZERO {
+                normal.any_orthonormal_vector()
+            } else {
+                tangent
+            };
+
+            tangent.extend(orientation)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use glam::{Vec2, Vec3, Vec4};
+
+    use crate::{mesh::Vertex, tangents::generate_tangents};
+
+    fn quad(mirrored: bool) -> Vec<Vertex> {
+        let corners = [
+            (Vec3::new(-1.0, -1.0, 0.0), Vec2::new(0.0, 0.0)),
+            (Vec3::new(1.0, -1.0, 0.0), Vec2::new(1.0, 0.0)),
+            (Vec3::new(1.0, 1.0, 0.0), Vec2::new(1.0, 1.0)),
+            (Vec3::new(-1.0, 1.0, 0.0), Vec2::new(0.0, 1.0)),
+        ];
+
+        [0, 1, 2, 0, 2, 3]
+            .into_iter()
+            .map(|i| {
+                let (position, tex_coord) = corners[i];
+                let tex_coord = if mirrored {
+                    Vec2::new(1.0 - tex_coord.x, tex_coord.y)
+                } else {
+                    tex_coord
+                };
+
+                Vertex::new(position, tex_coord, Vec3::Z)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quad_tangents() {
+        for tangent in generate_tangents(&quad(false)) {
+            assert!(tangent.abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, 1.0), 1e-6));
+        }
+    }
+
+    #[test]
+    fn mirrored_quad_tangents() {
+        for tangent in generate_tangents(&quad(true)) {
+            assert!(tangent.abs_diff_eq(Vec4::new(-1.0, 0.0, 0.0, -1.0), 1e-6));
+        }
+    }
+
+    #[test]
+    fn cylinder_tangents() {
+        const SEGMENTS: usize = 16;
+
+        let vertex = |segment: usize, height: f32| {
+            let u = segment as f32 / SEGMENTS as f32;
+            let (sin, cos) = (u * TAU).sin_cos();
+
+            Vertex::new(
+                Vec3::new(cos, height, sin),
+                Vec2::new(u, height),
+                Vec3::new(cos, 0.0, sin),
+            )
+        };
+
+        let mut vertices = Vec::new();
+        for segment in 0..SEGMENTS {
+            let a = vertex(segment, 0.0);
+            let b = vertex(segment + 1, 0.0);
+            let c = vertex(segment + 1, 1.0);
+            let d = vertex(segment, 1.0);
+
+            // Wound so the face normals point outwards like the vertex normals.
+            vertices.extend([a, c, b, a, d, c]);
+        }
+
+        for (vertex, tangent) in vertices.iter().zip(generate_tangents(&vertices)) {
+            let u = vertex.tex_coord.x * TAU;
+            let expected_tangent = Vec3::new(-u.sin(), 0.0, u.cos());
+            let expected_sign = vertex.normal.cross(expected_tangent).dot(Vec3::Y).signum();
+
+            assert!(tangent.truncate().abs_diff_eq(expected_tangent, 1e-5));
+            assert_eq!(tangent.w, expected_sign);
+        }
+    }
+}